@@ -1,9 +1,102 @@
-use actix_web::{App, HttpServer};
+use actix_web::{get, web, App, HttpResponse, HttpServer};
+use autovet_core::{
+	config::Config,
+	package::{Package, PackageChannel},
+	store::{AsyncStore, PackageQuery, WorkerQuery},
+	worker::Worker,
+};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct CommandLine {
+	/// The configuration file to use
+	#[clap(long)]
+	config: Option<PathBuf>,
+
+	/// The store URL
+	#[clap(long)]
+	store: Option<String>,
+
+	/// The address to listen on
+	#[clap(long)]
+	bind: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Fault {
+	fault_message: String,
+}
+
+fn fault(message: String) -> HttpResponse {
+	HttpResponse::InternalServerError().json(Fault {
+		fault_message: message,
+	})
+}
+
+#[derive(Deserialize)]
+struct PackagesParams {
+	channel: Option<PackageChannel>,
+	name: Option<String>,
+}
+
+#[get("/packages")]
+async fn packages(
+	store: web::Data<dyn AsyncStore>,
+	params: web::Query<PackagesParams>,
+) -> actix_web::Result<HttpResponse> {
+	let query = PackageQuery {
+		channel: params.channel,
+		name: params.name.clone(),
+		..Default::default()
+	};
+
+	Ok(match Package::find_async(store.as_ref(), &query).await {
+		Ok(packages) => HttpResponse::Ok().json(packages),
+		Err(e) => fault(e.to_string()),
+	})
+}
+
+#[get("/workers")]
+async fn workers(store: web::Data<dyn AsyncStore>) -> actix_web::Result<HttpResponse> {
+	Ok(
+		match Worker::find_async(store.as_ref(), &WorkerQuery::default()).await {
+			Ok(workers) => HttpResponse::Ok().json(workers),
+			Err(e) => fault(e.to_string()),
+		},
+	)
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-	HttpServer::new(|| App::new())
-		.bind(("127.0.0.1", 8080))?
-		.run()
-		.await
+	let command_line = CommandLine::parse();
+	env_logger::init_from_env(env_logger::Env::new());
+
+	let mut config = Config::load(command_line.config.as_deref())
+		.map_err(|e| std::io::Error::other(e.to_string()))?;
+	if let Some(store) = command_line.store {
+		config.store.url = store;
+	}
+	if let Some(bind) = command_line.bind {
+		config.api.bind = bind;
+	}
+	config
+		.validate()
+		.map_err(|e| std::io::Error::other(e.to_string()))?;
+
+	let store: Arc<dyn AsyncStore> = autovet_core::store::open_async(&config.store)
+		.map_err(|e| std::io::Error::other(e.to_string()))?
+		.into();
+
+	HttpServer::new(move || {
+		App::new()
+			.app_data(web::Data::from(store.clone()))
+			.service(packages)
+			.service(workers)
+	})
+	.bind(config.api.bind)?
+	.run()
+	.await
 }
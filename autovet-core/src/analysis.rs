@@ -1,7 +1,10 @@
+use crate::error::Result;
+use crate::store::{AsyncStore, Store};
+use log::info;
 use serde::{Deserialize, Serialize};
 use std::default::Default;
 
-#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug, Clone, Copy)]
 pub enum FindingSeverity {
 	#[default]
 	Info,
@@ -9,21 +12,68 @@ pub enum FindingSeverity {
 	Critical,
 }
 
-#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug, Clone)]
 pub struct Finding {
 	pub severity: FindingSeverity,
 
 	pub message: String,
 }
 
-#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug)]
+/// What a file in a package is, as far as analysis is concerned.
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum FileKind {
+	/// An ELF program, including position independent executables
+	Executable,
+	SharedObject,
+	/// A file run by an interpreter, such as a shell script or install hook
+	Script,
+	#[default]
+	Data,
+	Directory,
+	Symlink,
+	/// A device node, FIFO or other special file, which is never unpacked
+	Special,
+}
+
+/// A file found in a package archive.
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug, Clone)]
+pub struct PackageFile {
+	pub path: String,
+
+	pub kind: FileKind,
+
+	pub mode: u32,
+
+	/// The owning user's name, or ID if the archive has no name
+	pub owner: String,
+
+	pub group: String,
+
+	pub size: u64,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub sha256sum: Option<String>,
+
+	/// The target of a symbolic or hard link
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub link_target: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
 pub struct Analysis {
-	pub _id: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub _id: Option<String>,
 
-	pub _rev: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub _rev: Option<String>,
 
 	pub package_id: String,
 
+	/// The ID of the earlier package version this analysis was compared
+	/// against, if any
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub baseline_id: Option<String>,
+
 	pub name: String,
 
 	pub progress: u32,
@@ -33,6 +83,38 @@ pub struct Analysis {
 	pub end_time: u64,
 
 	pub findings: Vec<Finding>,
+
+	/// Every file in the analyzed package
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub files: Vec<PackageFile>,
+}
+
+impl Analysis {
+	pub fn find(store: &dyn Store, package_id: &str) -> Result<Vec<Analysis>> {
+		store.find_analyses(package_id)
+	}
+
+	pub fn update(&mut self, store: &dyn Store) -> Result<()> {
+		if self._rev.is_none() {
+			info!("Creating new analysis: {}", self.name);
+			store.create_analysis(self)
+		} else {
+			store.update_analysis(self)
+		}
+	}
+
+	pub async fn find_async(store: &dyn AsyncStore, package_id: &str) -> Result<Vec<Analysis>> {
+		store.find_analyses(package_id).await
+	}
+
+	pub async fn update_async(&mut self, store: &dyn AsyncStore) -> Result<()> {
+		if self._rev.is_none() {
+			info!("Creating new analysis: {}", self.name);
+			store.create_analysis(self).await
+		} else {
+			store.update_analysis(self).await
+		}
+	}
 }
 
 pub trait StaticAnalyzer {
@@ -0,0 +1,894 @@
+//! Configuration shared by the autovet poller, worker and API server.
+//!
+//! Configuration is layered: built-in defaults are overridden by a TOML file,
+//! which is overridden by `AUTOVET_*` environment variables, which are finally
+//! overridden by command line arguments. For example:
+//!
+//! ```toml
+//! [store]
+//! url = "http://db.auto.vet:5984"
+//! username = "autovet"
+//! password_file = "/run/secrets/autovet-store"
+//!
+//! [pacman]
+//! mirror = "http://mirror.fossable.org/archlinux"
+//! repos = ["core", "extra", "core-testing"]
+//! architectures = ["x86_64", "aarch64"]
+//! compare_mirrors = ["https://geo.mirror.pkgbuild.com"]
+//! max_database_age = 604800
+//!
+//! [pacman.mirrors]
+//! aarch64 = "http://mirror.archlinuxarm.org/$arch/$repo"
+//!
+//! [debian]
+//! suites = ["bookworm", "bookworm-updates"]
+//! components = ["main"]
+//!
+//! [pypi]
+//! projects = ["requests", "urllib3"]
+//!
+//! [npm]
+//! packages = ["left-pad", "@types/node"]
+//!
+//! [crates_io]
+//! crates = ["serde", "tokio"]
+//!
+//! [maven]
+//! artifacts = ["org.apache.logging.log4j:log4j-core"]
+//!
+//! [oci]
+//! images = ["docker.io/library/alpine:3.18", "oci:/srv/images/demo:latest"]
+//!
+//! [poll]
+//! interval = 3600
+//! jitter = 300
+//!
+//! [poll.intervals]
+//! pacman = 900
+//!
+//! [api]
+//! bind = "0.0.0.0:8080"
+//!
+//! [worker]
+//! poll_interval = 100
+//! lease_duration = 3600
+//! cache_dir = "/var/cache/autovet"
+//! max_instructions = 10000000
+//! analysis_timeout = 300
+//! ```
+//!
+//! Secrets are never compiled in; they must come from the environment
+//! (`AUTOVET_STORE_PASSWORD`) or from a file referenced by `password_file`.
+
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use std::{
+	collections::BTreeMap,
+	fmt,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+/// The configuration file read when no other file is requested.
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/autovet/config.toml";
+
+#[derive(Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	pub store: StoreConfig,
+
+	pub pacman: PacmanConfig,
+
+	pub debian: DebianConfig,
+
+	pub pypi: PyPiConfig,
+
+	pub npm: NpmConfig,
+
+	pub crates_io: CratesIoConfig,
+
+	pub maven: MavenConfig,
+
+	pub oci: OciConfig,
+
+	pub poll: PollConfig,
+
+	pub api: ApiConfig,
+
+	pub worker: WorkerConfig,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+	/// The store location (see [`crate::store::open`])
+	pub url: String,
+
+	pub username: Option<String>,
+
+	pub password: Option<String>,
+
+	/// A file containing the store password
+	pub password_file: Option<PathBuf>,
+}
+
+impl Default for StoreConfig {
+	fn default() -> Self {
+		StoreConfig {
+			url: String::from("http://db.auto.vet:5984"),
+			username: None,
+			password: None,
+			password_file: None,
+		}
+	}
+}
+
+// Avoid leaking the password into logs
+impl fmt::Debug for StoreConfig {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("StoreConfig")
+			.field("url", &self.url)
+			.field("username", &self.username)
+			.field("password", &self.password.as_ref().map(|_| "<redacted>"))
+			.field("password_file", &self.password_file)
+			.finish()
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PacmanConfig {
+	/// The Arch Linux mirror, either a base URL or a server line like those in
+	/// pacman's mirrorlist, with `$repo` and `$arch` placeholders
+	pub mirror: String,
+
+	/// Mirrors for specific architectures, overriding `mirror`
+	pub mirrors: BTreeMap<String, String>,
+
+	/// Other mirrors (in the same format as `mirror`) whose databases are
+	/// compared with the primary mirror's to detect tampering
+	pub compare_mirrors: Vec<String>,
+
+	/// The repositories to synchronize. pacman isn't polled at all if this is
+	/// empty.
+	pub repos: Vec<String>,
+
+	/// The architectures to synchronize each repository for. Packages built
+	/// for any architecture are downloaded from the first one.
+	pub architectures: Vec<String>,
+
+	/// The keyring that repository databases and packages must be signed
+	/// by (e.g. `/usr/share/pacman/keyrings/archlinux.gpg`). Signatures aren't
+	/// checked unless this is set.
+	///
+	/// Signatures made after a key expired or was revoked are rejected, as are
+	/// signatures by keys listed in the `-revoked` file next to the keyring
+	/// (e.g. `archlinux-revoked`). Every other key in the keyring is trusted:
+	/// unlike pacman, keys don't need to be certified by the keys listed in
+	/// `archlinux-trusted`.
+	pub keyring: Option<PathBuf>,
+
+	/// Seconds after which a database whose package versions haven't changed
+	/// is considered frozen, even if it's re-served with a fresh date.
+	/// Databases are never considered frozen unless this is set.
+	pub max_database_age: Option<u64>,
+}
+
+impl Default for PacmanConfig {
+	fn default() -> Self {
+		PacmanConfig {
+			mirror: String::from("http://mirror.fossable.org/archlinux"),
+			mirrors: BTreeMap::new(),
+			compare_mirrors: Vec::new(),
+			repos: ["core", "community", "extra", "multilib"]
+				.map(String::from)
+				.to_vec(),
+			architectures: vec![String::from("x86_64")],
+			keyring: None,
+			max_database_age: None,
+		}
+	}
+}
+
+/// Expand a mirror into the URL of a repository's directory for one
+/// architecture.
+fn expand_mirror(mirror: &str, repo: &str, arch: &str) -> String {
+	let mirror = if mirror.contains("$repo") {
+		mirror.to_string()
+	} else {
+		format!("{}/$repo/os/$arch", mirror.trim_end_matches('/'))
+	};
+	mirror.replace("$repo", repo).replace("$arch", arch)
+}
+
+impl PacmanConfig {
+	/// The URL of the directory holding the given repository's database and
+	/// packages for the given architecture.
+	pub fn server(&self, repo: &str, arch: &str) -> String {
+		expand_mirror(self.mirrors.get(arch).unwrap_or(&self.mirror), repo, arch)
+	}
+
+	/// The same directory on each of the mirrors used for comparison.
+	pub fn compare_servers(&self, repo: &str, arch: &str) -> Vec<String> {
+		self.compare_mirrors
+			.iter()
+			.map(|mirror| expand_mirror(mirror, repo, arch))
+			.collect()
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DebianConfig {
+	/// The base URL of the Debian mirror
+	pub mirror: String,
+
+	/// The suites to synchronize (none by default)
+	pub suites: Vec<String>,
+
+	pub components: Vec<String>,
+
+	pub architectures: Vec<String>,
+}
+
+impl Default for DebianConfig {
+	fn default() -> Self {
+		DebianConfig {
+			mirror: String::from("http://deb.debian.org/debian"),
+			suites: Vec::new(),
+			components: vec![String::from("main")],
+			architectures: vec![String::from("amd64")],
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PyPiConfig {
+	/// The base URL of the package index
+	pub index: String,
+
+	/// The projects to follow (none by default)
+	pub projects: Vec<String>,
+}
+
+impl Default for PyPiConfig {
+	fn default() -> Self {
+		PyPiConfig {
+			index: String::from("https://pypi.org"),
+			projects: Vec::new(),
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NpmConfig {
+	/// The base URL of the registry
+	pub registry: String,
+
+	/// The packages to follow (none by default)
+	pub packages: Vec<String>,
+}
+
+impl Default for NpmConfig {
+	fn default() -> Self {
+		NpmConfig {
+			registry: String::from("https://registry.npmjs.org"),
+			packages: Vec::new(),
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CratesIoConfig {
+	/// The base URL of the sparse index (or a local clone of the git index)
+	pub index: String,
+
+	/// The crates to follow (none by default)
+	pub crates: Vec<String>,
+}
+
+impl Default for CratesIoConfig {
+	fn default() -> Self {
+		CratesIoConfig {
+			index: String::from("https://index.crates.io"),
+			crates: Vec::new(),
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MavenConfig {
+	/// The base URL of the Maven repository
+	pub repository: String,
+
+	/// The `groupId:artifactId` coordinates to follow (none by default)
+	pub artifacts: Vec<String>,
+}
+
+impl Default for MavenConfig {
+	fn default() -> Self {
+		MavenConfig {
+			repository: String::from("https://repo1.maven.org/maven2"),
+			artifacts: Vec::new(),
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OciConfig {
+	/// The container images to follow (see [`crate::oci`] for the reference
+	/// syntax)
+	pub images: Vec<String>,
+}
+
+/// The channels that can be scheduled independently, named after their
+/// configuration sections.
+pub const POLL_CHANNELS: [&str; 7] = [
+	"pacman",
+	"debian",
+	"pypi",
+	"npm",
+	"crates_io",
+	"maven",
+	"oci",
+];
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PollConfig {
+	/// Seconds between synchronizations of a channel when running as a daemon
+	pub interval: u64,
+
+	/// Intervals for specific channels, overriding `interval`
+	pub intervals: BTreeMap<String, u64>,
+
+	/// The maximum number of seconds added to each interval at random, so
+	/// pollers started together don't hit the mirrors at the same time
+	pub jitter: u64,
+}
+
+impl Default for PollConfig {
+	fn default() -> Self {
+		PollConfig {
+			interval: 3600,
+			intervals: BTreeMap::new(),
+			jitter: 300,
+		}
+	}
+}
+
+impl PollConfig {
+	/// Seconds between synchronizations of the given channel.
+	pub fn interval(&self, channel: &str) -> u64 {
+		self.intervals
+			.get(channel)
+			.copied()
+			.unwrap_or(self.interval)
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+	/// The address the API server listens on
+	pub bind: String,
+}
+
+impl Default for ApiConfig {
+	fn default() -> Self {
+		ApiConfig {
+			bind: String::from("127.0.0.1:8080"),
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkerConfig {
+	/// Seconds to wait before polling again when there's nothing to process
+	pub poll_interval: u64,
+
+	/// Seconds a worker's claim on a package lasts before it may be reclaimed
+	pub lease_duration: u64,
+
+	/// Seconds between worker heartbeats
+	pub heartbeat_interval: u64,
+
+	/// Seconds without a heartbeat after which a worker is considered dead
+	pub heartbeat_timeout: u64,
+
+	/// Where downloaded package files are kept, named by their hash so that
+	/// every worker on the host can share them
+	pub cache_dir: PathBuf,
+
+	/// Instructions the static analyzer may emulate in each binary before
+	/// giving up on finding more syscalls
+	pub max_instructions: u64,
+
+	/// Execution paths the static analyzer may explore in each binary
+	pub max_paths: u64,
+
+	/// Seconds the static analyzer may spend on each binary
+	pub analysis_timeout: u64,
+}
+
+impl Default for WorkerConfig {
+	fn default() -> Self {
+		WorkerConfig {
+			poll_interval: 100,
+			lease_duration: 3600,
+			heartbeat_interval: 60,
+			heartbeat_timeout: 300,
+			cache_dir: PathBuf::from("/var/cache/autovet"),
+			max_instructions: 10_000_000,
+			max_paths: 100_000,
+			analysis_timeout: 300,
+		}
+	}
+}
+
+impl Config {
+	/// Load configuration from the given file (or the default location) and
+	/// the environment. Command line overrides should be applied afterwards,
+	/// followed by [`Config::validate`].
+	pub fn load(path: Option<&Path>) -> Result<Config> {
+		let path = match path {
+			Some(path) => Some(path.to_path_buf()),
+			None => match std::env::var_os("AUTOVET_CONFIG") {
+				Some(path) => Some(PathBuf::from(path)),
+				None => Some(PathBuf::from(DEFAULT_CONFIG_PATH)).filter(|path| path.exists()),
+			},
+		};
+
+		let mut config = match path {
+			Some(path) => Config::from_file(&path)?,
+			None => Config::default(),
+		};
+
+		config.apply_env(|key| std::env::var(key).ok())?;
+		Ok(config)
+	}
+
+	pub fn from_file(path: &Path) -> Result<Config> {
+		match std::fs::read_to_string(path) {
+			Ok(content) => Config::from_toml(&content),
+			Err(e) => Err(Error::Config(format!(
+				"Failed to read {}: {}",
+				path.display(),
+				e
+			))),
+		}
+	}
+
+	pub fn from_toml(content: &str) -> Result<Config> {
+		toml::from_str(content).map_err(|e| Error::Config(e.to_string()))
+	}
+
+	/// Override values with those found by the given environment lookup.
+	pub fn apply_env<F>(&mut self, var: F) -> Result<()>
+	where
+		F: Fn(&str) -> Option<String>,
+	{
+		if let Some(url) = var("AUTOVET_STORE_URL") {
+			self.store.url = url;
+		}
+		if let Some(username) = var("AUTOVET_STORE_USERNAME") {
+			self.store.username = Some(username);
+		}
+		if let Some(password) = var("AUTOVET_STORE_PASSWORD") {
+			self.store.password = Some(password);
+		}
+		if let Some(password_file) = var("AUTOVET_STORE_PASSWORD_FILE") {
+			self.store.password_file = Some(PathBuf::from(password_file));
+		}
+		if let Some(mirror) = var("AUTOVET_PACMAN_MIRROR") {
+			self.pacman.mirror = mirror;
+		}
+		if let Some(repos) = var("AUTOVET_PACMAN_REPOS") {
+			self.pacman.repos = split_list(&repos);
+		}
+		if let Some(mirrors) = var("AUTOVET_PACMAN_COMPARE_MIRRORS") {
+			self.pacman.compare_mirrors = split_list(&mirrors);
+		}
+		if let Some(architectures) = var("AUTOVET_PACMAN_ARCHITECTURES") {
+			self.pacman.architectures = split_list(&architectures);
+		}
+		if let Some(keyring) = var("AUTOVET_PACMAN_KEYRING") {
+			self.pacman.keyring = Some(PathBuf::from(keyring));
+		}
+		if let Some(value) = var("AUTOVET_PACMAN_MAX_DATABASE_AGE") {
+			self.pacman.max_database_age =
+				Some(parse_var("AUTOVET_PACMAN_MAX_DATABASE_AGE", &value)?);
+		}
+		if let Some(mirror) = var("AUTOVET_DEBIAN_MIRROR") {
+			self.debian.mirror = mirror;
+		}
+		if let Some(suites) = var("AUTOVET_DEBIAN_SUITES") {
+			self.debian.suites = split_list(&suites);
+		}
+		if let Some(components) = var("AUTOVET_DEBIAN_COMPONENTS") {
+			self.debian.components = split_list(&components);
+		}
+		if let Some(architectures) = var("AUTOVET_DEBIAN_ARCHITECTURES") {
+			self.debian.architectures = split_list(&architectures);
+		}
+		if let Some(index) = var("AUTOVET_PYPI_INDEX") {
+			self.pypi.index = index;
+		}
+		if let Some(projects) = var("AUTOVET_PYPI_PROJECTS") {
+			self.pypi.projects = split_list(&projects);
+		}
+		if let Some(registry) = var("AUTOVET_NPM_REGISTRY") {
+			self.npm.registry = registry;
+		}
+		if let Some(packages) = var("AUTOVET_NPM_PACKAGES") {
+			self.npm.packages = split_list(&packages);
+		}
+		if let Some(index) = var("AUTOVET_CRATES_IO_INDEX") {
+			self.crates_io.index = index;
+		}
+		if let Some(crates) = var("AUTOVET_CRATES_IO_CRATES") {
+			self.crates_io.crates = split_list(&crates);
+		}
+		if let Some(repository) = var("AUTOVET_MAVEN_REPOSITORY") {
+			self.maven.repository = repository;
+		}
+		if let Some(artifacts) = var("AUTOVET_MAVEN_ARTIFACTS") {
+			self.maven.artifacts = split_list(&artifacts);
+		}
+		if let Some(images) = var("AUTOVET_OCI_IMAGES") {
+			self.oci.images = split_list(&images);
+		}
+		if let Some(value) = var("AUTOVET_POLL_INTERVAL") {
+			self.poll.interval = parse_var("AUTOVET_POLL_INTERVAL", &value)?;
+		}
+		if let Some(value) = var("AUTOVET_POLL_JITTER") {
+			self.poll.jitter = parse_var("AUTOVET_POLL_JITTER", &value)?;
+		}
+		if let Some(bind) = var("AUTOVET_API_BIND") {
+			self.api.bind = bind;
+		}
+		if let Some(value) = var("AUTOVET_WORKER_POLL_INTERVAL") {
+			self.worker.poll_interval = parse_var("AUTOVET_WORKER_POLL_INTERVAL", &value)?;
+		}
+		if let Some(value) = var("AUTOVET_WORKER_LEASE_DURATION") {
+			self.worker.lease_duration = parse_var("AUTOVET_WORKER_LEASE_DURATION", &value)?;
+		}
+		if let Some(value) = var("AUTOVET_WORKER_HEARTBEAT_INTERVAL") {
+			self.worker.heartbeat_interval =
+				parse_var("AUTOVET_WORKER_HEARTBEAT_INTERVAL", &value)?;
+		}
+		if let Some(value) = var("AUTOVET_WORKER_HEARTBEAT_TIMEOUT") {
+			self.worker.heartbeat_timeout = parse_var("AUTOVET_WORKER_HEARTBEAT_TIMEOUT", &value)?;
+		}
+		if let Some(cache_dir) = var("AUTOVET_WORKER_CACHE_DIR") {
+			self.worker.cache_dir = PathBuf::from(cache_dir);
+		}
+		if let Some(value) = var("AUTOVET_WORKER_MAX_INSTRUCTIONS") {
+			self.worker.max_instructions = parse_var("AUTOVET_WORKER_MAX_INSTRUCTIONS", &value)?;
+		}
+		if let Some(value) = var("AUTOVET_WORKER_MAX_PATHS") {
+			self.worker.max_paths = parse_var("AUTOVET_WORKER_MAX_PATHS", &value)?;
+		}
+		if let Some(value) = var("AUTOVET_WORKER_ANALYSIS_TIMEOUT") {
+			self.worker.analysis_timeout = parse_var("AUTOVET_WORKER_ANALYSIS_TIMEOUT", &value)?;
+		}
+		Ok(())
+	}
+
+	/// Check that the configuration is usable and resolve the store password
+	/// from `password_file` if necessary.
+	pub fn validate(&mut self) -> Result<()> {
+		if let (None, Some(password_file)) = (&self.store.password, &self.store.password_file) {
+			match std::fs::read_to_string(password_file) {
+				Ok(password) => self.store.password = Some(password.trim_end().to_string()),
+				Err(e) => {
+					return Err(Error::Config(format!(
+						"Failed to read {}: {}",
+						password_file.display(),
+						e
+					)))
+				}
+			}
+		}
+
+		if self.store.url.is_empty() {
+			return Err(Error::Config(String::from("No store URL configured")));
+		}
+		if self.store.url.starts_with("http://") || self.store.url.starts_with("https://") {
+			if let Err(e) = reqwest::Url::parse(&self.store.url) {
+				return Err(Error::Config(format!(
+					"Invalid store URL {}: {}",
+					self.store.url, e
+				)));
+			}
+		}
+		if self.store.username.is_some() && self.store.password.is_none() {
+			return Err(Error::Config(String::from(
+				"A store username was configured without a password",
+			)));
+		}
+
+		for mirror in std::iter::once(&self.pacman.mirror)
+			.chain(self.pacman.mirrors.values())
+			.chain(&self.pacman.compare_mirrors)
+		{
+			if let Err(e) = reqwest::Url::parse(mirror) {
+				return Err(Error::Config(format!(
+					"Invalid pacman mirror {}: {}",
+					mirror, e
+				)));
+			}
+		}
+		if self.pacman.repos.iter().any(|repo| repo.is_empty()) {
+			return Err(Error::Config(format!(
+				"Invalid pacman repository list: {:?}",
+				self.pacman.repos
+			)));
+		}
+		if self.pacman.architectures.is_empty()
+			|| self.pacman.architectures.iter().any(|arch| arch.is_empty())
+		{
+			return Err(Error::Config(format!(
+				"Invalid pacman architecture list: {:?}",
+				self.pacman.architectures
+			)));
+		}
+		if let Some(keyring) = &self.pacman.keyring {
+			if !keyring.is_file() {
+				return Err(Error::Config(format!(
+					"Pacman keyring not found: {}",
+					keyring.display()
+				)));
+			}
+		}
+
+		if let Err(e) = reqwest::Url::parse(&self.debian.mirror) {
+			return Err(Error::Config(format!(
+				"Invalid Debian mirror {}: {}",
+				self.debian.mirror, e
+			)));
+		}
+		if !self.debian.suites.is_empty()
+			&& (self.debian.components.is_empty() || self.debian.architectures.is_empty())
+		{
+			return Err(Error::Config(String::from(
+				"Debian suites were configured without components or architectures",
+			)));
+		}
+
+		if let Err(e) = reqwest::Url::parse(&self.pypi.index) {
+			return Err(Error::Config(format!(
+				"Invalid PyPI index {}: {}",
+				self.pypi.index, e
+			)));
+		}
+
+		if let Err(e) = reqwest::Url::parse(&self.npm.registry) {
+			return Err(Error::Config(format!(
+				"Invalid npm registry {}: {}",
+				self.npm.registry, e
+			)));
+		}
+
+		if let Err(e) = reqwest::Url::parse(&self.crates_io.index) {
+			return Err(Error::Config(format!(
+				"Invalid crates.io index {}: {}",
+				self.crates_io.index, e
+			)));
+		}
+
+		if let Err(e) = reqwest::Url::parse(&self.maven.repository) {
+			return Err(Error::Config(format!(
+				"Invalid Maven repository {}: {}",
+				self.maven.repository, e
+			)));
+		}
+		if let Some(coordinates) = self
+			.maven
+			.artifacts
+			.iter()
+			.find(|coordinates| coordinates.split(':').count() != 2)
+		{
+			return Err(Error::Config(format!(
+				"Invalid Maven coordinates (expected groupId:artifactId): {}",
+				coordinates
+			)));
+		}
+
+		for image in &self.oci.images {
+			crate::oci::ImageReference::parse(image)?;
+		}
+
+		if self.poll.interval == 0 || self.poll.intervals.values().any(|&interval| interval == 0) {
+			return Err(Error::Config(String::from(
+				"Poll intervals must be positive",
+			)));
+		}
+		if let Some(channel) = self
+			.poll
+			.intervals
+			.keys()
+			.find(|channel| !POLL_CHANNELS.contains(&channel.as_str()))
+		{
+			return Err(Error::Config(format!(
+				"Unknown channel in poll intervals: {}",
+				channel
+			)));
+		}
+
+		if self.api.bind.parse::<SocketAddr>().is_err() {
+			return Err(Error::Config(format!(
+				"Invalid API bind address: {}",
+				self.api.bind
+			)));
+		}
+
+		if self.worker.poll_interval == 0 {
+			return Err(Error::Config(String::from(
+				"The worker poll interval must be positive",
+			)));
+		}
+		if self.worker.lease_duration == 0 {
+			return Err(Error::Config(String::from(
+				"The worker lease duration must be positive",
+			)));
+		}
+		if self.worker.heartbeat_interval == 0
+			|| self.worker.heartbeat_interval >= self.worker.heartbeat_timeout
+		{
+			return Err(Error::Config(String::from(
+				"The worker heartbeat interval must be positive and shorter than the heartbeat timeout",
+			)));
+		}
+		if self.worker.max_instructions == 0
+			|| self.worker.max_paths == 0
+			|| self.worker.analysis_timeout == 0
+		{
+			return Err(Error::Config(String::from(
+				"The worker analysis budgets must be positive",
+			)));
+		}
+
+		Ok(())
+	}
+}
+
+/// Parse a comma-separated environment variable. An empty variable is an
+/// empty list.
+fn split_list(value: &str) -> Vec<String> {
+	if value.trim().is_empty() {
+		return Vec::new();
+	}
+
+	value
+		.split(',')
+		.map(|item| item.trim().to_string())
+		.collect()
+}
+
+/// Parse a numeric environment variable.
+fn parse_var<T: FromStr>(key: &str, value: &str) -> Result<T> {
+	value
+		.parse()
+		.map_err(|_| Error::Config(format!("Invalid {}: {}", key, value)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashMap;
+
+	#[test]
+	fn test_layering() -> Result<()> {
+		let mut config = Config::from_toml(
+			r#"
+			[store]
+			url = "sqlite://autovet.db"
+
+			[pacman]
+			repos = ["core"]
+			"#,
+		)?;
+		assert_eq!(config.store.url, "sqlite://autovet.db");
+		assert_eq!(config.pacman.repos, vec!["core"]);
+		assert_eq!(config.pacman.mirror, PacmanConfig::default().mirror);
+
+		let env: HashMap<&str, &str> = HashMap::from([
+			("AUTOVET_STORE_URL", "http://localhost:5984"),
+			("AUTOVET_STORE_USERNAME", "autovet"),
+			("AUTOVET_STORE_PASSWORD", "secret"),
+			("AUTOVET_PACMAN_REPOS", "core, extra"),
+		]);
+		config.apply_env(|key| env.get(key).map(|value| value.to_string()))?;
+		assert_eq!(config.store.url, "http://localhost:5984");
+		assert_eq!(config.store.password.as_deref(), Some("secret"));
+		assert_eq!(config.pacman.repos, vec!["core", "extra"]);
+
+		config.validate()?;
+		Ok(())
+	}
+
+	#[test]
+	fn test_validate() {
+		let mut config = Config::default();
+		assert!(config.validate().is_ok());
+
+		config.store.username = Some(String::from("autovet"));
+		assert!(config.validate().is_err());
+
+		let mut config = Config::default();
+		config.api.bind = String::from("localhost");
+		assert!(config.validate().is_err());
+
+		assert!(Config::from_toml("[store]\nuri = \"typo\"").is_err());
+
+		let mut config = Config::default();
+		config.poll.intervals.insert(String::from("pacmn"), 60);
+		assert!(config.validate().is_err());
+
+		let mut config = Config::default();
+		config.worker.max_paths = 0;
+		assert!(config.validate().is_err());
+
+		// pacman can be disabled, but not with a blank repository
+		let mut config = Config::default();
+		config.pacman.repos = Vec::new();
+		assert!(config.validate().is_ok());
+		config.pacman.repos = split_list("core, ");
+		assert!(config.validate().is_err());
+		assert!(split_list("").is_empty());
+	}
+
+	#[test]
+	fn test_pacman_server() -> Result<()> {
+		let config = Config::from_toml(
+			r#"
+			[pacman]
+			mirror = "http://mirror.fossable.org/archlinux/"
+			architectures = ["x86_64", "aarch64"]
+
+			compare_mirrors = ["https://geo.mirror.pkgbuild.com"]
+
+			[pacman.mirrors]
+			aarch64 = "http://mirror.archlinuxarm.org/$arch/$repo"
+
+			[poll]
+			interval = 600
+
+			[poll.intervals]
+			pacman = 60
+			"#,
+		)?;
+		assert_eq!(
+			config.pacman.server("core-testing", "x86_64"),
+			"http://mirror.fossable.org/archlinux/core-testing/os/x86_64"
+		);
+		assert_eq!(
+			config.pacman.server("core", "aarch64"),
+			"http://mirror.archlinuxarm.org/aarch64/core"
+		);
+		assert_eq!(
+			config.pacman.compare_servers("core", "x86_64"),
+			vec!["https://geo.mirror.pkgbuild.com/core/os/x86_64"]
+		);
+		assert_eq!(config.poll.interval("pacman"), 60);
+		assert_eq!(config.poll.interval("debian"), 600);
+		Ok(())
+	}
+
+	#[test]
+	fn test_password_redacted() {
+		let config = StoreConfig {
+			password: Some(String::from("secret")),
+			..Default::default()
+		};
+		assert!(!format!("{:?}", config).contains("secret"));
+	}
+}
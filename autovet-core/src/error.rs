@@ -0,0 +1,149 @@
+use log::warn;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// Errors produced by autovet-core.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+	/// The store or remote server could not be reached
+	#[error("Transport error: {0}")]
+	Transport(#[from] reqwest::Error),
+
+	/// The document was modified by someone else since it was read
+	#[error("Document update conflict: {0}")]
+	Conflict(String),
+
+	/// An update was requested for a document that has no `_id` and `_rev`
+	/// yet, so it has to be created instead
+	#[error("Document was never created: {0}")]
+	NotCreated(String),
+
+	/// The store refused to write a document for a reason other than a
+	/// conflict
+	#[error("Document rejected: {0}")]
+	Rejected(String),
+
+	/// The server responded with an unexpected status
+	#[error("Unexpected status {status} from {url}")]
+	Status { status: StatusCode, url: String },
+
+	/// A response or stored document could not be deserialized
+	#[error("Deserialization error: {0}")]
+	Deserialize(#[from] serde_json::Error),
+
+	/// Input such as a pacman desc file could not be parsed
+	#[error("Parse error: {0}")]
+	Parse(String),
+
+	/// Downloaded content didn't match its published digest
+	#[error("Checksum mismatch for {name}: expected {expected}, got {actual}")]
+	Checksum {
+		name: String,
+		expected: String,
+		actual: String,
+	},
+
+	/// A signature was malformed, invalid or made by an unknown key
+	#[error("Signature error: {0}")]
+	Signature(String),
+
+	/// Some parts of an operation failed while the others went ahead. The
+	/// failures were logged as they happened; only the failed parts are listed
+	#[error("Incomplete, failed: {}", .0.join(", "))]
+	Incomplete(Vec<String>),
+
+	#[error("Database error: {0}")]
+	Database(#[from] rusqlite::Error),
+
+	#[error("Configuration error: {0}")]
+	Config(String),
+
+	#[error("IO error: {0}")]
+	Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+	/// Whether the error was caused by a concurrent modification.
+	pub fn is_conflict(&self) -> bool {
+		matches!(self, Error::Conflict(_))
+	}
+
+	/// Whether retrying the operation later might succeed.
+	pub fn is_transient(&self) -> bool {
+		match self {
+			Error::Transport(e) => {
+				e.is_timeout()
+					|| e.is_connect()
+					|| e.is_request()
+					|| e.status().is_some_and(|status| status.is_server_error())
+			}
+			Error::Status { status, .. } => {
+				status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+			}
+			Error::Database(rusqlite::Error::SqliteFailure(e, _)) => {
+				e.code == rusqlite::ErrorCode::DatabaseBusy
+					|| e.code == rusqlite::ErrorCode::DatabaseLocked
+			}
+			_ => false,
+		}
+	}
+}
+
+/// Run the given operation, retrying transient failures with exponential
+/// backoff. Other errors are returned immediately.
+pub fn retry<T, F>(attempts: u32, mut operation: F) -> Result<T>
+where
+	F: FnMut() -> Result<T>,
+{
+	let mut delay = Duration::from_secs(1);
+	let mut attempt = 1;
+
+	loop {
+		match operation() {
+			Err(e) if e.is_transient() && attempt < attempts => {
+				warn!(
+					"Attempt {} failed ({}), retrying in {:?}",
+					attempt, e, delay
+				);
+				std::thread::sleep(delay);
+				delay *= 2;
+				attempt += 1;
+			}
+			result => return result,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_classification() {
+		assert!(Error::Conflict(String::from("id")).is_conflict());
+		assert!(Error::Status {
+			status: StatusCode::SERVICE_UNAVAILABLE,
+			url: String::new(),
+		}
+		.is_transient());
+		assert!(!Error::Status {
+			status: StatusCode::UNAUTHORIZED,
+			url: String::new(),
+		}
+		.is_transient());
+		assert!(!Error::Parse(String::new()).is_transient());
+	}
+
+	#[test]
+	fn test_retry_stops_on_permanent_error() {
+		let mut calls = 0;
+		let result: Result<()> = retry(5, || {
+			calls += 1;
+			Err(Error::Conflict(String::from("id")))
+		});
+		assert!(result.unwrap_err().is_conflict());
+		assert_eq!(calls, 1);
+	}
+}
@@ -0,0 +1,386 @@
+//! Verifying detached OpenPGP signatures against a keyring of trusted keys.
+//!
+//! Keys are identified by the hex fingerprint of their primary key, even when
+//! a signature was made by one of its subkeys.
+//!
+//! A key only vouches for signatures made while it was valid: not after it
+//! expired, and not after it was revoked. Revocations for compromised keys (or
+//! without a reason) invalidate every signature the key ever made. The keyring
+//! file itself is trusted, so self-signatures in it aren't re-verified, and
+//! keys aren't required to be certified by anyone else.
+
+use crate::error::{Error, Result};
+use log::debug;
+use pgp::composed::{Deserializable, DetachedSignature, SignedPublicKey, SignedPublicSubKey};
+use pgp::packet::{RevocationCode, Signature, SignatureType};
+use pgp::types::KeyDetails;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+pub struct Keyring {
+	keys: Vec<SignedPublicKey>,
+	/// Fingerprints of keys that must not be trusted even though the keyring
+	/// contains them.
+	revoked: HashSet<String>,
+}
+
+/// Whether the given signature claims to be made by the given key.
+fn issued_by(signature: &Signature, key: &impl KeyDetails) -> bool {
+	signature
+		.issuer_fingerprint()
+		.into_iter()
+		.any(|fingerprint| *fingerprint == key.fingerprint())
+		|| signature
+			.issuer_key_id()
+			.into_iter()
+			.any(|key_id| *key_id == key.legacy_key_id())
+}
+
+/// Whether the given revocation applies to a signature made at the given time.
+/// Keys that were superseded or retired stay valid for what they signed before.
+fn revokes(revocation: &Signature, created: u32) -> bool {
+	match revocation.revocation_reason_code() {
+		Some(
+			RevocationCode::KeySuperseded
+			| RevocationCode::KeyRetired
+			| RevocationCode::CertUserIdInvalid,
+		) => revocation
+			.created()
+			.is_none_or(|revoked| revoked.as_secs() <= created),
+		_ => true,
+	}
+}
+
+/// Whether the key expired before the given time, according to the newest of
+/// its self-signatures.
+fn expired<'a>(
+	key: &impl KeyDetails,
+	signatures: impl Iterator<Item = &'a Signature>,
+	created: u32,
+) -> bool {
+	let expiration = signatures
+		.max_by_key(|signature| signature.created().map(|created| created.as_secs()))
+		.and_then(|signature| signature.key_expiration_time())
+		.map(|expiration| expiration.as_secs())
+		.unwrap_or_default();
+	expiration != 0 && created >= key.created_at().as_secs().saturating_add(expiration)
+}
+
+/// Why the key (or the given subkey of it) can't vouch for a signature made at
+/// the given time, if it can't. The reason follows the key's fingerprint in an
+/// error message.
+fn invalid(
+	key: &SignedPublicKey,
+	subkey: Option<&SignedPublicSubKey>,
+	created: u32,
+) -> Option<&'static str> {
+	let primary = &key.primary_key;
+	let self_signatures = key
+		.details
+		.direct_signatures
+		.iter()
+		.chain(key.details.users.iter().flat_map(|user| &user.signatures))
+		.filter(|signature| issued_by(signature, primary));
+	if key
+		.details
+		.revocation_signatures
+		.iter()
+		.any(|revocation| issued_by(revocation, primary) && revokes(revocation, created))
+	{
+		return Some("is revoked");
+	}
+	if expired(primary, self_signatures, created) {
+		return Some("had expired by then");
+	}
+
+	let subkey = subkey?;
+	let bindings = subkey
+		.signatures
+		.iter()
+		.filter(|signature| issued_by(signature, primary));
+	if bindings.clone().any(|signature| {
+		signature.typ() == Some(SignatureType::SubkeyRevocation) && revokes(signature, created)
+	}) {
+		return Some("has revoked the subkey that made it");
+	}
+	let bindings =
+		bindings.filter(|signature| signature.typ() == Some(SignatureType::SubkeyBinding));
+	if expired(&subkey.key, bindings, created) {
+		return Some("let the subkey that made it expire");
+	}
+	None
+}
+
+impl Keyring {
+	/// Load every key from an armored or binary keyring file, such as pacman's
+	/// `archlinux.gpg`. Keys listed in the `-revoked` file next to it (e.g.
+	/// `archlinux-revoked`) aren't trusted.
+	pub fn load(path: &Path) -> Result<Keyring> {
+		let mut keyring = Keyring::from_reader(File::open(path)?)
+			.map_err(|e| Error::Config(format!("Invalid keyring {}: {}", path.display(), e)))?;
+
+		if let Some(stem) = path.file_stem() {
+			let mut revoked = stem.to_os_string();
+			revoked.push("-revoked");
+			let revoked = path.with_file_name(revoked);
+			if revoked.is_file() {
+				debug!("Loading revoked keys from {}", revoked.display());
+				keyring.revoke(&fs::read_to_string(revoked)?);
+			}
+		}
+		Ok(keyring)
+	}
+
+	pub fn from_reader(reader: impl Read) -> Result<Keyring> {
+		let (keys, _) = SignedPublicKey::from_reader_many(BufReader::new(reader))
+			.map_err(|e| Error::Signature(e.to_string()))?;
+		let keys = keys
+			.collect::<std::result::Result<Vec<_>, _>>()
+			.map_err(|e| Error::Signature(e.to_string()))?;
+
+		if keys.is_empty() {
+			return Err(Error::Signature(String::from("No keys found")));
+		}
+		Ok(Keyring {
+			keys,
+			revoked: HashSet::new(),
+		})
+	}
+
+	/// Stop trusting the keys in the given list of fingerprints, one per line.
+	pub fn revoke(&mut self, fingerprints: &str) {
+		self.revoked.extend(
+			fingerprints
+				.lines()
+				.filter_map(|line| line.split_whitespace().next())
+				.map(|fingerprint| fingerprint.to_uppercase()),
+		);
+	}
+
+	/// Verify a detached signature (armored or binary) over the given data,
+	/// returning the fingerprint of the key that made it.
+	pub fn verify(&self, data: impl Read, signature: &[u8]) -> Result<String> {
+		let (signature, _) = DetachedSignature::from_reader_single(signature)
+			.map_err(|e| Error::Signature(format!("Malformed signature: {}", e)))?;
+		let signature = signature.signature;
+		let created = signature
+			.created()
+			.ok_or_else(|| Error::Signature(String::from("Signature has no creation time")))?
+			.as_secs();
+
+		for key in &self.keys {
+			let fingerprint = format!("{:X}", key.primary_key.fingerprint());
+
+			let subkey = if issued_by(&signature, &key.primary_key) {
+				None
+			} else if let Some(subkey) = key
+				.public_subkeys
+				.iter()
+				.find(|subkey| issued_by(&signature, &subkey.key))
+			{
+				Some(subkey)
+			} else {
+				continue;
+			};
+
+			if self.revoked.contains(&fingerprint) {
+				return Err(Error::Signature(format!(
+					"Signed by {}, which is listed as revoked",
+					fingerprint
+				)));
+			}
+			if let Some(reason) = invalid(key, subkey, created) {
+				return Err(Error::Signature(format!(
+					"Signed by {}, which {}",
+					fingerprint, reason
+				)));
+			}
+
+			let result = match subkey {
+				Some(subkey) => signature.verify(&subkey.key, data),
+				None => signature.verify(&key.primary_key, data),
+			};
+
+			return match result {
+				Ok(()) => Ok(fingerprint),
+				Err(e) => Err(Error::Signature(format!(
+					"Invalid signature by {}: {}",
+					fingerprint, e
+				))),
+			};
+		}
+
+		let issuers: Vec<String> = signature
+			.issuer_fingerprint()
+			.into_iter()
+			.map(|fingerprint| format!("{:X}", fingerprint))
+			.chain(signature.issuer_key_id().into_iter().map(hex::encode_upper))
+			.collect();
+		Err(Error::Signature(format!(
+			"Signed by a key that isn't in the keyring: {}",
+			issuers.join(", ")
+		)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_testing::{generate_key, public_keys, sign};
+	use pgp::composed::{KeyType, SecretKeyParamsBuilder, SignedSecretKey};
+	use pgp::packet::{SignatureConfig, Subpacket, SubpacketData};
+	use pgp::ser::Serialize;
+	use pgp::types::{Duration, Password, Timestamp};
+
+	/// Sign a direct key or revocation signature over the key, made at the
+	/// given time.
+	fn sign_key(
+		key: &SignedSecretKey,
+		typ: SignatureType,
+		created: u32,
+		data: SubpacketData,
+	) -> Signature {
+		let mut config =
+			SignatureConfig::from_key(rand::thread_rng(), &key.primary_key, typ).unwrap();
+		config.hashed_subpackets = [
+			SubpacketData::SignatureCreationTime(Timestamp::from_secs(created)),
+			SubpacketData::IssuerFingerprint(key.fingerprint()),
+			data,
+		]
+		.into_iter()
+		.map(|data| Subpacket::regular(data).unwrap())
+		.collect();
+		config
+			.sign_key(
+				&key.primary_key,
+				&Password::empty(),
+				key.primary_key.public_key(),
+			)
+			.unwrap()
+	}
+
+	/// A keyring holding the key with the given signatures added to it.
+	fn keyring(
+		key: &SignedSecretKey,
+		direct: Vec<Signature>,
+		revocations: Vec<Signature>,
+	) -> Keyring {
+		let mut public = SignedPublicKey::from(key.clone());
+		public.details.direct_signatures.extend(direct);
+		public.details.revocation_signatures.extend(revocations);
+		Keyring::from_reader(public.to_bytes().unwrap().as_slice()).unwrap()
+	}
+
+	#[test]
+	fn test_verify() -> Result<()> {
+		let trusted = generate_key("Trusted <trusted@example.com>");
+		let untrusted = generate_key("Mallory <mallory@example.com>");
+		let keyring = Keyring::from_reader(public_keys(&[&trusted]).as_slice())?;
+
+		let fingerprint = keyring.verify(&b"data"[..], &sign(&trusted, b"data"))?;
+		assert_eq!(fingerprint, format!("{:X}", trusted.fingerprint()));
+
+		// Tampered data
+		assert!(matches!(
+			keyring.verify(&b"other"[..], &sign(&trusted, b"data")),
+			Err(Error::Signature(_))
+		));
+
+		// Unknown key
+		assert!(matches!(
+			keyring.verify(&b"data"[..], &sign(&untrusted, b"data")),
+			Err(Error::Signature(_))
+		));
+
+		// Garbage
+		assert!(keyring.verify(&b"data"[..], b"not a signature").is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_verify_revoked() -> Result<()> {
+		let key = generate_key("Packager <packager@example.com>");
+		let now = Timestamp::now().as_secs();
+		let revocation = |code, created| {
+			sign_key(
+				&key,
+				SignatureType::KeyRevocation,
+				created,
+				SubpacketData::RevocationReason(code, "".into()),
+			)
+		};
+
+		// A compromised key is never trusted again
+		let keyring = self::keyring(
+			&key,
+			vec![],
+			vec![revocation(RevocationCode::KeyCompromised, now + 60)],
+		);
+		assert!(matches!(
+			keyring.verify(&b"data"[..], &sign(&key, b"data")),
+			Err(Error::Signature(message)) if message.contains("revoked")
+		));
+
+		// A retired key still vouches for what it signed before retiring
+		let keyring = self::keyring(
+			&key,
+			vec![],
+			vec![revocation(RevocationCode::KeyRetired, now + 60)],
+		);
+		keyring.verify(&b"data"[..], &sign(&key, b"data"))?;
+		let keyring = self::keyring(
+			&key,
+			vec![],
+			vec![revocation(RevocationCode::KeyRetired, now - 60)],
+		);
+		assert!(matches!(
+			keyring.verify(&b"data"[..], &sign(&key, b"data")),
+			Err(Error::Signature(message)) if message.contains("is revoked")
+		));
+
+		// Keys on the revoked list
+		let mut keyring = Keyring::from_reader(public_keys(&[&key]).as_slice())?;
+		keyring.revoke(&format!("{:x}\n", key.fingerprint()));
+		assert!(matches!(
+			keyring.verify(&b"data"[..], &sign(&key, b"data")),
+			Err(Error::Signature(message)) if message.contains("listed as revoked")
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn test_verify_expired() -> Result<()> {
+		let now = Timestamp::now().as_secs();
+		let key = SecretKeyParamsBuilder::default()
+			.key_type(KeyType::Ed25519Legacy)
+			.can_certify(true)
+			.can_sign(true)
+			.primary_user_id(String::from("Packager <packager@example.com>"))
+			.created_at(Timestamp::from_secs(now - 7200))
+			.build()
+			.unwrap()
+			.generate(rand::thread_rng())
+			.unwrap();
+		// Newer than the self-signature made with the key
+		let expiring = |seconds| {
+			sign_key(
+				&key,
+				SignatureType::Key,
+				now + 1,
+				SubpacketData::KeyExpirationTime(Duration::from_secs(seconds)),
+			)
+		};
+
+		let keyring = self::keyring(&key, vec![expiring(3600)], vec![]);
+		assert!(matches!(
+			keyring.verify(&b"data"[..], &sign(&key, b"data")),
+			Err(Error::Signature(message)) if message.contains("expired")
+		));
+
+		let keyring = self::keyring(&key, vec![expiring(3 * 3600)], vec![]);
+		keyring.verify(&b"data"[..], &sign(&key, b"data"))?;
+		Ok(())
+	}
+}
@@ -0,0 +1,256 @@
+//! Workers claim packages with time-limited leases.
+//!
+//! A claim records the worker on the package along with an expiry time. If the
+//! worker crashes, the lease eventually expires (or the worker stops sending
+//! heartbeats) and [`reclaim`] returns the package to the queue so another
+//! worker can pick it up.
+
+use crate::{
+	error::Result,
+	package::Package,
+	store::{PackageQuery, Store, WorkerQuery},
+	worker::Worker,
+};
+use log::{info, warn};
+use std::collections::HashSet;
+
+/// Claim the given package for a worker. Fails with a conflict if another
+/// worker modified the package first.
+pub fn claim(
+	store: &dyn Store,
+	package: &mut Package,
+	worker_id: &str,
+	duration: u64,
+) -> Result<()> {
+	package.worker = Some(worker_id.to_string());
+	package.lease_expires = Some(crate::timestamp() + duration);
+	package.update(store)
+}
+
+/// Extend a lease that the worker already holds.
+pub fn renew(store: &dyn Store, package: &mut Package, duration: u64) -> Result<()> {
+	package.lease_expires = Some(crate::timestamp() + duration);
+	package.update(store)
+}
+
+/// Give up the lease once processing is finished. The worker remains recorded
+/// as the one that processed the package.
+pub fn complete(store: &dyn Store, package: &mut Package) -> Result<()> {
+	package.lease_expires = None;
+	package.update(store)
+}
+
+/// Whether the given leased package should be returned to the queue.
+fn is_dropped(package: &Package, live_workers: &HashSet<String>, now: u64) -> bool {
+	match (&package.worker, package.lease_expires) {
+		(Some(worker), Some(expires)) => expires < now || !live_workers.contains(worker),
+		(None, Some(_)) => true,
+		(_, None) => false,
+	}
+}
+
+/// Return packages whose lease expired or whose worker stopped sending
+/// heartbeats to the queue, then forget the workers that stopped. Returns the
+/// number of packages reclaimed.
+pub fn reclaim(store: &dyn Store, heartbeat_timeout: u64) -> Result<usize> {
+	let now = crate::timestamp();
+	let cutoff = now.saturating_sub(heartbeat_timeout);
+
+	let live_workers: HashSet<String> = Worker::find(
+		store,
+		&WorkerQuery {
+			checked_in_since: Some(cutoff),
+			..Default::default()
+		},
+	)?
+	.into_iter()
+	.filter_map(|worker| worker._id)
+	.collect();
+
+	let leased = Package::find(
+		store,
+		&PackageQuery {
+			leased: true,
+			..Default::default()
+		},
+	)?;
+
+	let mut reclaimed = 0;
+	for mut package in leased {
+		if !is_dropped(&package, &live_workers, now) {
+			continue;
+		}
+
+		info!(
+			"Reclaiming package {} from worker {:?}",
+			package.name, package.worker
+		);
+		package.worker = None;
+		package.lease_expires = None;
+
+		match package.update(store) {
+			Ok(()) => reclaimed += 1,
+			// Someone else reclaimed or renewed it first
+			Err(e) if e.is_conflict() => {}
+			Err(e) => warn!("Failed to reclaim package {}: {}", package.name, e),
+		}
+	}
+
+	let dead_workers = Worker::find(
+		store,
+		&WorkerQuery {
+			checked_in_before: Some(cutoff),
+			..Default::default()
+		},
+	)?;
+	for worker in dead_workers {
+		match worker.delete(store) {
+			Ok(()) => info!("Removed dead worker {:?}", worker._id),
+			// It sent a heartbeat or was removed by someone else first
+			Err(e) if e.is_conflict() => {}
+			Err(e) => warn!("Failed to remove worker {:?}: {}", worker._id, e),
+		}
+	}
+
+	Ok(reclaimed)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{package::PackageChannel, store::sqlite::SqliteStore};
+
+	fn package(store: &dyn Store, name: &str) -> Result<Package> {
+		let mut package = Package {
+			channel: PackageChannel::Pacman,
+			name: name.to_string(),
+			version: String::from("1.0-1"),
+			..Default::default()
+		};
+		package.update(store)?;
+		Ok(package)
+	}
+
+	fn worker(store: &dyn Store, last_checkin: u64) -> Result<String> {
+		let mut worker = Worker {
+			hostname: String::from("localhost"),
+			last_checkin,
+			..Default::default()
+		};
+		worker.update(store)?;
+		Ok(worker._id.unwrap())
+	}
+
+	#[test]
+	fn test_claim_conflict() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+		let mut first = package(&store, "grep")?;
+		let mut second = Package::find(&store, &PackageQuery::default())?.remove(0);
+
+		claim(&store, &mut first, "first", 60)?;
+		assert!(claim(&store, &mut second, "second", 60)
+			.unwrap_err()
+			.is_conflict());
+		Ok(())
+	}
+
+	#[test]
+	fn test_renew() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+		let mut grep = package(&store, "grep")?;
+
+		claim(&store, &mut grep, "worker", 60)?;
+		grep.lease_expires = Some(1);
+		renew(&store, &mut grep, 60)?;
+		assert!(grep.lease_expires > Some(crate::timestamp()));
+
+		// The renewed revision is the one that gets released
+		complete(&store, &mut grep)?;
+		assert_eq!(reclaim(&store, 300)?, 0);
+		Ok(())
+	}
+
+	#[test]
+	fn test_reclaim() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+		let alive = worker(&store, crate::timestamp())?;
+		let dead = worker(&store, 0)?;
+
+		// Held by a live worker with a valid lease
+		claim(&store, &mut package(&store, "grep")?, &alive, 60)?;
+
+		// Held by a dead worker
+		claim(&store, &mut package(&store, "sed")?, &dead, 60)?;
+
+		// Held by a live worker, but the lease expired
+		let mut expired = package(&store, "awk")?;
+		claim(&store, &mut expired, &alive, 60)?;
+		expired.lease_expires = Some(1);
+		expired.update(&store)?;
+
+		// Processed and released
+		let mut done = package(&store, "tar")?;
+		claim(&store, &mut done, &dead, 60)?;
+		complete(&store, &mut done)?;
+
+		assert_eq!(reclaim(&store, 300)?, 2);
+
+		let mut queued: Vec<String> = Package::find(
+			&store,
+			&PackageQuery {
+				unassigned: true,
+				..Default::default()
+			},
+		)?
+		.into_iter()
+		.map(|package| package.name)
+		.collect();
+		queued.sort();
+		assert_eq!(queued, vec!["awk", "sed"]);
+
+		// Only the live worker is left
+		let workers: Vec<String> = Worker::find(&store, &WorkerQuery::default())?
+			.into_iter()
+			.filter_map(|worker| worker._id)
+			.collect();
+		assert_eq!(workers, vec![alive]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_reclaim_many_workers() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+		for _ in 0..30 {
+			worker(&store, 0)?;
+		}
+		let alive = worker(&store, crate::timestamp())?;
+		claim(&store, &mut package(&store, "grep")?, &alive, 60)?;
+
+		assert_eq!(reclaim(&store, 300)?, 0);
+		assert_eq!(Worker::find(&store, &WorkerQuery::default())?.len(), 1);
+		Ok(())
+	}
+
+	#[test]
+	fn test_heartbeat_after_lost_response() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+		let mut worker = Worker {
+			hostname: String::from("localhost"),
+			..Default::default()
+		};
+		worker.update(&store)?;
+
+		// The store accepted a heartbeat whose response never arrived
+		let mut sent = worker.clone();
+		sent.heartbeat(&store)?;
+
+		worker.heartbeat(&store)?;
+		assert_eq!(worker._rev.as_deref(), Some("3"));
+
+		// A worker that was removed as dead registers again
+		worker.delete(&store)?;
+		worker.heartbeat(&store)?;
+		assert_eq!(Worker::find(&store, &WorkerQuery::default())?, vec![worker]);
+		Ok(())
+	}
+}
@@ -1,8 +1,24 @@
 pub mod analysis;
+pub mod config;
 pub mod definition;
+pub mod error;
+pub mod keyring;
+pub mod lease;
+pub mod oci;
 pub mod package;
+pub mod repository;
+pub mod store;
+pub mod version;
 pub mod worker;
 
+/// The current time in seconds since the epoch.
+pub fn timestamp() -> u64 {
+	std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map(|duration| duration.as_secs())
+		.unwrap_or_default()
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Syscall {
 	pub name: String,
@@ -12,7 +28,11 @@ pub struct Syscall {
 	//pub result: String,
 }
 
-#[derive(strum_macros::FromRepr)]
+/// The x86_64 Linux syscalls, named as in the kernel.
+#[derive(
+	strum_macros::FromRepr, strum_macros::IntoStaticStr, Debug, Clone, Copy, PartialEq, Eq,
+)]
+#[strum(serialize_all = "snake_case")]
 #[repr(u64)]
 pub enum SyscallType {
 	Read = 0,
@@ -87,11 +107,11 @@ pub enum SyscallType {
 	Msgsnd = 69,
 	Msgrcv = 70,
 	Msgctl = 71,
-	Gcntl = 72,
-	Glock = 73,
-	Gsync = 74,
-	Gdatasync = 75,
-	Yruncate = 76,
+	Fcntl = 72,
+	Flock = 73,
+	Fsync = 74,
+	Fdatasync = 75,
+	Truncate = 76,
 	Ftruncate = 77,
 	Getdents = 78,
 	Getcwd = 79,
@@ -99,8 +119,8 @@ pub enum SyscallType {
 	Fchdir = 81,
 	Rename = 82,
 	Mkdir = 83,
-	Tmdir = 84,
-	Vreat = 85,
+	Rmdir = 84,
+	Creat = 85,
 	Link = 86,
 	Unlink = 87,
 	Symlink = 88,
@@ -276,7 +296,7 @@ pub enum SyscallType {
 	Mknodat = 259,
 	Fchownat = 260,
 	Futimesat = 261,
-	Fewfstatat = 262,
+	Newfstatat = 262,
 	Unlinkat = 263,
 	Renameat = 264,
 	Linkat = 265,
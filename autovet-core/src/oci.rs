@@ -0,0 +1,496 @@
+//! OCI container images.
+//!
+//! Images are read either from an on-disk [image layout] (`oci:/path/to/layout`
+//! with an optional `:tag`) or from a registry speaking the distribution API
+//! (`docker.io/library/alpine:3.18`, `localhost:5000/demo@sha256:...`).
+//!
+//! [image layout]: https://github.com/opencontainers/image-spec/blob/main/image-layout.md
+
+use crate::error::{Error, Result};
+use reqwest::{blocking::Client, header, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::BTreeMap,
+	io::{Read, Write},
+	path::PathBuf,
+	sync::Mutex,
+};
+
+/// The manifest formats we understand, in order of preference.
+const MANIFEST_TYPES: [&str; 4] = [
+	"application/vnd.oci.image.index.v1+json",
+	"application/vnd.oci.image.manifest.v1+json",
+	"application/vnd.docker.distribution.manifest.list.v2+json",
+	"application/vnd.docker.distribution.manifest.v2+json",
+];
+
+/// The platform selected from multi-platform images.
+const PLATFORM: (&str, &str) = ("linux", "amd64");
+
+/// The annotation holding a manifest's tag in an image layout's index.
+const REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+	pub media_type: String,
+
+	pub digest: String,
+
+	pub size: u64,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub platform: Option<Platform>,
+
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+	pub architecture: String,
+
+	pub os: String,
+}
+
+/// An image manifest or an index of manifests.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ManifestOrIndex {
+	#[serde(default)]
+	manifests: Vec<Descriptor>,
+
+	config: Option<Descriptor>,
+
+	#[serde(default)]
+	layers: Vec<Descriptor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+	/// The digest of the manifest itself, which identifies the image
+	pub digest: String,
+
+	pub config: Descriptor,
+
+	/// The filesystem layers, from the bottom up
+	pub layers: Vec<Descriptor>,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ImageConfig {
+	pub architecture: String,
+
+	pub os: String,
+
+	pub config: ContainerConfig,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct ContainerConfig {
+	pub entrypoint: Option<Vec<String>>,
+
+	pub cmd: Option<Vec<String>>,
+
+	pub env: Option<Vec<String>>,
+
+	pub working_dir: Option<String>,
+
+	pub user: Option<String>,
+}
+
+impl ContainerConfig {
+	/// The command the container runs by default.
+	pub fn command(&self) -> Vec<String> {
+		let mut command = self.entrypoint.clone().unwrap_or_default();
+		command.extend(self.cmd.clone().unwrap_or_default());
+		command
+	}
+}
+
+/// Where an image's manifests and blobs are read from.
+pub enum ImageSource {
+	Layout(PathBuf),
+
+	Registry {
+		/// The registry's base URL
+		base: String,
+
+		repository: String,
+
+		client: Client,
+
+		/// A bearer token obtained after the registry asked for one
+		token: Mutex<Option<String>>,
+	},
+}
+
+/// A parsed image reference.
+pub struct ImageReference {
+	pub source: ImageSource,
+
+	/// A tag or digest
+	pub reference: String,
+
+	/// The canonical name of the image, without the tag or digest
+	pub name: String,
+}
+
+impl ImageReference {
+	pub fn parse(reference: &str) -> Result<ImageReference> {
+		if let Some(path) = reference.strip_prefix("oci:") {
+			// The tag follows the last colon, if it isn't part of the path
+			let (path, tag) = match path.rsplit_once(':') {
+				Some((path, tag)) if !tag.contains('/') => (path, tag),
+				_ => (path, "latest"),
+			};
+			return Ok(ImageReference {
+				source: ImageSource::Layout(PathBuf::from(path)),
+				reference: tag.to_string(),
+				name: format!("oci:{}", path),
+			});
+		}
+
+		let (name, reference) = match reference.split_once('@') {
+			Some((name, digest)) => (name, digest.to_string()),
+			None => match reference.rsplit_once(':') {
+				Some((name, tag)) if !tag.contains('/') => (name, tag.to_string()),
+				_ => (reference, String::from("latest")),
+			},
+		};
+
+		// The first component is a registry if it looks like a host name
+		let (registry, repository) = match name.split_once('/') {
+			Some((registry, repository))
+				if registry.contains('.') || registry.contains(':') || registry == "localhost" =>
+			{
+				(registry, repository.to_string())
+			}
+			_ => ("docker.io", name.to_string()),
+		};
+		let repository = if registry == "docker.io" && !repository.contains('/') {
+			format!("library/{}", repository)
+		} else {
+			repository
+		};
+
+		let base = match registry {
+			"docker.io" => String::from("https://registry-1.docker.io"),
+			registry if registry.starts_with("localhost") || registry.starts_with("127.") => {
+				format!("http://{}", registry)
+			}
+			registry => format!("https://{}", registry),
+		};
+
+		if repository.is_empty() || reference.is_empty() {
+			return Err(Error::Parse(format!("Invalid image reference: {}", name)));
+		}
+
+		Ok(ImageReference {
+			name: format!("{}/{}", registry, repository),
+			source: ImageSource::Registry {
+				base,
+				repository,
+				client: Client::new(),
+				token: Mutex::new(None),
+			},
+			reference,
+		})
+	}
+}
+
+/// The hex part of a `sha256:` digest.
+fn sha256_hex(digest: &str) -> Result<&str> {
+	match digest.strip_prefix("sha256:") {
+		Some(hex) if hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) => Ok(hex),
+		_ => Err(Error::Parse(format!("Unsupported digest: {}", digest))),
+	}
+}
+
+/// Parse the parameters of a `WWW-Authenticate: Bearer ...` challenge. Values
+/// may be quoted strings, which can contain commas and escaped characters.
+fn parse_challenge(challenge: &str) -> Option<BTreeMap<String, String>> {
+	let (scheme, mut rest) = challenge.trim_start().split_once(' ')?;
+	if !scheme.eq_ignore_ascii_case("bearer") {
+		return None;
+	}
+
+	let mut parameters = BTreeMap::new();
+	loop {
+		rest = rest.trim_start_matches([' ', '\t', ',']);
+		if rest.is_empty() {
+			return Some(parameters);
+		}
+
+		let (key, value) = rest.split_once('=')?;
+		let value = value.trim_start();
+
+		let mut parsed = String::new();
+		if let Some(quoted) = value.strip_prefix('"') {
+			let mut chars = quoted.char_indices();
+			loop {
+				match chars.next()? {
+					(_, '\\') => parsed.push(chars.next()?.1),
+					(i, '"') => {
+						rest = &quoted[i + 1..];
+						break;
+					}
+					(_, c) => parsed.push(c),
+				}
+			}
+		} else {
+			let end = value.find(',').unwrap_or(value.len());
+			parsed.push_str(value[..end].trim_end());
+			rest = &value[end..];
+		}
+
+		parameters.insert(key.trim().to_string(), parsed);
+	}
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+	token: Option<String>,
+	access_token: Option<String>,
+}
+
+impl ImageSource {
+	/// Open a manifest (by tag or digest) or a blob (by digest).
+	fn open(&self, kind: &str, reference: &str, accept: &str) -> Result<Box<dyn Read>> {
+		match self {
+			ImageSource::Layout(path) => {
+				let hex = sha256_hex(reference)?;
+				Ok(Box::new(std::fs::File::open(
+					path.join("blobs/sha256").join(hex),
+				)?))
+			}
+			ImageSource::Registry {
+				base,
+				repository,
+				client,
+				token,
+			} => {
+				let url = format!("{}/v2/{}/{}/{}", base, repository, kind, reference);
+				let request = |bearer: &Option<String>| {
+					let request = client.get(&url).header(header::ACCEPT, accept);
+					match bearer {
+						Some(bearer) => request.bearer_auth(bearer),
+						None => request,
+					}
+				};
+
+				let current = token.lock().unwrap().clone();
+				let mut rs = request(&current).send()?;
+
+				if rs.status() == StatusCode::UNAUTHORIZED {
+					// Anonymous pulls still need a token from the registry's auth service
+					let challenge = rs
+						.headers()
+						.get(header::WWW_AUTHENTICATE)
+						.and_then(|value| value.to_str().ok())
+						.and_then(parse_challenge);
+					if let Some(challenge) = challenge {
+						let bearer = self.authenticate(client, &challenge)?;
+						rs = request(&Some(bearer.clone())).send()?;
+						*token.lock().unwrap() = Some(bearer);
+					}
+				}
+
+				if rs.status().is_success() {
+					Ok(Box::new(rs))
+				} else {
+					Err(Error::Status {
+						status: rs.status(),
+						url,
+					})
+				}
+			}
+		}
+	}
+
+	fn authenticate(
+		&self,
+		client: &Client,
+		challenge: &BTreeMap<String, String>,
+	) -> Result<String> {
+		let realm = match challenge.get("realm") {
+			Some(realm) => realm,
+			None => return Err(Error::Parse(String::from("No realm in auth challenge"))),
+		};
+		let query: Vec<(&String, &String)> = challenge
+			.iter()
+			.filter(|(key, _)| *key == "service" || *key == "scope")
+			.collect();
+
+		let rs: TokenResponse = client
+			.get(realm)
+			.query(&query)
+			.send()?
+			.error_for_status()?
+			.json()?;
+		match rs.token.or(rs.access_token) {
+			Some(token) => Ok(token),
+			None => Err(Error::Parse(String::from("No token in auth response"))),
+		}
+	}
+
+	fn read_manifest(&self, reference: &str) -> Result<Vec<u8>> {
+		let mut content = Vec::new();
+		self.open("manifests", reference, &MANIFEST_TYPES.join(", "))?
+			.read_to_end(&mut content)?;
+		Ok(content)
+	}
+
+	/// Resolve a tag (or digest) to the image manifest, selecting the linux/amd64
+	/// image from multi-platform indices.
+	pub fn manifest(&self, reference: &str) -> Result<Manifest> {
+		let mut reference = match self {
+			// Tags live in the layout's top level index
+			ImageSource::Layout(path) if !reference.starts_with("sha256:") => {
+				let index: ManifestOrIndex =
+					serde_json::from_slice(&std::fs::read(path.join("index.json"))?)?;
+				let tagged = index
+					.manifests
+					.iter()
+					.find(|m| m.annotations.get(REF_NAME).map(String::as_str) == Some(reference));
+				match (tagged, &index.manifests[..]) {
+					(Some(manifest), _) => manifest.digest.clone(),
+					// An untagged layout with a single image
+					(None, [manifest]) if reference == "latest" => manifest.digest.clone(),
+					_ => {
+						return Err(Error::Parse(format!(
+							"No image tagged {} in {}",
+							reference,
+							path.display()
+						)))
+					}
+				}
+			}
+			_ => reference.to_string(),
+		};
+
+		// Follow at most one level of index
+		for _ in 0..2 {
+			let content = self.read_manifest(&reference)?;
+			let digest = format!("sha256:{}", hex::encode(Sha256::digest(&content)));
+			if reference.starts_with("sha256:") && reference != digest {
+				return Err(Error::Checksum {
+					name: String::from("manifest"),
+					expected: reference,
+					actual: digest,
+				});
+			}
+
+			let manifest: ManifestOrIndex = serde_json::from_slice(&content)?;
+			if let Some(config) = manifest.config {
+				return Ok(Manifest {
+					digest,
+					config,
+					layers: manifest.layers,
+				});
+			}
+
+			reference = match manifest.manifests.iter().find(|m| {
+				m.platform
+					.as_ref()
+					.is_some_and(|p| (p.os.as_str(), p.architecture.as_str()) == PLATFORM)
+			}) {
+				Some(descriptor) => descriptor.digest.clone(),
+				None => {
+					return Err(Error::Parse(format!(
+						"No {}/{} image in index {}",
+						PLATFORM.0, PLATFORM.1, digest
+					)))
+				}
+			};
+		}
+
+		Err(Error::Parse(format!("Nested image index at {}", reference)))
+	}
+
+	/// Copy a blob to the given writer, verifying its digest.
+	pub fn download_blob<W: Write>(&self, digest: &str, mut writer: W) -> Result<u64> {
+		let expected = sha256_hex(digest)?;
+		let mut reader = self.open("blobs", digest, "*/*")?;
+
+		let mut hasher = Sha256::new();
+		let mut buffer = [0u8; 64 * 1024];
+		let mut size = 0;
+		loop {
+			let count = reader.read(&mut buffer)?;
+			if count == 0 {
+				break;
+			}
+			hasher.update(&buffer[..count]);
+			writer.write_all(&buffer[..count])?;
+			size += count as u64;
+		}
+
+		let actual = hex::encode(hasher.finalize());
+		if actual != expected {
+			return Err(Error::Checksum {
+				name: digest.to_string(),
+				expected: expected.to_string(),
+				actual,
+			});
+		}
+		Ok(size)
+	}
+
+	/// Download and parse the image configuration.
+	pub fn config(&self, manifest: &Manifest) -> Result<ImageConfig> {
+		let mut content = Vec::new();
+		self.download_blob(&manifest.config.digest, &mut content)?;
+		Ok(serde_json::from_slice(&content)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_reference() -> Result<()> {
+		let reference = ImageReference::parse("alpine")?;
+		assert_eq!(reference.name, "docker.io/library/alpine");
+		assert_eq!(reference.reference, "latest");
+
+		let reference = ImageReference::parse("localhost:5000/team/demo:1.0")?;
+		assert_eq!(reference.name, "localhost:5000/team/demo");
+		assert_eq!(reference.reference, "1.0");
+		match reference.source {
+			ImageSource::Registry { base, .. } => assert_eq!(base, "http://localhost:5000"),
+			_ => panic!("Expected a registry"),
+		}
+
+		let reference = ImageReference::parse("oci:/tmp/layout:v2")?;
+		assert_eq!(reference.name, "oci:/tmp/layout");
+		assert_eq!(reference.reference, "v2");
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_challenge() {
+		let challenge = parse_challenge(
+			r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/alpine:pull""#,
+		)
+		.unwrap();
+		assert_eq!(challenge["realm"], "https://auth.docker.io/token");
+		assert_eq!(challenge["scope"], "repository:library/alpine:pull");
+
+		let challenge = parse_challenge(
+			r#"Bearer realm="https://ghcr.io/token", scope="repository:user/app:pull,push", error=insufficient_scope, note="a \"quoted\" word""#,
+		)
+		.unwrap();
+		assert_eq!(challenge["scope"], "repository:user/app:pull,push");
+		assert_eq!(challenge["error"], "insufficient_scope");
+		assert_eq!(challenge["note"], r#"a "quoted" word"#);
+		assert_eq!(challenge.len(), 4);
+
+		assert!(parse_challenge(r#"Basic realm="registry""#).is_none());
+		assert!(parse_challenge(r#"Bearer realm="unterminated"#).is_none());
+	}
+}
@@ -1,13 +1,15 @@
 use crate::analysis::Analysis;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::store::{AsyncStore, PackageQuery, Store};
+use crate::version::Version;
 use log::info;
-use reqwest::StatusCode;
-use serde::{Deserialize, Serialize};
-use simple_error::bail;
-use std::collections::HashMap;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::default::Default;
-use std::error::Error;
 
-#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug, Clone, Copy)]
 pub enum PackageChannel {
 	#[default]
 	None,
@@ -20,7 +22,32 @@ pub enum PackageChannel {
 	DockerHub,
 }
 
-#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug)]
+/// A downloadable file belonging to a package, for channels that publish more
+/// than one file per version.
+#[derive(Serialize, Deserialize, Default, PartialEq, Eq, Debug, Clone)]
+pub struct Artifact {
+	pub filename: String,
+
+	pub url: String,
+
+	/// The kind of file as described by the channel (e.g. `sdist`)
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub kind: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub size: Option<u64>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub md5sum: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub sha1sum: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub sha256sum: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
 pub struct Package {
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub _id: Option<String>,
@@ -34,14 +61,30 @@ pub struct Package {
 
 	pub version: String,
 
+	/// Where the package can be downloaded. For pacman and Debian this is
+	/// relative to the mirror (see [`Package::download_url`]).
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub url: Option<String>,
 
+	/// The repository the package was published in, for channels that have
+	/// more than one
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub repo: Option<String>,
+
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub description: Option<String>,
 
+	/// The compressed size of the package archive in bytes
+	#[serde(
+		default,
+		deserialize_with = "lenient_number",
+		skip_serializing_if = "Option::is_none"
+	)]
+	pub size: Option<u64>,
+
+	/// The size of the installed package in bytes
 	#[serde(skip_serializing_if = "Option::is_none")]
-	pub size: Option<String>,
+	pub installed_size: Option<u64>,
 
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub md5sum: Option<String>,
@@ -49,17 +92,112 @@ pub struct Package {
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub sha256sum: Option<String>,
 
+	/// A Subresource Integrity string (e.g. `sha512-...`)
 	#[serde(skip_serializing_if = "Option::is_none")]
-	pub upstream_url: Option<String>,
+	pub integrity: Option<String>,
 
+	/// The detached signature of the package (base64)
 	#[serde(skip_serializing_if = "Option::is_none")]
-	pub license: Option<String>,
+	pub pgpsig: Option<String>,
+
+	/// The fingerprint of the OpenPGP key whose signature over the package
+	/// was verified
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub signing_key: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub upstream_url: Option<String>,
+
+	#[serde(
+		default,
+		deserialize_with = "lenient_list",
+		skip_serializing_if = "Vec::is_empty"
+	)]
+	pub license: Vec<String>,
 
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub arch: Option<String>,
 
+	/// When the package was built (seconds since the epoch)
+	#[serde(
+		default,
+		deserialize_with = "lenient_number",
+		skip_serializing_if = "Option::is_none"
+	)]
+	pub build_date: Option<u64>,
+
 	#[serde(skip_serializing_if = "Option::is_none")]
-	pub build_date: Option<String>,
+	pub packager: Option<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub maintainers: Vec<String>,
+
+	/// The source package this package was built from
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub base: Option<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub groups: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub depends: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub optdepends: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub makedepends: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub checkdepends: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub provides: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub conflicts: Vec<String>,
+
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub replaces: Vec<String>,
+
+	/// The downloadable files of this version. For container images these
+	/// are the filesystem layers, from the bottom up.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub artifacts: Vec<Artifact>,
+
+	/// The digest of a container image's manifest
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub digest: Option<String>,
+
+	/// The digest of a container image's configuration
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub config_digest: Option<String>,
+
+	/// The command a container image runs by default (entrypoint followed by
+	/// arguments)
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub entrypoint: Vec<String>,
+
+	/// Optional features and the features or dependencies they enable
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub features: BTreeMap<String, Vec<String>>,
+
+	/// Whether the version was withdrawn from the channel after publication
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub yanked: bool,
+
+	/// Scripts the package manager runs while installing or removing the
+	/// package, keyed by lifecycle event
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub scripts: BTreeMap<String, String>,
+
+	/// Metadata fields that autovet doesn't know about, keyed by field name
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub extra: BTreeMap<String, Vec<String>>,
+
+	/// The ID of the previous version of this package
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub supersedes: Option<String>,
 
 	/// The analysis results
 	#[serde(skip_serializing_if = "Option::is_none")]
@@ -68,109 +206,359 @@ pub struct Package {
 	/// The ID of the worker assigned to process this package
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub worker: Option<String>,
+
+	/// When the worker's claim on this package expires (seconds since the
+	/// epoch). This is only set while the package is being processed.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub lease_expires: Option<u64>,
 }
 
-mod db {
-	use serde::Deserialize;
+impl Package {
+	/// Parse a package from a pacman `desc` file. Each field starts with a
+	/// `%NAME%` header followed by one value per line until the next blank
+	/// line.
+	pub fn from_pacman_desc(desc: &str) -> Result<Package> {
+		let mut package = Package {
+			channel: PackageChannel::Pacman,
+			..Default::default()
+		};
 
-	#[derive(Deserialize)]
-	pub struct UpdateDocumentResponse {
-		pub id: String,
-		pub ok: bool,
-		pub rev: String,
-	}
+		let mut lines = desc.lines().map(|line| line.trim());
+		while let Some(line) = lines.next() {
+			let field = match line
+				.strip_prefix('%')
+				.and_then(|line| line.strip_suffix('%'))
+			{
+				Some(field) => field,
+				None if line.is_empty() => continue,
+				None => return Err(Error::Parse(format!("Unexpected line: {}", line))),
+			};
 
-	#[derive(Deserialize)]
-	pub struct FindPackageResponse {
-		pub docs: Vec<super::Package>,
+			let mut values: Vec<String> = lines
+				.by_ref()
+				.take_while(|line| !line.is_empty())
+				.map(String::from)
+				.collect();
+
+			match field {
+				"NAME" => package.name = single(field, values)?,
+				"FILENAME" => package.url = Some(single(field, values)?),
+				"VERSION" => package.version = single(field, values)?,
+				"DESC" => package.description = Some(values.join("\n")),
+				"CSIZE" => package.size = Some(number(field, values)?),
+				"ISIZE" => package.installed_size = Some(number(field, values)?),
+				"MD5SUM" => package.md5sum = Some(single(field, values)?),
+				"SHA256SUM" => package.sha256sum = Some(single(field, values)?),
+				"PGPSIG" => package.pgpsig = Some(values.concat()),
+				"URL" => package.upstream_url = Some(single(field, values)?),
+				"LICENSE" => package.license.append(&mut values),
+				"ARCH" => package.arch = Some(single(field, values)?),
+				"BUILDDATE" => package.build_date = Some(number(field, values)?),
+				"PACKAGER" => package.packager = Some(single(field, values)?),
+				"BASE" => package.base = Some(single(field, values)?),
+				"GROUPS" => package.groups.append(&mut values),
+				"DEPENDS" => package.depends.append(&mut values),
+				"OPTDEPENDS" => package.optdepends.append(&mut values),
+				"MAKEDEPENDS" => package.makedepends.append(&mut values),
+				"CHECKDEPENDS" => package.checkdepends.append(&mut values),
+				"PROVIDES" => package.provides.append(&mut values),
+				"CONFLICTS" => package.conflicts.append(&mut values),
+				"REPLACES" => package.replaces.append(&mut values),
+				_ => package
+					.extra
+					.entry(field.to_string())
+					.or_default()
+					.append(&mut values),
+			}
+		}
+
+		if package.name.is_empty() {
+			return Err(Error::Parse(String::from("No package name found")));
+		}
+
+		if package.version.is_empty() {
+			return Err(Error::Parse(String::from("No package version found")));
+		}
+
+		Ok(package)
 	}
-}
 
-impl Package {
-	pub fn from_pacman_desc(desc: &str) -> Result<Package, Box<dyn Error>> {
-		let lines: Vec<&str> = desc.split("\n").collect();
-		let mut package = Package::default();
-		package.channel = PackageChannel::Pacman;
-
-		for i in 0..lines.len() {
-			match lines[i].trim() {
-				"%NAME%" => package.name = lines[i + 1].trim().to_string(),
-				"%FILENAME%" => package.url = Some(lines[i + 1].trim().to_string()), // TODO
-				"%VERSION%" => package.version = lines[i + 1].trim().to_string(),
-				"%DESC%" => package.description = Some(lines[i + 1].trim().to_string()),
-				"%CSIZE%" => package.size = Some(lines[i + 1].trim().to_string()),
-				"%MD5SUM%" => package.md5sum = Some(lines[i + 1].trim().to_string()),
-				"%SHA256SUM%" => package.sha256sum = Some(lines[i + 1].trim().to_string()),
-				"%URL%" => package.upstream_url = Some(lines[i + 1].trim().to_string()),
-				"%LICENSE%" => package.license = Some(lines[i + 1].trim().to_string()),
-				"%ARCH%" => package.arch = Some(lines[i + 1].trim().to_string()),
-				"%BUILDDATE%" => package.build_date = Some(lines[i + 1].trim().to_string()),
-				_ => continue,
+	/// Parse a package from a stanza of a Debian `Packages` index. Fields are
+	/// `Name: value` lines and values may continue on following lines that
+	/// start with whitespace.
+	pub fn from_debian_stanza(stanza: &str) -> Result<Package> {
+		let mut package = Package {
+			channel: PackageChannel::Debian,
+			..Default::default()
+		};
+
+		for (field, value) in parse_stanza(stanza)? {
+			match field.as_str() {
+				"Package" => package.name = value,
+				"Version" => package.version = value,
+				"Architecture" => package.arch = Some(value),
+				"Filename" => package.url = Some(value),
+				"Size" => package.size = Some(parse_number(&field, &value)?),
+				// Debian records the installed size in KiB
+				"Installed-Size" => {
+					package.installed_size = Some(parse_number(&field, &value)? * 1024)
+				}
+				"MD5sum" => package.md5sum = Some(value),
+				"SHA256" => package.sha256sum = Some(value),
+				"Homepage" => package.upstream_url = Some(value),
+				"Description" => package.description = Some(value),
+				"Maintainer" => package.maintainers = vec![value],
+				// The source may include a version in parentheses
+				"Source" => package.base = value.split_whitespace().next().map(String::from),
+				"Depends" | "Pre-Depends" => package.depends.append(&mut split_relations(&value)),
+				"Recommends" | "Suggests" => {
+					package.optdepends.append(&mut split_relations(&value))
+				}
+				"Provides" => package.provides = split_relations(&value),
+				"Conflicts" | "Breaks" => package.conflicts.append(&mut split_relations(&value)),
+				"Replaces" => package.replaces = split_relations(&value),
+				_ => {
+					package.extra.insert(field, vec![value]);
+				}
 			}
 		}
 
-		if package.name == "" {
-			bail!("No package name found");
+		if package.name.is_empty() {
+			return Err(Error::Parse(String::from("No package name found")));
 		}
 
-		if package.version == "" {
-			bail!("No package version found");
+		if package.version.is_empty() {
+			return Err(Error::Parse(String::from("No package version found")));
 		}
 
 		Ok(package)
 	}
 
-	pub fn find(body: serde_json::Value) -> Result<Vec<Package>, Box<dyn Error>> {
-		let rs = reqwest::blocking::Client::new()
-			.post("http://db.auto.vet:5984/packages/_find")
-			.header(reqwest::header::AUTHORIZATION, "Basic YWRtaW46YWRtaW4=")
-			.header(reqwest::header::ACCEPT, "application/json")
-			.header(reqwest::header::CONTENT_TYPE, "application/json")
-			.body(serde_json::to_string(&body)?)
-			.send()?;
-
-		match rs.status() {
-			StatusCode::OK => {
-				let rs: db::FindPackageResponse = rs.json()?;
-				Ok(rs.docs)
+	/// The artifact that is downloaded for packages without a file of their
+	/// own (such as PyPI releases): the source distribution if there is one,
+	/// otherwise the first artifact.
+	fn primary_artifact(&self) -> Option<&Artifact> {
+		if self.url.is_some() {
+			return None;
+		}
+		self.artifacts
+			.iter()
+			.find(|artifact| artifact.kind.as_deref() == Some("sdist"))
+			.or_else(|| self.artifacts.first())
+	}
+
+	/// The absolute URL of the package's file, resolving relative locations
+	/// against the configured mirror or registry of its channel.
+	pub fn download_url(&self, config: &Config) -> Option<String> {
+		let url = match self.primary_artifact() {
+			Some(artifact) => &artifact.url,
+			None => self.url.as_ref()?,
+		};
+		if url.contains("://") {
+			return Some(url.clone());
+		}
+
+		let base = |base: &str| Some(format!("{}/{}", base.trim_end_matches('/'), url));
+		match self.channel {
+			PackageChannel::Pacman => {
+				// Packages for any architecture are published in every tree
+				let arch = match self.arch.as_deref() {
+					Some("any") | None => config.pacman.architectures.first()?,
+					Some(arch) => arch,
+				};
+				Some(format!(
+					"{}/{}",
+					config.pacman.server(self.repo.as_ref()?, arch),
+					url
+				))
 			}
-			_ => bail!(""),
+			PackageChannel::Debian => base(&config.debian.mirror),
+			PackageChannel::PyPi => base(&config.pypi.index),
+			PackageChannel::Npm => base(&config.npm.registry),
+			PackageChannel::CratesIo => base(&config.crates_io.index),
+			PackageChannel::MavenCentral => base(&config.maven.repository),
+			// Images are pulled through the registry API instead
+			PackageChannel::DockerHub | PackageChannel::None => None,
 		}
 	}
 
-	pub fn update(&mut self) -> Result<(), Box<dyn Error>> {
-		let rs = if self._id.is_none() {
+	/// The SHA-256 and MD5 sums of the file at [`Package::download_url`].
+	pub fn download_checksums(&self) -> (Option<&str>, Option<&str>) {
+		match self.primary_artifact() {
+			Some(artifact) => (artifact.sha256sum.as_deref(), artifact.md5sum.as_deref()),
+			None => (self.sha256sum.as_deref(), self.md5sum.as_deref()),
+		}
+	}
+
+	pub fn find(store: &dyn Store, query: &PackageQuery) -> Result<Vec<Package>> {
+		store.find_packages(query)
+	}
+
+	pub fn update(&mut self, store: &dyn Store) -> Result<()> {
+		if self._rev.is_none() {
 			info!("Creating new package: {}", self.name);
+			store.create_package(self)
+		} else {
+			info!("Updating package: {}", self.name);
+			store.update_package(self)
+		}
+	}
+
+	/// Every known version of this package, oldest first.
+	pub fn history(&self, store: &dyn Store) -> Result<Vec<Package>> {
+		store.package_history(self.channel, &self.name)
+	}
+
+	/// Find the newest version of this package in the store that is older
+	/// than this one.
+	pub fn predecessor(&self, store: &dyn Store) -> Result<Option<Package>> {
+		Ok(self
+			.history(store)?
+			.into_iter()
+			.rev()
+			.find(|candidate| self.supersedes_version(candidate)))
+	}
 
-			reqwest::blocking::Client::new()
-				.post("http://db.auto.vet:5984/packages")
-				.header(reqwest::header::AUTHORIZATION, "Basic YWRtaW46YWRtaW4=")
-				.header(reqwest::header::ACCEPT, "application/json")
-				.json(&self)
-				.send()?
+	/// Find the newest of the given packages that is an older version of this
+	/// one.
+	pub fn predecessor_in<'a, I>(&self, candidates: I) -> Option<&'a Package>
+	where
+		I: IntoIterator<Item = &'a Package>,
+	{
+		candidates
+			.into_iter()
+			.filter(|candidate| self.supersedes_version(candidate))
+			.max_by(|a, b| {
+				a.channel_version()
+					.partial_cmp(&b.channel_version())
+					.unwrap_or(Ordering::Equal)
+			})
+	}
+
+	/// Whether this package is a newer version of the given one.
+	fn supersedes_version(&self, other: &Package) -> bool {
+		other.name == self.name && other.channel_version() < self.channel_version()
+	}
+
+	/// The version of this package, ordered by the rules of its channel.
+	pub fn channel_version(&self) -> Version<'_> {
+		Version::new(self.channel, &self.version)
+	}
+
+	pub async fn find_async(store: &dyn AsyncStore, query: &PackageQuery) -> Result<Vec<Package>> {
+		store.find_packages(query).await
+	}
+
+	pub async fn update_async(&mut self, store: &dyn AsyncStore) -> Result<()> {
+		if self._rev.is_none() {
+			info!("Creating new package: {}", self.name);
+			store.create_package(self).await
 		} else {
 			info!("Updating package: {}", self.name);
-			reqwest::blocking::Client::new()
-				.put(format!(
-					"http://db.auto.vet:5984/packages/{}?rev={}",
-					self._id.as_ref().unwrap(),
-					self._rev.as_ref().unwrap()
-				))
-				.header(reqwest::header::AUTHORIZATION, "Basic YWRtaW46YWRtaW4=")
-				.json(&self)
-				.send()?
-		};
+			store.update_package(self).await
+		}
+	}
+}
 
-		match rs.status() {
-			StatusCode::CREATED | StatusCode::ACCEPTED => {
-				let rs: db::UpdateDocumentResponse = rs.json()?;
-				self._rev = Some(rs.rev);
-				self._id = Some(rs.id);
-				Ok(())
+/// Split a Debian control stanza into fields. Continuation lines are joined
+/// with newlines and a lone `.` stands for an empty line.
+pub fn parse_stanza(stanza: &str) -> Result<Vec<(String, String)>> {
+	let mut fields: Vec<(String, String)> = Vec::new();
+
+	for line in stanza.lines() {
+		if line.starts_with(' ') || line.starts_with('\t') {
+			let line = line.trim();
+			match fields.last_mut() {
+				Some((_, value)) => {
+					value.push('\n');
+					if line != "." {
+						value.push_str(line);
+					}
+				}
+				None => return Err(Error::Parse(format!("Unexpected continuation: {}", line))),
 			}
-			_ => bail!(""),
+		} else if let Some((field, value)) = line.split_once(':') {
+			fields.push((field.trim().to_string(), value.trim().to_string()));
+		} else if !line.trim().is_empty() {
+			return Err(Error::Parse(format!("Unexpected line: {}", line)));
 		}
 	}
+
+	Ok(fields)
+}
+
+/// Split a Debian relationship field like `libc6 (>= 2.34), zlib1g`.
+fn split_relations(value: &str) -> Vec<String> {
+	value
+		.split(',')
+		.map(|relation| relation.trim().to_string())
+		.filter(|relation| !relation.is_empty())
+		.collect()
+}
+
+fn parse_number(field: &str, value: &str) -> Result<u64> {
+	value
+		.parse()
+		.map_err(|_| Error::Parse(format!("Invalid number for {}: {}", field, value)))
+}
+
+/// Get the value of a field that must have exactly one line.
+fn single(field: &str, values: Vec<String>) -> Result<String> {
+	match <[String; 1]>::try_from(values) {
+		Ok([value]) => Ok(value),
+		Err(values) => Err(Error::Parse(format!(
+			"Expected one value for %{}%, found {}",
+			field,
+			values.len()
+		))),
+	}
+}
+
+/// Get the value of a numeric field.
+fn number(field: &str, values: Vec<String>) -> Result<u64> {
+	parse_number(&format!("%{}%", field), &single(field, values)?)
+}
+
+/// Deserialize a number that older documents stored as a string.
+fn lenient_number<'de, D>(deserializer: D) -> std::result::Result<Option<u64>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum Number {
+		Number(u64),
+		String(String),
+	}
+
+	match Option::<Number>::deserialize(deserializer)? {
+		Some(Number::Number(number)) => Ok(Some(number)),
+		Some(Number::String(string)) if string.trim().is_empty() => Ok(None),
+		Some(Number::String(string)) => match string.trim().parse() {
+			Ok(number) => Ok(Some(number)),
+			Err(_) => Err(D::Error::custom(format!("Invalid number: {}", string))),
+		},
+		None => Ok(None),
+	}
+}
+
+/// Deserialize a list that older documents stored as a single string.
+fn lenient_list<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum List {
+		List(Vec<String>),
+		String(String),
+	}
+
+	match Option::<List>::deserialize(deserializer)? {
+		Some(List::List(list)) => Ok(list),
+		Some(List::String(string)) => Ok(vec![string]),
+		None => Ok(Vec::new()),
+	}
 }
 
 #[cfg(test)]
@@ -233,7 +621,10 @@ mod tests {
 				pcre
 				
 				%MAKEDEPENDS%
-				texinfo"#
+				texinfo
+				
+				%XDATA%
+				pkgtype=pkg"#
 			).unwrap(),
 			Package {
 				_id: None,
@@ -243,18 +634,282 @@ mod tests {
 				version: String::from("3.7-1"),
 				url: Some(String::from("grep-3.7-1-x86_64.pkg.tar.zst")),
 				description: Some(String::from("A string search utility")),
-				size: Some(String::from("254020")),
+				size: Some(254020),
+				installed_size: Some(846791),
 				md5sum: Some(String::from("df80335ae92442feb3c9bfdb9d91eff6")),
 				sha256sum: Some(String::from(
 					"eca12ceaef774299ed7022de9b00dd7ce379186da4c223cf357fc157a9064da6"
 				)),
+				pgpsig: Some(String::from("iQIrBAABCgAdFiEEVyJOyaX8pvvJK7iqShr8NF6+GPgFAmEe3IIACgkQShr8NF6+GPh9jg++KqhTxYryohCP8quWh9CUrrsLmGBipBTvEzAs/hOs3JsaqezSx8SETMvFYtAvrX0ACap9T2rFsfudrRF4Rd7aHw4OCBk2klHKeQK/2z4JjQ7unJh0Q59fAkrBFf1T0aCP9gCJr6FC08+UACihhZhqTxO5dvsD1SAdTqf61Ax6m1NosBKni4NnJpj/7Z7rBCl32EED6sxm+JqQXF4fxA9uKKJklFND7qMYeY61f/j3jZcVITNAkUidzmE14wB2oePn9gk3e/LBF6rTdcvLsPfADl3tuFW6tV652kA+dB9K7Di64qQ90bHguegWpN/FuOxOzwh5EkLc8Sr7L8YymJf+if5KtiMYIoVZ4Ol8dDGBOyohJQ08i9KeLJDNam68k8ctXibSfHHE+ROtcqOUaCzuuLZXm3yF467t/kbtpMVzYI1uyvmUKH2iF7MHmLsAHTbbsz+1rh4vOtXgvaaxAe642jqoc3EqmC3BwKq9ij8Hp4/ZWIzHySgqsxTX4/5DmZl7qv2LnO49t62Ow2FZzTw2tEOWyCqlpiLsP5+Iyqq8Cp8wbS1eIzun2BacMcdjE760luR5kMB96lxYVTEinedumoS8rwJPW0m+9UjalUXYlksjiKgqta8mpfbllibO9MLbPPW9IaPleEvG7P/jVsY7cFkkSWXUnK08")),
 				upstream_url: Some(String::from("https://www.gnu.org/software/grep/")),
-				license: Some(String::from("GPL3")),
+				license: vec![String::from("GPL3")],
 				arch: Some(String::from("x86_64")),
-				build_date: Some(String::from("1629412344")),
+				build_date: Some(1629412344),
+				packager: Some(String::from("Sébastien Luttringer <seblu@seblu.net>")),
+				base: Some(String::from("grep")),
+				groups: vec![String::from("base-devel")],
+				depends: vec![String::from("glibc"), String::from("pcre")],
+				makedepends: vec![String::from("texinfo")],
+				extra: BTreeMap::from([(String::from("XDATA"), vec![String::from("pkgtype=pkg")])]),
 				analysis: None,
 				worker: None,
+				lease_expires: None,
+				..Default::default()
 			}
 		);
 	}
+
+	#[test]
+	fn test_parse_pacman_desc_lists() -> Result<()> {
+		let package = Package::from_pacman_desc(
+			"%NAME%\nperl-clone\n\n%VERSION%\n0.45-4\n\n%LICENSE%\nGPL\nPerlArtistic\n",
+		)?;
+		assert_eq!(package.license, vec!["GPL", "PerlArtistic"]);
+
+		assert!(
+			Package::from_pacman_desc("%NAME%\ngrep\n\n%VERSION%\n3.7-1\n\n%CSIZE%\nbig\n")
+				.is_err()
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_deserialize_old_document() -> Result<()> {
+		let package: Package = serde_json::from_str(
+			r#"{
+				"_id": "0a1b2c",
+				"_rev": "3-abc",
+				"channel": "Pacman",
+				"name": "grep",
+				"version": "3.7-1",
+				"size": "254020",
+				"license": "GPL3",
+				"build_date": "1639598477"
+			}"#,
+		)?;
+		assert_eq!(package.size, Some(254020));
+		assert_eq!(package.license, vec!["GPL3"]);
+		assert_eq!(package.build_date, Some(1639598477));
+
+		// Documents written since then round trip unchanged
+		let json = serde_json::to_string(&package)?;
+		assert_eq!(serde_json::from_str::<Package>(&json)?, package);
+		Ok(())
+	}
+
+	#[test]
+	fn test_history() -> Result<()> {
+		let store = crate::store::sqlite::SqliteStore::open_in_memory()?;
+		for version in ["3.7-1", "3.10-1", "3.8-2", "1:2.0-1"] {
+			Package {
+				channel: PackageChannel::Pacman,
+				name: String::from("grep"),
+				version: version.to_string(),
+				..Default::default()
+			}
+			.update(&store)?;
+		}
+
+		let package = Package {
+			channel: PackageChannel::Pacman,
+			name: String::from("grep"),
+			version: String::from("3.11-1"),
+			..Default::default()
+		};
+		assert_eq!(package.predecessor(&store)?.unwrap().version, "3.10-1");
+
+		let history: Vec<String> = package
+			.history(&store)?
+			.into_iter()
+			.map(|package| package.version)
+			.collect();
+		assert_eq!(history, vec!["3.7-1", "3.8-2", "3.10-1", "1:2.0-1"]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_history_unorderable() -> Result<()> {
+		let store = crate::store::sqlite::SqliteStore::open_in_memory()?;
+		for version in ["1.10.0", "latest", "1.2.0", "1.2", "0.9.0"] {
+			Package {
+				channel: PackageChannel::CratesIo,
+				name: String::from("serde"),
+				version: version.to_string(),
+				..Default::default()
+			}
+			.update(&store)?;
+		}
+
+		let history: Vec<String> = store
+			.package_history(PackageChannel::CratesIo, "serde")?
+			.into_iter()
+			.map(|package| package.version)
+			.collect();
+		assert_eq!(history, vec!["latest", "1.2", "0.9.0", "1.2.0", "1.10.0"]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_debian_stanza() -> Result<()> {
+		let package = Package::from_debian_stanza(
+			"Package: grep
+Source: grep (3.8-5)
+Version: 3.8-5
+Installed-Size: 1012
+Maintainer: Anibal Monsalve Salazar <anibal@debian.org>
+Architecture: amd64
+Pre-Depends: libc6 (>= 2.34), libpcre2-8-0 (>= 10.22)
+Suggests: libpcre3
+Description: GNU grep, egrep and fgrep
+ 'grep' is a utility to search for text in files.
+ .
+ It is also used by many scripts.
+Homepage: https://www.gnu.org/software/grep/
+Section: utils
+Filename: pool/main/g/grep/grep_3.8-5_amd64.deb
+Size: 294576
+SHA256: 4b5b0dd6b7e55e40bf4bd8e0ee6b4a3b5d0dc51c6e3e8dcd7b0a3b7df8e6d1d7
+",
+		)?;
+
+		assert_eq!(package.name, "grep");
+		assert_eq!(package.version, "3.8-5");
+		assert_eq!(package.base.as_deref(), Some("grep"));
+		assert_eq!(package.installed_size, Some(1012 * 1024));
+		assert_eq!(package.size, Some(294576));
+		assert_eq!(
+			package.depends,
+			vec!["libc6 (>= 2.34)", "libpcre2-8-0 (>= 10.22)"]
+		);
+		assert_eq!(
+			package.description.as_deref(),
+			Some("GNU grep, egrep and fgrep\n'grep' is a utility to search for text in files.\n\nIt is also used by many scripts.")
+		);
+		assert_eq!(package.extra["Section"], vec!["utils"]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_download_url() {
+		let config = Config::default();
+		let mut package = Package {
+			channel: PackageChannel::Pacman,
+			url: Some(String::from("grep-3.7-1-x86_64.pkg.tar.zst")),
+			..Default::default()
+		};
+		assert_eq!(package.download_url(&config), None);
+
+		package.repo = Some(String::from("core"));
+		assert_eq!(
+			package.download_url(&config).as_deref(),
+			Some(
+				"http://mirror.fossable.org/archlinux/core/os/x86_64/grep-3.7-1-x86_64.pkg.tar.zst"
+			)
+		);
+
+		package.arch = Some(String::from("aarch64"));
+		assert_eq!(
+			package.download_url(&config).as_deref(),
+			Some(
+				"http://mirror.fossable.org/archlinux/core/os/aarch64/grep-3.7-1-x86_64.pkg.tar.zst"
+			)
+		);
+
+		let package = Package {
+			channel: PackageChannel::Debian,
+			url: Some(String::from("pool/main/g/grep/grep_3.8-5_amd64.deb")),
+			..Default::default()
+		};
+		assert_eq!(
+			package.download_url(&config).as_deref(),
+			Some("http://deb.debian.org/debian/pool/main/g/grep/grep_3.8-5_amd64.deb")
+		);
+
+		let package = Package {
+			channel: PackageChannel::CratesIo,
+			url: Some(String::from(
+				"https://static.crates.io/crates/demo/demo-1.0.0.crate",
+			)),
+			..Default::default()
+		};
+		assert_eq!(package.download_url(&config), package.url);
+
+		let package = Package {
+			channel: PackageChannel::Npm,
+			url: Some(String::from("left-pad/-/left-pad-1.3.0.tgz")),
+			..Default::default()
+		};
+		assert_eq!(
+			package.download_url(&config).as_deref(),
+			Some("https://registry.npmjs.org/left-pad/-/left-pad-1.3.0.tgz")
+		);
+
+		let package = Package {
+			channel: PackageChannel::MavenCentral,
+			url: Some(String::from(
+				"org/slf4j/slf4j-api/2.0.0/slf4j-api-2.0.0.jar",
+			)),
+			..Default::default()
+		};
+		assert_eq!(
+			package.download_url(&config).as_deref(),
+			Some("https://repo1.maven.org/maven2/org/slf4j/slf4j-api/2.0.0/slf4j-api-2.0.0.jar")
+		);
+
+		let package = Package {
+			channel: PackageChannel::DockerHub,
+			url: Some(String::from("library/alpine")),
+			..Default::default()
+		};
+		assert_eq!(package.download_url(&config), None);
+	}
+
+	#[test]
+	fn test_download_artifact() {
+		let config = Config::default();
+		let artifact = |filename: &str, kind: &str, sha256sum: &str| Artifact {
+			filename: filename.to_string(),
+			url: format!("https://files.pythonhosted.org/packages/{}", filename),
+			kind: Some(kind.to_string()),
+			sha256sum: Some(sha256sum.to_string()),
+			..Default::default()
+		};
+		let mut package = Package {
+			channel: PackageChannel::PyPi,
+			artifacts: vec![
+				artifact("demo-1.0-py3-none-any.whl", "bdist_wheel", "aa"),
+				artifact("demo-1.0.tar.gz", "sdist", "bb"),
+			],
+			..Default::default()
+		};
+		assert_eq!(
+			package.download_url(&config).as_deref(),
+			Some("https://files.pythonhosted.org/packages/demo-1.0.tar.gz")
+		);
+		assert_eq!(package.download_checksums(), (Some("bb"), None));
+
+		// Without a source distribution the first file is used
+		package.artifacts.pop();
+		assert_eq!(
+			package.download_url(&config).as_deref(),
+			Some("https://files.pythonhosted.org/packages/demo-1.0-py3-none-any.whl")
+		);
+
+		// Maven artifacts without a main file fall back to the POM
+		let package = Package {
+			channel: PackageChannel::MavenCentral,
+			artifacts: vec![Artifact {
+				filename: String::from("demo-1.0.pom"),
+				url: String::from("com/example/demo/1.0/demo-1.0.pom"),
+				md5sum: Some(String::from("cc")),
+				..Default::default()
+			}],
+			..Default::default()
+		};
+		assert_eq!(
+			package.download_url(&config).as_deref(),
+			Some("https://repo1.maven.org/maven2/com/example/demo/1.0/demo-1.0.pom")
+		);
+		assert_eq!(package.download_checksums(), (None, Some("cc")));
+	}
 }
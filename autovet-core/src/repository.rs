@@ -0,0 +1,113 @@
+use crate::analysis::Finding;
+use crate::error::Result;
+use crate::package::PackageChannel;
+use crate::store::{AsyncStore, RepositoryQuery, Store};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What a poller knows about a repository index as of its last successful
+/// synchronization.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+pub struct Repository {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub _id: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub _rev: Option<String>,
+
+	pub channel: PackageChannel,
+
+	/// The repository's name within the channel, like `core` for pacman. The
+	/// state of the channel as a whole is recorded under an empty name.
+	pub name: String,
+
+	/// The architecture the index was synchronized for, if it's specific to one
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub arch: Option<String>,
+
+	/// The URL of the index that was downloaded
+	pub url: String,
+
+	/// The `ETag` of the index, used to skip unchanged downloads
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub etag: Option<String>,
+
+	/// The `Last-Modified` date of the index, used to skip unchanged
+	/// downloads
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub last_modified: Option<String>,
+
+	/// When the index was last synchronized successfully (seconds since the
+	/// epoch)
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub last_sync: Option<u64>,
+
+	/// When the index was last modified according to the server that serves
+	/// it (seconds since the epoch). An index that goes backwards was rolled
+	/// back.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub timestamp: Option<u64>,
+
+	/// When the package versions in the index were last seen to change
+	/// (seconds since the epoch). An index whose versions stop changing is
+	/// frozen, however recently it was modified.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub changed: Option<u64>,
+
+	/// The version of each package in the index, keyed by name
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub versions: BTreeMap<String, String>,
+
+	/// Problems with the index itself, such as rollbacks
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub alerts: Vec<Finding>,
+}
+
+impl Repository {
+	/// Find the state of the given repository, or start a new one.
+	pub fn find(
+		store: &dyn Store,
+		channel: PackageChannel,
+		name: &str,
+		arch: Option<&str>,
+	) -> Result<Repository> {
+		let query = RepositoryQuery {
+			channel: Some(channel),
+			name: Some(name.to_string()),
+			arch: Some(arch.map(String::from)),
+		};
+		Ok(store
+			.find_repositories(&query)?
+			.into_iter()
+			.next()
+			.unwrap_or_else(|| Repository {
+				channel,
+				name: name.to_string(),
+				arch: arch.map(String::from),
+				..Default::default()
+			}))
+	}
+
+	pub fn update(&mut self, store: &dyn Store) -> Result<()> {
+		if self._rev.is_none() {
+			store.create_repository(self)
+		} else {
+			store.update_repository(self)
+		}
+	}
+
+	pub async fn find_async(
+		store: &dyn AsyncStore,
+		query: &RepositoryQuery,
+	) -> Result<Vec<Repository>> {
+		store.find_repositories(query).await
+	}
+
+	pub async fn update_async(&mut self, store: &dyn AsyncStore) -> Result<()> {
+		if self._rev.is_none() {
+			store.create_repository(self).await
+		} else {
+			store.update_repository(self).await
+		}
+	}
+}
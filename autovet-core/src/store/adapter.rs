@@ -0,0 +1,179 @@
+use super::{AsyncStore, PackageQuery, RepositoryQuery, Store, WorkerQuery};
+use crate::{
+	analysis::Analysis,
+	error::{Error, Result},
+	package::Package,
+	repository::Repository,
+	worker::Worker,
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Exposes a blocking [`Store`] as an [`AsyncStore`] by running each operation
+/// on tokio's blocking thread pool.
+pub struct BlockingAdapter {
+	store: Arc<dyn Store>,
+}
+
+impl BlockingAdapter {
+	pub fn new(store: Arc<dyn Store>) -> BlockingAdapter {
+		BlockingAdapter { store }
+	}
+
+	async fn run<T, F>(&self, operation: F) -> Result<T>
+	where
+		T: Send + 'static,
+		F: FnOnce(&dyn Store) -> T + Send + 'static,
+	{
+		let store = self.store.clone();
+		tokio::task::spawn_blocking(move || operation(store.as_ref()))
+			.await
+			.map_err(|e| Error::Io(std::io::Error::other(e)))
+	}
+
+	/// Run a write operation on a copy of the document and move the result
+	/// back into place afterwards. The caller's document is left untouched if
+	/// the operation never completes.
+	async fn write<D, F>(&self, document: &mut D, operation: F) -> Result<()>
+	where
+		D: Clone + Send + 'static,
+		F: FnOnce(&dyn Store, &mut D) -> Result<()> + Send + 'static,
+	{
+		let mut owned = document.clone();
+		let (owned, result) = self
+			.run(move |store| {
+				let result = operation(store, &mut owned);
+				(owned, result)
+			})
+			.await?;
+
+		*document = owned;
+		result
+	}
+}
+
+#[async_trait]
+impl AsyncStore for BlockingAdapter {
+	async fn find_packages(&self, query: &PackageQuery) -> Result<Vec<Package>> {
+		let query = query.clone();
+		self.run(move |store| store.find_packages(&query)).await?
+	}
+
+	async fn create_package(&self, package: &mut Package) -> Result<()> {
+		self.write(package, |store, package| store.create_package(package))
+			.await
+	}
+
+	async fn create_packages(&self, packages: &mut [Package]) -> Result<Vec<Result<()>>> {
+		let mut owned = packages.to_vec();
+		let (owned, results) = self
+			.run(move |store| {
+				let results = store.create_packages(&mut owned);
+				(owned, results)
+			})
+			.await?;
+
+		for (package, owned) in packages.iter_mut().zip(owned) {
+			*package = owned;
+		}
+		results
+	}
+
+	async fn update_package(&self, package: &mut Package) -> Result<()> {
+		self.write(package, |store, package| store.update_package(package))
+			.await
+	}
+
+	async fn find_analyses(&self, package_id: &str) -> Result<Vec<Analysis>> {
+		let package_id = package_id.to_string();
+		self.run(move |store| store.find_analyses(&package_id))
+			.await?
+	}
+
+	async fn create_analysis(&self, analysis: &mut Analysis) -> Result<()> {
+		self.write(analysis, |store, analysis| store.create_analysis(analysis))
+			.await
+	}
+
+	async fn update_analysis(&self, analysis: &mut Analysis) -> Result<()> {
+		self.write(analysis, |store, analysis| store.update_analysis(analysis))
+			.await
+	}
+
+	async fn find_workers(&self, query: &WorkerQuery) -> Result<Vec<Worker>> {
+		let query = query.clone();
+		self.run(move |store| store.find_workers(&query)).await?
+	}
+
+	async fn create_worker(&self, worker: &mut Worker) -> Result<()> {
+		self.write(worker, |store, worker| store.create_worker(worker))
+			.await
+	}
+
+	async fn update_worker(&self, worker: &mut Worker) -> Result<()> {
+		self.write(worker, |store, worker| store.update_worker(worker))
+			.await
+	}
+
+	async fn delete_worker(&self, worker: &Worker) -> Result<()> {
+		let worker = worker.clone();
+		self.run(move |store| store.delete_worker(&worker)).await?
+	}
+
+	async fn find_repositories(&self, query: &RepositoryQuery) -> Result<Vec<Repository>> {
+		let query = query.clone();
+		self.run(move |store| store.find_repositories(&query))
+			.await?
+	}
+
+	async fn create_repository(&self, repository: &mut Repository) -> Result<()> {
+		self.write(repository, |store, repository| {
+			store.create_repository(repository)
+		})
+		.await
+	}
+
+	async fn update_repository(&self, repository: &mut Repository) -> Result<()> {
+		self.write(repository, |store, repository| {
+			store.update_repository(repository)
+		})
+		.await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{package::PackageChannel, store::sqlite::SqliteStore};
+
+	#[tokio::test]
+	async fn test_concurrent_operations() -> Result<()> {
+		let store = BlockingAdapter::new(Arc::new(SqliteStore::open_in_memory()?));
+
+		let mut grep = Package {
+			channel: PackageChannel::Pacman,
+			name: String::from("grep"),
+			version: String::from("3.7-1"),
+			..Default::default()
+		};
+		let mut sed = Package {
+			channel: PackageChannel::Pacman,
+			name: String::from("sed"),
+			version: String::from("4.8-1"),
+			..Default::default()
+		};
+
+		let (first, second) = tokio::join!(
+			store.create_package(&mut grep),
+			store.create_package(&mut sed)
+		);
+		first?;
+		second?;
+		assert!(grep._id.is_some());
+		assert!(sed._id.is_some());
+
+		let found = store.find_packages(&PackageQuery::default()).await?;
+		assert_eq!(found.len(), 2);
+		Ok(())
+	}
+}
@@ -0,0 +1,269 @@
+//! Storage backends for packages, analyses, workers and repository state.
+//!
+//! The rest of autovet only talks to the [`Store`] trait, so the same binaries
+//! can run against the production CouchDB instance or a local SQLite file.
+
+use crate::{
+	analysis::Analysis,
+	config::StoreConfig,
+	error::{Error, Result},
+	package::{Package, PackageChannel},
+	repository::Repository,
+	version,
+	worker::Worker,
+};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
+
+pub mod adapter;
+pub mod couchdb;
+pub mod sqlite;
+
+/// Criteria for selecting packages from a store. Unset fields match anything.
+#[derive(Default, Debug, Clone)]
+pub struct PackageQuery {
+	pub channel: Option<PackageChannel>,
+
+	pub name: Option<String>,
+
+	/// Only select packages with one of these names
+	pub names: Option<Vec<String>>,
+
+	pub version: Option<String>,
+
+	pub repo: Option<String>,
+
+	/// Only select packages that have no worker assigned
+	pub unassigned: bool,
+
+	/// Only select packages that are currently leased by a worker
+	pub leased: bool,
+
+	/// Restrict the fields that are returned (backends may return more)
+	pub fields: Option<Vec<String>>,
+
+	pub limit: Option<usize>,
+}
+
+/// Criteria for selecting workers from a store. Unset fields match anything.
+#[derive(Default, Debug, Clone)]
+pub struct WorkerQuery {
+	pub id: Option<String>,
+
+	/// Only select workers that sent a heartbeat at or after this time
+	pub checked_in_since: Option<u64>,
+
+	/// Only select workers that sent no heartbeat since this time
+	pub checked_in_before: Option<u64>,
+}
+
+/// Criteria for selecting repositories from a store. Unset fields match
+/// anything.
+#[derive(Default, Debug, Clone)]
+pub struct RepositoryQuery {
+	pub channel: Option<PackageChannel>,
+
+	pub name: Option<String>,
+
+	/// Only select repositories of this architecture, or with `Some(None)`
+	/// ones that aren't specific to an architecture
+	pub arch: Option<Option<String>>,
+}
+
+/// A backend capable of persisting autovet documents.
+///
+/// `create_*` methods assign `_id` and `_rev` on the given document and
+/// `update_*` methods refresh `_rev`. Updates and deletes must fail if the
+/// document's revision is stale, or with [`Error::NotCreated`] if it has none.
+pub trait Store: Send + Sync {
+	fn find_packages(&self, query: &PackageQuery) -> Result<Vec<Package>>;
+
+	fn create_package(&self, package: &mut Package) -> Result<()>;
+
+	/// Create many packages at once, returning the outcome for each one in
+	/// order. Backends that can write in bulk should override this.
+	fn create_packages(&self, packages: &mut [Package]) -> Result<Vec<Result<()>>> {
+		Ok(packages
+			.iter_mut()
+			.map(|package| self.create_package(package))
+			.collect())
+	}
+
+	fn update_package(&self, package: &mut Package) -> Result<()>;
+
+	fn find_analyses(&self, package_id: &str) -> Result<Vec<Analysis>>;
+
+	fn create_analysis(&self, analysis: &mut Analysis) -> Result<()>;
+
+	fn update_analysis(&self, analysis: &mut Analysis) -> Result<()>;
+
+	fn find_workers(&self, query: &WorkerQuery) -> Result<Vec<Worker>>;
+
+	fn create_worker(&self, worker: &mut Worker) -> Result<()>;
+
+	fn update_worker(&self, worker: &mut Worker) -> Result<()>;
+
+	fn delete_worker(&self, worker: &Worker) -> Result<()>;
+
+	fn find_repositories(&self, query: &RepositoryQuery) -> Result<Vec<Repository>>;
+
+	fn create_repository(&self, repository: &mut Repository) -> Result<()>;
+
+	fn update_repository(&self, repository: &mut Repository) -> Result<()>;
+
+	/// Every known version of the given package, oldest first.
+	fn package_history(&self, channel: PackageChannel, name: &str) -> Result<Vec<Package>> {
+		let mut packages = self.find_packages(&history_query(channel, name))?;
+		version::sort(channel, &mut packages, |package| &package.version);
+		Ok(packages)
+	}
+}
+
+/// The non-blocking counterpart of [`Store`] for use inside async runtimes.
+#[async_trait]
+pub trait AsyncStore: Send + Sync {
+	async fn find_packages(&self, query: &PackageQuery) -> Result<Vec<Package>>;
+
+	async fn create_package(&self, package: &mut Package) -> Result<()>;
+
+	/// Create many packages at once, returning the outcome for each one in
+	/// order. Backends that can write in bulk should override this.
+	async fn create_packages(&self, packages: &mut [Package]) -> Result<Vec<Result<()>>> {
+		let mut results = Vec::with_capacity(packages.len());
+		for package in packages.iter_mut() {
+			results.push(self.create_package(package).await);
+		}
+		Ok(results)
+	}
+
+	async fn update_package(&self, package: &mut Package) -> Result<()>;
+
+	async fn find_analyses(&self, package_id: &str) -> Result<Vec<Analysis>>;
+
+	async fn create_analysis(&self, analysis: &mut Analysis) -> Result<()>;
+
+	async fn update_analysis(&self, analysis: &mut Analysis) -> Result<()>;
+
+	async fn find_workers(&self, query: &WorkerQuery) -> Result<Vec<Worker>>;
+
+	async fn create_worker(&self, worker: &mut Worker) -> Result<()>;
+
+	async fn update_worker(&self, worker: &mut Worker) -> Result<()>;
+
+	async fn delete_worker(&self, worker: &Worker) -> Result<()>;
+
+	async fn find_repositories(&self, query: &RepositoryQuery) -> Result<Vec<Repository>>;
+
+	async fn create_repository(&self, repository: &mut Repository) -> Result<()>;
+
+	async fn update_repository(&self, repository: &mut Repository) -> Result<()>;
+
+	/// Every known version of the given package, oldest first.
+	async fn package_history(&self, channel: PackageChannel, name: &str) -> Result<Vec<Package>> {
+		let mut packages = self.find_packages(&history_query(channel, name)).await?;
+		version::sort(channel, &mut packages, |package| &package.version);
+		Ok(packages)
+	}
+}
+
+/// Every version of the given package. Versions can only be ordered once all
+/// of them are loaded, so this must never set a limit.
+pub(crate) fn history_query(channel: PackageChannel, name: &str) -> PackageQuery {
+	PackageQuery {
+		channel: Some(channel),
+		name: Some(name.to_string()),
+		..Default::default()
+	}
+}
+
+/// A document with CouchDB-style `_id` and `_rev` metadata.
+pub(crate) trait Document: Serialize + DeserializeOwned + Send + Sync {
+	/// The database (or table) that holds documents of this type
+	const DATABASE: &'static str;
+
+	fn id(&self) -> Option<&str>;
+
+	fn rev(&self) -> Option<&str>;
+
+	fn set_id(&mut self, id: String);
+
+	fn set_meta(&mut self, id: String, rev: String);
+}
+
+/// A fresh random document ID.
+pub(crate) fn new_id() -> String {
+	uuid::Uuid::new_v4().simple().to_string()
+}
+
+macro_rules! impl_document {
+	($type:ty, $database:literal) => {
+		impl Document for $type {
+			const DATABASE: &'static str = $database;
+
+			fn id(&self) -> Option<&str> {
+				self._id.as_deref()
+			}
+
+			fn rev(&self) -> Option<&str> {
+				self._rev.as_deref()
+			}
+
+			fn set_id(&mut self, id: String) {
+				self._id = Some(id);
+			}
+
+			fn set_meta(&mut self, id: String, rev: String) {
+				self._id = Some(id);
+				self._rev = Some(rev);
+			}
+		}
+	};
+}
+
+impl_document!(Package, "packages");
+impl_document!(Analysis, "analyses");
+impl_document!(Worker, "workers");
+impl_document!(Repository, "repositories");
+
+/// Open the store described by the given configuration.
+///
+/// - `http://host:5984` selects CouchDB
+/// - `sqlite://path/to/file.db` selects a SQLite file
+/// - `sqlite::memory:` selects a throwaway in-memory SQLite database
+///
+/// CouchDB stores have their query indexes created when they are opened.
+pub fn open(config: &StoreConfig) -> Result<Box<dyn Store>> {
+	let url = config.url.as_str();
+
+	if url.starts_with("http://") || url.starts_with("https://") {
+		let store =
+			couchdb::CouchDbStore::new(url, config.username.clone(), config.password.clone())?;
+		store.create_indexes()?;
+		Ok(Box::new(store))
+	} else if url == "sqlite::memory:" {
+		Ok(Box::new(sqlite::SqliteStore::open_in_memory()?))
+	} else if let Some(path) = url.strip_prefix("sqlite://") {
+		Ok(Box::new(sqlite::SqliteStore::open(path)?))
+	} else {
+		Err(Error::Config(format!("Unknown store: {}", url)))
+	}
+}
+
+/// Open the store described by the given configuration for use from an async
+/// runtime. Backends without native async support run on tokio's blocking
+/// thread pool. Unlike [`open`], this doesn't create CouchDB indexes.
+pub fn open_async(config: &StoreConfig) -> Result<Box<dyn AsyncStore>> {
+	let url = config.url.as_str();
+
+	if url.starts_with("http://") || url.starts_with("https://") {
+		Ok(Box::new(couchdb::AsyncCouchDbStore::new(
+			url,
+			config.username.clone(),
+			config.password.clone(),
+		)?))
+	} else {
+		let store: Arc<dyn Store> = open(config)?.into();
+		Ok(Box::new(adapter::BlockingAdapter::new(store)))
+	}
+}
@@ -0,0 +1,441 @@
+use super::{new_id, Document, PackageQuery, RepositoryQuery, Store, WorkerQuery};
+use crate::{
+	analysis::Analysis,
+	error::{Error, Result},
+	package::Package,
+	repository::Repository,
+	worker::Worker,
+};
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use serde_json::Value;
+use std::{path::Path, sync::Mutex};
+
+/// A store backed by an embedded SQLite database.
+///
+/// Documents are stored as JSON with one table per document type, mirroring
+/// the layout of the CouchDB databases.
+pub struct SqliteStore {
+	connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteStore> {
+		SqliteStore::init(Connection::open(path)?)
+	}
+
+	pub fn open_in_memory() -> Result<SqliteStore> {
+		SqliteStore::init(Connection::open_in_memory()?)
+	}
+
+	fn init(connection: Connection) -> Result<SqliteStore> {
+		for table in [
+			Package::DATABASE,
+			Analysis::DATABASE,
+			Worker::DATABASE,
+			Repository::DATABASE,
+		] {
+			connection.execute_batch(&format!(
+				"CREATE TABLE IF NOT EXISTS {table} (
+					id TEXT PRIMARY KEY,
+					rev INTEGER NOT NULL,
+					doc TEXT NOT NULL
+				);"
+			))?;
+		}
+
+		Ok(SqliteStore {
+			connection: Mutex::new(connection),
+		})
+	}
+
+	fn find<T: Document>(&self, filter: &str, params: &[&dyn ToSql]) -> Result<Vec<T>> {
+		let connection = self.connection.lock().unwrap();
+		let mut statement =
+			connection.prepare(&format!("SELECT doc FROM {} WHERE {}", T::DATABASE, filter))?;
+
+		let mut documents = Vec::new();
+		for doc in statement.query_map(params, |row| row.get::<_, String>(0))? {
+			documents.push(serde_json::from_str(&doc?)?);
+		}
+		Ok(documents)
+	}
+
+	fn create<T: Document>(&self, document: &mut T) -> Result<()> {
+		insert(&self.connection.lock().unwrap(), document)
+	}
+
+	fn update<T: Document>(&self, document: &mut T) -> Result<()> {
+		let (id, rev) = match (document.id(), document.rev()) {
+			(Some(id), Some(rev)) => (id.to_string(), rev.to_string()),
+			(id, _) => {
+				return Err(Error::NotCreated(format!(
+					"{}/{}",
+					T::DATABASE,
+					id.unwrap_or_default()
+				)))
+			}
+		};
+		let current: i64 = match rev.parse() {
+			Ok(current) => current,
+			Err(_) => return Err(Error::Parse(format!("Invalid revision: {}", rev))),
+		};
+
+		let connection = self.connection.lock().unwrap();
+		let stored: Option<i64> = connection
+			.query_row(
+				&format!("SELECT rev FROM {} WHERE id = ?1", T::DATABASE),
+				params![id],
+				|row| row.get(0),
+			)
+			.optional()?;
+
+		match stored {
+			Some(stored) if stored == current => {}
+			_ => return Err(Error::Conflict(format!("{}/{}", T::DATABASE, id))),
+		}
+
+		document.set_meta(id.clone(), (current + 1).to_string());
+		connection.execute(
+			&format!(
+				"UPDATE {} SET rev = ?1, doc = ?2 WHERE id = ?3",
+				T::DATABASE
+			),
+			params![current + 1, serde_json::to_string(document)?, id],
+		)?;
+		Ok(())
+	}
+
+	fn delete<T: Document>(&self, document: &T) -> Result<()> {
+		let (id, rev) = match (document.id(), document.rev()) {
+			(Some(id), Some(rev)) => (id, rev),
+			(id, _) => {
+				return Err(Error::NotCreated(format!(
+					"{}/{}",
+					T::DATABASE,
+					id.unwrap_or_default()
+				)))
+			}
+		};
+
+		let deleted = self.connection.lock().unwrap().execute(
+			&format!("DELETE FROM {} WHERE id = ?1 AND rev = ?2", T::DATABASE),
+			params![id, rev],
+		)?;
+		if deleted == 0 {
+			return Err(Error::Conflict(format!("{}/{}", T::DATABASE, id)));
+		}
+		Ok(())
+	}
+}
+
+/// Insert a new document, assigning it an ID unless it already has one.
+fn insert<T: Document>(connection: &Connection, document: &mut T) -> Result<()> {
+	let id = match document.id() {
+		Some(id) => id.to_string(),
+		None => new_id(),
+	};
+	document.set_id(id.clone());
+
+	// The revision is only set once the insert succeeded, so a failed attempt
+	// can be retried as a create
+	let mut doc = serde_json::to_value(&*document)?;
+	doc["_rev"] = Value::from("1");
+
+	let inserted = connection.execute(
+		&format!(
+			"INSERT OR IGNORE INTO {} (id, rev, doc) VALUES (?1, 1, ?2)",
+			T::DATABASE
+		),
+		params![id, doc.to_string()],
+	)?;
+	if inserted == 0 {
+		return Err(Error::Conflict(format!("{}/{}", T::DATABASE, id)));
+	}
+	document.set_meta(id, String::from("1"));
+	Ok(())
+}
+
+impl Store for SqliteStore {
+	fn find_packages(&self, query: &PackageQuery) -> Result<Vec<Package>> {
+		let channel = match &query.channel {
+			Some(channel) => Some(serde_json::to_value(channel)?.as_str().unwrap().to_string()),
+			None => None,
+		};
+		let limit = query.limit.map(|limit| limit as i64).unwrap_or(-1);
+		let names = match &query.names {
+			Some(names) => Some(serde_json::to_string(names)?),
+			None => None,
+		};
+
+		self.find(
+			"(?1 IS NULL OR json_extract(doc, '$.channel') = ?1)
+				AND (?2 IS NULL OR json_extract(doc, '$.name') = ?2)
+				AND (?3 IS NULL OR json_extract(doc, '$.version') = ?3)
+				AND (?4 IS NULL OR json_extract(doc, '$.repo') = ?4)
+				AND (?5 = 0 OR json_extract(doc, '$.worker') IS NULL)
+				AND (?6 = 0 OR json_extract(doc, '$.lease_expires') IS NOT NULL)
+				AND (?8 IS NULL OR json_extract(doc, '$.name') IN (SELECT value FROM json_each(?8)))
+				LIMIT ?7",
+			params![
+				channel,
+				query.name,
+				query.version,
+				query.repo,
+				query.unassigned,
+				query.leased,
+				limit,
+				names
+			],
+		)
+	}
+
+	fn create_package(&self, package: &mut Package) -> Result<()> {
+		self.create(package)
+	}
+
+	/// Insert every package in a single transaction, which is much faster
+	/// than committing each one.
+	fn create_packages(&self, packages: &mut [Package]) -> Result<Vec<Result<()>>> {
+		let mut connection = self.connection.lock().unwrap();
+		let transaction = connection.transaction()?;
+		let results = packages
+			.iter_mut()
+			.map(|package| insert(&transaction, package))
+			.collect();
+		transaction.commit()?;
+		Ok(results)
+	}
+
+	fn update_package(&self, package: &mut Package) -> Result<()> {
+		self.update(package)
+	}
+
+	fn find_analyses(&self, package_id: &str) -> Result<Vec<Analysis>> {
+		self.find(
+			"json_extract(doc, '$.package_id') = ?1",
+			params![package_id],
+		)
+	}
+
+	fn create_analysis(&self, analysis: &mut Analysis) -> Result<()> {
+		self.create(analysis)
+	}
+
+	fn update_analysis(&self, analysis: &mut Analysis) -> Result<()> {
+		self.update(analysis)
+	}
+
+	fn find_workers(&self, query: &WorkerQuery) -> Result<Vec<Worker>> {
+		self.find(
+			"(?1 IS NULL OR id = ?1)
+				AND (?2 IS NULL OR json_extract(doc, '$.last_checkin') >= ?2)
+				AND (?3 IS NULL OR json_extract(doc, '$.last_checkin') < ?3)",
+			params![
+				query.id,
+				query.checked_in_since.map(|time| time as i64),
+				query.checked_in_before.map(|time| time as i64)
+			],
+		)
+	}
+
+	fn create_worker(&self, worker: &mut Worker) -> Result<()> {
+		self.create(worker)
+	}
+
+	fn update_worker(&self, worker: &mut Worker) -> Result<()> {
+		self.update(worker)
+	}
+
+	fn delete_worker(&self, worker: &Worker) -> Result<()> {
+		self.delete(worker)
+	}
+
+	fn find_repositories(&self, query: &RepositoryQuery) -> Result<Vec<Repository>> {
+		let channel = match &query.channel {
+			Some(channel) => Some(serde_json::to_value(channel)?.as_str().unwrap().to_string()),
+			None => None,
+		};
+
+		self.find(
+			"(?1 IS NULL OR json_extract(doc, '$.channel') = ?1)
+				AND (?2 IS NULL OR json_extract(doc, '$.name') = ?2)
+				AND (?3 = 0 OR json_extract(doc, '$.arch') IS ?4)",
+			params![
+				channel,
+				query.name,
+				query.arch.is_some(),
+				query.arch.clone().flatten()
+			],
+		)
+	}
+
+	fn create_repository(&self, repository: &mut Repository) -> Result<()> {
+		self.create(repository)
+	}
+
+	fn update_repository(&self, repository: &mut Repository) -> Result<()> {
+		self.update(repository)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::package::PackageChannel;
+
+	fn package(name: &str, version: &str) -> Package {
+		Package {
+			channel: PackageChannel::Pacman,
+			name: name.to_string(),
+			version: version.to_string(),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_create_and_find_packages() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+
+		let mut grep = package("grep", "3.7-1");
+		store.create_package(&mut grep)?;
+		assert!(grep._id.is_some());
+		assert_eq!(grep._rev.as_deref(), Some("1"));
+
+		store.create_package(&mut package("sed", "4.8-1"))?;
+
+		let found = store.find_packages(&PackageQuery {
+			channel: Some(PackageChannel::Pacman),
+			name: Some(String::from("grep")),
+			..Default::default()
+		})?;
+		assert_eq!(found, vec![grep]);
+
+		let found = store.find_packages(&PackageQuery {
+			limit: Some(1),
+			..Default::default()
+		})?;
+		assert_eq!(found.len(), 1);
+
+		store.create_package(&mut package("gawk", "5.1.1-1"))?;
+		let mut found: Vec<String> = store
+			.find_packages(&PackageQuery {
+				names: Some(vec![String::from("grep"), String::from("gawk")]),
+				..Default::default()
+			})?
+			.into_iter()
+			.map(|package| package.name)
+			.collect();
+		found.sort();
+		assert_eq!(found, vec!["gawk", "grep"]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_unassigned_packages() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+
+		let mut grep = package("grep", "3.7-1");
+		store.create_package(&mut grep)?;
+		store.create_package(&mut package("sed", "4.8-1"))?;
+
+		grep.worker = Some(String::from("worker"));
+		store.update_package(&mut grep)?;
+
+		let found = store.find_packages(&PackageQuery {
+			unassigned: true,
+			..Default::default()
+		})?;
+		assert_eq!(found.len(), 1);
+		assert_eq!(found[0].name, "sed");
+		Ok(())
+	}
+
+	#[test]
+	fn test_update_conflict() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+
+		let mut first = package("grep", "3.7-1");
+		store.create_package(&mut first)?;
+		let mut second = store.find_packages(&PackageQuery::default())?.remove(0);
+
+		first.worker = Some(String::from("first"));
+		store.update_package(&mut first)?;
+		assert_eq!(first._rev.as_deref(), Some("2"));
+
+		second.worker = Some(String::from("second"));
+		assert!(store.update_package(&mut second).unwrap_err().is_conflict());
+
+		assert!(matches!(
+			store.update_package(&mut package("sed", "4.8-1")),
+			Err(Error::NotCreated(_))
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn test_create_packages() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+
+		let mut packages = vec![package("grep", "3.7-1"), package("sed", "4.8-1")];
+		packages[1].repo = Some(String::from("core"));
+		let results = store.create_packages(&mut packages)?;
+		assert!(results.iter().all(|result| result.is_ok()));
+		assert!(packages.iter().all(|package| package._id.is_some()));
+
+		// Creating the same documents again conflicts without losing the rest
+		let mut duplicate = package("grep", "3.7-1");
+		duplicate._id = packages[0]._id.clone();
+		let mut again = vec![duplicate, package("gawk", "5.1.1-1")];
+		let results = store.create_packages(&mut again)?;
+		assert!(results[0].as_ref().unwrap_err().is_conflict());
+		assert!(results[1].is_ok());
+
+		let found = store.find_packages(&PackageQuery {
+			repo: Some(String::from("core")),
+			..Default::default()
+		})?;
+		assert_eq!(found.len(), 1);
+		assert_eq!(found[0], packages[1]);
+		assert_eq!(store.find_packages(&PackageQuery::default())?.len(), 3);
+		Ok(())
+	}
+
+	#[test]
+	fn test_repositories() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+
+		let mut core = Repository::find(&store, PackageChannel::Pacman, "core", Some("x86_64"))?;
+		assert!(core._id.is_none());
+		core.etag = Some(String::from("\"abc\""));
+		core.update(&store)?;
+
+		let mut found = Repository::find(&store, PackageChannel::Pacman, "core", Some("x86_64"))?;
+		assert_eq!(found, core);
+		assert!(
+			Repository::find(&store, PackageChannel::Pacman, "core", None)?
+				._id
+				.is_none()
+		);
+		found.last_sync = Some(1);
+		found.update(&store)?;
+		assert!(core.update(&store).unwrap_err().is_conflict());
+
+		// A repository that isn't specific to an architecture is kept apart
+		let mut any = Repository::find(&store, PackageChannel::Pacman, "core", None)?;
+		any.update(&store)?;
+		assert_eq!(
+			Repository::find(&store, PackageChannel::Pacman, "core", None)?,
+			any
+		);
+		assert_eq!(
+			Repository::find(&store, PackageChannel::Pacman, "core", Some("x86_64"))?,
+			found
+		);
+		assert!(
+			Repository::find(&store, PackageChannel::Debian, "core", None)?
+				._id
+				.is_none()
+		);
+		Ok(())
+	}
+}
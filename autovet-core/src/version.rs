@@ -0,0 +1,549 @@
+//! Version ordering rules for each package channel.
+//!
+//! Every channel has its own idea of what makes one version newer than
+//! another, so versions are always compared in the context of a
+//! [`PackageChannel`].
+
+use crate::package::PackageChannel;
+use std::cmp::Ordering;
+
+/// A package version ordered by the rules of its channel. Versions from
+/// different channels, or that their channel can't order, are incomparable.
+#[derive(Debug, Clone, Copy)]
+pub struct Version<'a> {
+	channel: PackageChannel,
+
+	version: &'a str,
+}
+
+impl<'a> Version<'a> {
+	pub fn new(channel: PackageChannel, version: &'a str) -> Version<'a> {
+		Version { channel, version }
+	}
+
+	pub fn channel(&self) -> PackageChannel {
+		self.channel
+	}
+
+	pub fn as_str(&self) -> &'a str {
+		self.version
+	}
+}
+
+impl PartialEq for Version<'_> {
+	fn eq(&self, other: &Self) -> bool {
+		self.partial_cmp(other) == Some(Ordering::Equal)
+	}
+}
+
+impl PartialOrd for Version<'_> {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		if self.channel != other.channel {
+			return None;
+		}
+		compare(self.channel, self.version, other.version)
+	}
+}
+
+/// Compare two versions according to the rules of the given channel. Returns
+/// `None` if the channel has no known ordering or a version couldn't be
+/// parsed.
+pub fn compare(channel: PackageChannel, a: &str, b: &str) -> Option<Ordering> {
+	match channel {
+		PackageChannel::Pacman => Some(vercmp(a, b)),
+		PackageChannel::Debian => Some(dpkg_compare(a, b)),
+		PackageChannel::CratesIo | PackageChannel::Npm => semver_compare(a, b),
+		PackageChannel::PyPi => pep440_compare(a, b),
+		_ => None,
+	}
+}
+
+/// Sort items from the oldest version to the newest. Versions that the
+/// channel can't order come first in their original order, and versions that
+/// compare equal (e.g. `1.0` and `1.0-1` in pacman) are ordered by their text
+/// so the comparison is always a total order.
+pub fn sort<T, F>(channel: PackageChannel, items: &mut Vec<T>, version: F)
+where
+	F: Fn(&T) -> &str,
+{
+	let (mut orderable, unorderable): (Vec<T>, Vec<T>) = items
+		.drain(..)
+		.partition(|item| compare(channel, version(item), version(item)).is_some());
+
+	orderable.sort_by(|a, b| {
+		compare(channel, version(a), version(b))
+			.unwrap_or(Ordering::Equal)
+			.then_with(|| version(a).cmp(version(b)))
+	});
+
+	items.extend(unorderable);
+	items.extend(orderable);
+}
+
+/// Compare two pacman versions (`[epoch:]pkgver[-pkgrel]`) like alpm's
+/// `vercmp`.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+	if a == b {
+		return Ordering::Equal;
+	}
+
+	let (epoch_a, version_a, release_a) = parse_evr(a);
+	let (epoch_b, version_b, release_b) = parse_evr(b);
+
+	rpmvercmp(epoch_a, epoch_b)
+		.then_with(|| rpmvercmp(version_a, version_b))
+		.then_with(|| match (release_a, release_b) {
+			(Some(release_a), Some(release_b)) => rpmvercmp(release_a, release_b),
+			_ => Ordering::Equal,
+		})
+}
+
+/// Split a pacman version into epoch, version and release.
+fn parse_evr(evr: &str) -> (&str, &str, Option<&str>) {
+	let digits = evr.len() - evr.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+
+	let (epoch, rest) = match evr[digits..].strip_prefix(':') {
+		Some(rest) if digits == 0 => ("0", rest),
+		Some(rest) => (&evr[..digits], rest),
+		None => ("0", evr),
+	};
+
+	match rest.rfind('-') {
+		Some(i) => (epoch, &rest[..i], Some(&rest[i + 1..])),
+		None => (epoch, rest, None),
+	}
+}
+
+/// The segment comparison used by alpm (and rpm).
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+	if a == b {
+		return Ordering::Equal;
+	}
+
+	let (a, b) = (a.as_bytes(), b.as_bytes());
+	let (mut one, mut two) = (0, 0);
+
+	while one < a.len() && two < b.len() {
+		let (start_one, start_two) = (one, two);
+		while one < a.len() && !a[one].is_ascii_alphanumeric() {
+			one += 1;
+		}
+		while two < b.len() && !b[two].is_ascii_alphanumeric() {
+			two += 1;
+		}
+
+		if one == a.len() || two == b.len() {
+			break;
+		}
+
+		// More separators means a newer version
+		if one - start_one != two - start_two {
+			return (one - start_one).cmp(&(two - start_two));
+		}
+
+		let numeric = a[one].is_ascii_digit();
+		let segment = |s: &[u8], start: usize| -> usize {
+			let mut end = start;
+			while end < s.len()
+				&& if numeric {
+					s[end].is_ascii_digit()
+				} else {
+					s[end].is_ascii_alphabetic()
+				} {
+				end += 1;
+			}
+			end
+		};
+		let (end_one, end_two) = (segment(a, one), segment(b, two));
+
+		// Numeric segments are always newer than alpha segments
+		if two == end_two {
+			return if numeric {
+				Ordering::Greater
+			} else {
+				Ordering::Less
+			};
+		}
+
+		let (mut seg_one, mut seg_two) = (&a[one..end_one], &b[two..end_two]);
+		if numeric {
+			while seg_one.first() == Some(&b'0') {
+				seg_one = &seg_one[1..];
+			}
+			while seg_two.first() == Some(&b'0') {
+				seg_two = &seg_two[1..];
+			}
+
+			match seg_one.len().cmp(&seg_two.len()) {
+				Ordering::Equal => {}
+				ordering => return ordering,
+			}
+		}
+
+		match seg_one.cmp(seg_two) {
+			Ordering::Equal => {}
+			ordering => return ordering,
+		}
+
+		one = end_one;
+		two = end_two;
+	}
+
+	match (a.get(one), b.get(two)) {
+		(None, None) => Ordering::Equal,
+		// A remaining alpha segment never beats an empty string
+		(None, Some(c)) if !c.is_ascii_alphabetic() => Ordering::Less,
+		(Some(c), _) if c.is_ascii_alphabetic() => Ordering::Less,
+		_ => Ordering::Greater,
+	}
+}
+
+/// Compare two Debian versions (`[epoch:]upstream[-revision]`) like
+/// `dpkg --compare-versions`.
+pub fn dpkg_compare(a: &str, b: &str) -> Ordering {
+	let parse = |version: &str| -> (u64, String, String) {
+		let (epoch, rest) = match version.split_once(':') {
+			Some((epoch, rest)) => (epoch.parse().unwrap_or_default(), rest),
+			None => (0, version),
+		};
+		match rest.rsplit_once('-') {
+			Some((upstream, revision)) => (epoch, upstream.to_string(), revision.to_string()),
+			None => (epoch, rest.to_string(), String::new()),
+		}
+	};
+
+	let (epoch_a, upstream_a, revision_a) = parse(a);
+	let (epoch_b, upstream_b, revision_b) = parse(b);
+
+	epoch_a
+		.cmp(&epoch_b)
+		.then_with(|| verrevcmp(&upstream_a, &upstream_b))
+		.then_with(|| verrevcmp(&revision_a, &revision_b))
+}
+
+/// The weight of a non-digit character in a Debian version. A tilde sorts
+/// before anything, even the end of the string.
+fn dpkg_order(c: Option<&u8>) -> i32 {
+	match c {
+		None => 0,
+		Some(c) if c.is_ascii_digit() => 0,
+		Some(c) if c.is_ascii_alphabetic() => *c as i32,
+		Some(b'~') => -1,
+		Some(c) => *c as i32 + 256,
+	}
+}
+
+fn verrevcmp(a: &str, b: &str) -> Ordering {
+	let (a, b) = (a.as_bytes(), b.as_bytes());
+	let (mut i, mut j) = (0, 0);
+	let is_digit = |c: Option<&u8>| c.is_some_and(|c| c.is_ascii_digit());
+
+	while i < a.len() || j < b.len() {
+		while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+			let (ac, bc) = (dpkg_order(a.get(i)), dpkg_order(b.get(j)));
+			if ac != bc {
+				return ac.cmp(&bc);
+			}
+			i += 1;
+			j += 1;
+		}
+
+		while a.get(i) == Some(&b'0') {
+			i += 1;
+		}
+		while b.get(j) == Some(&b'0') {
+			j += 1;
+		}
+
+		let mut first_diff = Ordering::Equal;
+		while is_digit(a.get(i)) && is_digit(b.get(j)) {
+			if first_diff == Ordering::Equal {
+				first_diff = a[i].cmp(&b[j]);
+			}
+			i += 1;
+			j += 1;
+		}
+
+		if is_digit(a.get(i)) {
+			return Ordering::Greater;
+		}
+		if is_digit(b.get(j)) {
+			return Ordering::Less;
+		}
+		if first_diff != Ordering::Equal {
+			return first_diff;
+		}
+	}
+
+	Ordering::Equal
+}
+
+/// A segment of the local part of a PEP 440 version. Numeric segments sort
+/// after alphanumeric ones.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum LocalSegment {
+	Alphanumeric(String),
+	Numeric(u64),
+}
+
+/// The parts of a PEP 440 version that determine its order, arranged so the
+/// derived ordering matches the specification.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct Pep440 {
+	epoch: u64,
+
+	/// The release segments without trailing zeros
+	release: Vec<u64>,
+
+	/// The pre-release phase and number. Development releases of a final
+	/// release come before its alphas (phase 0), followed by alphas (1), betas
+	/// (2), release candidates (3) and everything else (4).
+	pre: (u8, u64),
+
+	post: Option<u64>,
+
+	/// Whether this isn't a development release, and its number if it is
+	dev: (bool, u64),
+
+	local: Vec<LocalSegment>,
+}
+
+impl Pep440 {
+	fn parse(version: &str) -> Option<Pep440> {
+		let version = version.trim().to_lowercase();
+		let version = version.strip_prefix('v').unwrap_or(&version);
+
+		let (public, local) = match version.split_once('+') {
+			Some((public, local)) => (public, Some(local)),
+			None => (version, None),
+		};
+		let (epoch, mut rest) = match public.split_once('!') {
+			Some((epoch, rest)) => (epoch.parse().ok()?, rest),
+			None => (0, public),
+		};
+
+		let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+
+		let mut release = Vec::new();
+		loop {
+			let end = digits(rest);
+			if end == 0 {
+				return None;
+			}
+			release.push(rest[..end].parse().ok()?);
+			rest = &rest[end..];
+
+			match rest.strip_prefix('.') {
+				Some(next) if digits(next) > 0 => rest = next,
+				_ => break,
+			}
+		}
+		while release.last() == Some(&0) {
+			release.pop();
+		}
+
+		let separators: &[char] = &['.', '-', '_'];
+		let (mut pre, mut post, mut dev) = (None, None, None);
+		loop {
+			rest = rest.trim_start_matches(separators);
+			if rest.is_empty() {
+				break;
+			}
+
+			let end = rest.len()
+				- rest
+					.trim_start_matches(|c: char| c.is_ascii_alphabetic())
+					.len();
+			let label = &rest[..end];
+			rest = rest[end..].trim_start_matches(separators);
+
+			let end = digits(rest);
+			let number = if end == 0 {
+				0
+			} else {
+				rest[..end].parse().ok()?
+			};
+			rest = &rest[end..];
+
+			match label {
+				"a" | "alpha" => pre = Some((1, number)),
+				"b" | "beta" => pre = Some((2, number)),
+				"rc" | "c" | "pre" | "preview" => pre = Some((3, number)),
+				"post" | "rev" | "r" => post = Some(number),
+				// An implicit post release like `1.0-1`
+				"" if end > 0 => post = Some(number),
+				"dev" => dev = Some(number),
+				_ => return None,
+			}
+		}
+
+		let pre = match (pre, post, dev) {
+			(Some(pre), _, _) => pre,
+			(None, None, Some(_)) => (0, 0),
+			_ => (4, 0),
+		};
+
+		let local = match local {
+			Some(local) => local
+				.split(separators)
+				.map(|segment| match segment.parse() {
+					Ok(number) => LocalSegment::Numeric(number),
+					Err(_) => LocalSegment::Alphanumeric(segment.to_string()),
+				})
+				.collect(),
+			None => Vec::new(),
+		};
+
+		Some(Pep440 {
+			epoch,
+			release,
+			pre,
+			post,
+			dev: match dev {
+				Some(dev) => (false, dev),
+				None => (true, 0),
+			},
+			local,
+		})
+	}
+}
+
+/// Compare two Python package versions as described by PEP 440.
+fn pep440_compare(a: &str, b: &str) -> Option<Ordering> {
+	Some(Pep440::parse(a)?.cmp(&Pep440::parse(b)?))
+}
+
+/// Compare two semantic versions.
+fn semver_compare(a: &str, b: &str) -> Option<Ordering> {
+	let a = semver::Version::parse(a.trim_start_matches('v')).ok()?;
+	let b = semver::Version::parse(b.trim_start_matches('v')).ok()?;
+	Some(a.cmp(&b))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_vercmp() {
+		// Cases from alpm's vercmptest.sh
+		for (a, b, expected) in [
+			("1.5.0", "1.5.0", Ordering::Equal),
+			("1.5.1", "1.5.0", Ordering::Greater),
+			("1.5.1", "1.5", Ordering::Greater),
+			("1.5.0-1", "1.5.0-2", Ordering::Less),
+			("1.5.0-1", "1.5.1-1", Ordering::Less),
+			("1.5.0-2", "1.5.1-1", Ordering::Less),
+			("1.5-1", "1.5", Ordering::Equal),
+			("1.1-1", "1.1", Ordering::Equal),
+			("1.0-1", "1.1", Ordering::Less),
+			("1.1-1", "1.0", Ordering::Greater),
+			("1.0a", "1.0b", Ordering::Less),
+			("1.0b", "1.0beta", Ordering::Less),
+			("1.0beta", "1.0p", Ordering::Less),
+			("1.0pre", "1.0rc", Ordering::Less),
+			("1.0rc", "1.0", Ordering::Less),
+			("1.0", "1.0.a", Ordering::Less),
+			("1.0.a", "1.0.1", Ordering::Less),
+			("1.0", "1.0.1", Ordering::Less),
+			("1.0a", "1.0.1", Ordering::Less),
+			("1.0", "1.0.", Ordering::Less),
+			("1.0.", "1.0.0", Ordering::Less),
+			("1..0", "1.0", Ordering::Greater),
+			("1:1.0", "1.0", Ordering::Greater),
+			("1:1.0", "2.0", Ordering::Greater),
+			("0:1.0", "1.0", Ordering::Equal),
+			("1:1.0-1", "1:1.0-2", Ordering::Less),
+			("3.7-1", "3.10-1", Ordering::Less),
+			("1.0.1", "1.0.01", Ordering::Equal),
+		] {
+			assert_eq!(vercmp(a, b), expected, "{} vs {}", a, b);
+			assert_eq!(vercmp(b, a), expected.reverse(), "{} vs {}", b, a);
+		}
+	}
+
+	#[test]
+	fn test_dpkg_compare() {
+		for (a, b, expected) in [
+			("1.0", "1.0", Ordering::Equal),
+			("1.0-1", "1.0-2", Ordering::Less),
+			("1.0~rc1", "1.0", Ordering::Less),
+			("1.0+b1", "1.0", Ordering::Greater),
+			("1:0.9", "2.0", Ordering::Greater),
+			("2.10", "2.9", Ordering::Greater),
+			("1.0a", "1.0", Ordering::Greater),
+		] {
+			assert_eq!(dpkg_compare(a, b), expected, "{} vs {}", a, b);
+			assert_eq!(dpkg_compare(b, a), expected.reverse(), "{} vs {}", b, a);
+		}
+	}
+
+	#[test]
+	fn test_compare() {
+		assert_eq!(
+			compare(PackageChannel::CratesIo, "1.0.0-alpha", "1.0.0"),
+			Some(Ordering::Less)
+		);
+		assert_eq!(compare(PackageChannel::Npm, "1.0", "1.0.0"), None);
+		assert_eq!(compare(PackageChannel::MavenCentral, "1.0", "2.0"), None);
+	}
+
+	#[test]
+	fn test_pep440_compare() {
+		for (a, b, expected) in [
+			("1.0", "1.0.0", Ordering::Equal),
+			("1.10", "1.9", Ordering::Greater),
+			("1.0.dev0", "1.0a1", Ordering::Less),
+			("1.0a1", "1.0b2", Ordering::Less),
+			("1.0b2", "1.0rc1", Ordering::Less),
+			("1.0rc1", "1.0", Ordering::Less),
+			("1.0a1.dev0", "1.0a1", Ordering::Less),
+			("1.0", "1.0.post1.dev0", Ordering::Less),
+			("1.0.post1.dev0", "1.0.post1", Ordering::Less),
+			("1.0-1", "1.0.post1", Ordering::Equal),
+			("1.0-alpha-1", "1.0a1", Ordering::Equal),
+			("1!0.1", "2.0", Ordering::Greater),
+			("1.0", "1.0+local", Ordering::Less),
+			("1.0+abc.5", "1.0+abc.10", Ordering::Less),
+			("1.0+abc", "1.0+5", Ordering::Less),
+		] {
+			assert_eq!(pep440_compare(a, b), Some(expected), "{} vs {}", a, b);
+			assert_eq!(
+				pep440_compare(b, a),
+				Some(expected.reverse()),
+				"{} vs {}",
+				b,
+				a
+			);
+		}
+		assert_eq!(pep440_compare("1.0*", "1.0"), None);
+		assert_eq!(pep440_compare("latest", "1.0"), None);
+	}
+
+	#[test]
+	fn test_sort() {
+		let mut versions = vec!["1.10.0", "latest", "1.2.0", "1.2", "0.9.0"];
+		sort(PackageChannel::CratesIo, &mut versions, |version| version);
+		assert_eq!(versions, vec!["latest", "1.2", "0.9.0", "1.2.0", "1.10.0"]);
+
+		let mut versions = vec!["1.5-2", "1.5", "1.5-1"];
+		sort(PackageChannel::Pacman, &mut versions, |version| version);
+		assert_eq!(versions, vec!["1.5", "1.5-1", "1.5-2"]);
+	}
+
+	#[test]
+	fn test_version() {
+		let pacman = |version| Version::new(PackageChannel::Pacman, version);
+		assert!(pacman("3.7-1") < pacman("3.10-1"));
+		assert!(pacman("1:1.0") > pacman("2.0"));
+		assert!(pacman("1.0.1") == pacman("1.0.01"));
+
+		// Different channels have no common ordering
+		let debian = Version::new(PackageChannel::Debian, "3.7-1");
+		assert_eq!(pacman("3.7-1").partial_cmp(&debian), None);
+		assert!(pacman("3.7-1") != debian);
+
+		let maven = Version::new(PackageChannel::MavenCentral, "1.0");
+		assert_eq!(maven.partial_cmp(&maven), None);
+	}
+}
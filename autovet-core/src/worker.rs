@@ -1,7 +1,72 @@
+use crate::error::Result;
+use crate::store::{AsyncStore, Store, WorkerQuery};
+use serde::{Deserialize, Serialize};
+use std::default::Default;
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
 pub struct Worker {
-	pub _id: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub _id: Option<String>,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub _rev: Option<String>,
 
 	pub hostname: String,
 
+	/// The last time the worker sent a heartbeat (seconds since the epoch)
 	pub last_checkin: u64,
 }
+
+impl Worker {
+	pub fn find(store: &dyn Store, query: &WorkerQuery) -> Result<Vec<Worker>> {
+		store.find_workers(query)
+	}
+
+	/// Record a heartbeat for this worker.
+	///
+	/// If the stored revision moved on, for example because the response to an
+	/// earlier heartbeat was lost, the heartbeat is sent again on top of the
+	/// current revision. A worker whose document was deleted as dead is
+	/// registered again under the same ID.
+	pub fn heartbeat(&mut self, store: &dyn Store) -> Result<()> {
+		self.last_checkin = crate::timestamp();
+		match self.update(store) {
+			Err(e) if e.is_conflict() => {
+				let query = WorkerQuery {
+					id: self._id.clone(),
+					..Default::default()
+				};
+				self._rev = Worker::find(store, &query)?
+					.into_iter()
+					.next()
+					.and_then(|worker| worker._rev);
+				self.update(store)
+			}
+			result => result,
+		}
+	}
+
+	pub fn update(&mut self, store: &dyn Store) -> Result<()> {
+		if self._rev.is_none() {
+			store.create_worker(self)
+		} else {
+			store.update_worker(self)
+		}
+	}
+
+	pub fn delete(&self, store: &dyn Store) -> Result<()> {
+		store.delete_worker(self)
+	}
+
+	pub async fn find_async(store: &dyn AsyncStore, query: &WorkerQuery) -> Result<Vec<Worker>> {
+		store.find_workers(query).await
+	}
+
+	pub async fn update_async(&mut self, store: &dyn AsyncStore) -> Result<()> {
+		if self._rev.is_none() {
+			store.create_worker(self).await
+		} else {
+			store.update_worker(self).await
+		}
+	}
+}
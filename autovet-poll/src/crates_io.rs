@@ -0,0 +1,368 @@
+use crate::{
+	fetch::{fetch, fetch_optional, ATTEMPTS},
+	sync::{current_packages, insert_new},
+};
+use autovet_core::{
+	config::CratesIoConfig,
+	error::{retry, Result},
+	package::{Package, PackageChannel},
+	store::{PackageQuery, Store},
+	version,
+};
+use log::{info, warn};
+use serde::Deserialize;
+use std::{collections::BTreeMap, io::Read};
+
+/// The default download location used when the index doesn't specify one.
+const DEFAULT_DL: &str = "https://static.crates.io/crates";
+
+/// The index's `config.json`.
+#[derive(Deserialize)]
+struct IndexConfig {
+	dl: String,
+}
+
+/// One line of an index file, describing a single published version.
+#[derive(Deserialize)]
+struct IndexEntry {
+	name: String,
+	vers: String,
+	#[serde(default)]
+	deps: Vec<Dependency>,
+	cksum: String,
+	#[serde(default)]
+	features: BTreeMap<String, Vec<String>>,
+	/// Features using newer syntax are kept apart for older versions of cargo
+	#[serde(default)]
+	features2: BTreeMap<String, Vec<String>>,
+	#[serde(default)]
+	yanked: bool,
+	links: Option<String>,
+	rust_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Dependency {
+	name: String,
+	req: String,
+	#[serde(default)]
+	optional: bool,
+	target: Option<String>,
+	kind: Option<String>,
+	/// The real name of the dependency if it was renamed
+	package: Option<String>,
+}
+
+impl Dependency {
+	fn describe(&self) -> String {
+		let mut description = format!(
+			"{} {}",
+			self.package.as_ref().unwrap_or(&self.name),
+			self.req
+		);
+		if let Some(target) = &self.target {
+			description.push_str(&format!(" ({})", target));
+		}
+		description
+	}
+}
+
+impl IndexEntry {
+	fn into_package(self, dl: &str) -> Package {
+		let mut package = Package {
+			channel: PackageChannel::CratesIo,
+			url: Some(download_url(dl, &self.name, &self.vers, &self.cksum)),
+			name: self.name,
+			version: self.vers,
+			sha256sum: Some(self.cksum),
+			features: self.features,
+			yanked: self.yanked,
+			..Default::default()
+		};
+		package.features.extend(self.features2);
+
+		for dependency in self.deps {
+			let list = match (dependency.kind.as_deref(), dependency.optional) {
+				(Some("dev"), _) => &mut package.checkdepends,
+				(Some("build"), _) => &mut package.makedepends,
+				(_, true) => &mut package.optdepends,
+				_ => &mut package.depends,
+			};
+			list.push(dependency.describe());
+		}
+
+		if let Some(links) = self.links {
+			package.extra.insert(String::from("links"), vec![links]);
+		}
+		if let Some(rust_version) = self.rust_version {
+			package
+				.extra
+				.insert(String::from("rust_version"), vec![rust_version]);
+		}
+		package
+	}
+}
+
+/// Whether the given string is a valid crate name. Names of crates published
+/// to crates.io are limited to ASCII letters, digits, `-` and `_`.
+fn is_valid_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The directory holding a crate's file within the index, keeping the case of
+/// the given name (e.g. `Se/rd` for `Serde`).
+fn prefix(name: &str) -> String {
+	let chars: Vec<char> = name.chars().collect();
+	match chars.len() {
+		0 => String::new(),
+		1 => String::from("1"),
+		2 => String::from("2"),
+		3 => format!("3/{}", chars[0]),
+		_ => format!(
+			"{}/{}",
+			chars[..2].iter().collect::<String>(),
+			chars[2..4].iter().collect::<String>()
+		),
+	}
+}
+
+/// The path of a crate's file within the index.
+fn index_path(name: &str) -> String {
+	let name = name.to_lowercase();
+	format!("{}/{}", prefix(&name), name)
+}
+
+/// Expand the index's download template for the given version.
+fn download_url(dl: &str, name: &str, version: &str, cksum: &str) -> String {
+	let markers = [
+		"{crate}",
+		"{version}",
+		"{prefix}",
+		"{lowerprefix}",
+		"{sha256-checksum}",
+	];
+	if !markers.iter().any(|marker| dl.contains(marker)) {
+		return format!("{}/{}/{}/download", dl.trim_end_matches('/'), name, version);
+	}
+
+	let prefix = prefix(name);
+	dl.replace("{crate}", name)
+		.replace("{version}", version)
+		.replace("{prefix}", &prefix)
+		.replace("{lowerprefix}", &prefix.to_lowercase())
+		.replace("{sha256-checksum}", cksum)
+}
+
+/// Read the download template from the index configuration.
+fn fetch_dl(index: &str) -> Result<String> {
+	let url = format!("{}/config.json", index);
+	match retry(ATTEMPTS, || fetch_optional(&url))? {
+		Some(body) => Ok(serde_json::from_reader::<_, IndexConfig>(body)?.dl),
+		None => Ok(String::from(DEFAULT_DL)),
+	}
+}
+
+/// Record that a version which is already in the store was yanked (or
+/// restored).
+fn update_yanked(store: &dyn Store, name: &str, version: &str, yanked: bool) -> Result<()> {
+	let query = PackageQuery {
+		channel: Some(PackageChannel::CratesIo),
+		name: Some(name.to_string()),
+		version: Some(version.to_string()),
+		..Default::default()
+	};
+
+	for mut package in retry(ATTEMPTS, || Package::find(store, &query))? {
+		package.yanked = yanked;
+		match retry(ATTEMPTS, || package.update(store)) {
+			// The package changed under us, so the next sync will try again
+			Err(e) if e.is_conflict() => {}
+			result => result?,
+		}
+	}
+	Ok(())
+}
+
+/// Synchronize the versions of the configured crates from the index.
+pub fn sync(store: &dyn Store, config: &CratesIoConfig) -> Result<()> {
+	let mut current_packages = current_packages(store, PackageChannel::CratesIo)?;
+	let index = config.index.trim_end_matches('/');
+	let dl = fetch_dl(index)?;
+
+	for name in &config.crates {
+		if !is_valid_name(name) {
+			warn!("Skipping invalid crate name: {:?}", name);
+			continue;
+		}
+
+		let url = format!("{}/{}", index, index_path(name));
+		let mut content = String::new();
+		if let Err(e) = retry(ATTEMPTS, || fetch(&url)).and_then(|mut body| {
+			body.read_to_string(&mut content)?;
+			Ok(())
+		}) {
+			warn!("Failed to fetch crate {}: {}", name, e);
+			continue;
+		}
+
+		let mut entries: Vec<IndexEntry> = Vec::new();
+		for line in content.lines().filter(|line| !line.trim().is_empty()) {
+			match serde_json::from_str(line) {
+				Ok(entry) => entries.push(entry),
+				Err(e) => warn!("Skipping index entry for {}: {}", name, e),
+			}
+		}
+
+		// Versions are listed in publication order, which isn't version order
+		// once fixes are backported to older releases
+		version::sort(PackageChannel::CratesIo, &mut entries, |entry| &entry.vers);
+
+		let (mut new, mut yanked) = (0, 0);
+		for entry in entries {
+			let known = current_packages
+				.iter_mut()
+				.find(|p| p.name == entry.name && p.version == entry.vers);
+			match known {
+				Some(known) if known.yanked != entry.yanked => {
+					info!(
+						"{} {} was {}",
+						entry.name,
+						entry.vers,
+						if entry.yanked { "yanked" } else { "unyanked" }
+					);
+					update_yanked(store, &entry.name, &entry.vers, entry.yanked)?;
+					known.yanked = entry.yanked;
+					yanked += 1;
+				}
+				Some(_) => {}
+				None => {
+					if insert_new(store, &mut current_packages, entry.into_package(&dl))? {
+						new += 1;
+					}
+				}
+			}
+		}
+		info!(
+			"Found {} new versions and {} yank changes for {}",
+			new, yanked, name
+		);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+	use std::{fs, path::Path};
+
+	fn write_index(root: &Path, yanked: bool) -> Result<()> {
+		fs::create_dir_all(root.join("se/rd"))?;
+		// Listed out of version order, as happens when fixes are backported
+		let entries = [
+			json!({
+				"name": "serde-demo",
+				"vers": "1.0.1",
+				"deps": [],
+				"cksum": "5e1a7b2b3d2f3c6f0b5e5f2d1c4a7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d",
+				"features": {},
+				"yanked": false
+			}),
+			json!({
+				"name": "serde-demo",
+				"vers": "1.0.0",
+				"deps": [
+					{"name": "serde", "req": "^1", "features": [], "optional": false, "default_features": true, "target": null, "kind": "normal"},
+					{"name": "json", "req": "^1", "features": [], "optional": true, "default_features": true, "target": null, "kind": "normal", "package": "serde_json"},
+					{"name": "cc", "req": "^1", "features": [], "optional": false, "default_features": true, "target": null, "kind": "build"}
+				],
+				"cksum": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+				"features": {"default": ["std"], "std": []},
+				"features2": {"json": ["dep:json"]},
+				"yanked": yanked
+			}),
+		];
+		fs::write(
+			root.join("se/rd/serde-demo"),
+			entries.map(|entry| entry.to_string()).join("\n"),
+		)?;
+		fs::write(
+			root.join("config.json"),
+			json!({"dl": "https://static.example/{crate}/{crate}-{version}.crate"}).to_string(),
+		)?;
+		Ok(())
+	}
+
+	#[test]
+	fn test_sync_local_index() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		let config = CratesIoConfig {
+			index: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			crates: vec![String::from("serde-demo")],
+		};
+		let store = autovet_core::store::sqlite::SqliteStore::open_in_memory()?;
+
+		write_index(root.path(), false)?;
+		sync(&store, &config)?;
+
+		let mut packages = Package::find(&store, &PackageQuery::default())?;
+		packages.sort_by(|a, b| a.version.cmp(&b.version));
+		assert_eq!(packages.len(), 2);
+		assert_eq!(
+			packages[0].url.as_deref(),
+			Some("https://static.example/serde-demo/serde-demo-1.0.0.crate")
+		);
+		assert_eq!(packages[0].depends, vec!["serde ^1"]);
+		assert_eq!(packages[0].optdepends, vec!["serde_json ^1"]);
+		assert_eq!(packages[0].makedepends, vec!["cc ^1"]);
+		assert_eq!(packages[0].features["json"], vec!["dep:json"]);
+		assert_eq!(packages[1].supersedes, packages[0]._id);
+		assert!(!packages[0].yanked);
+
+		// Yank the first version
+		write_index(root.path(), true)?;
+		sync(&store, &config)?;
+
+		let yanked = Package::find(
+			&store,
+			&PackageQuery {
+				version: Some(String::from("1.0.0")),
+				..Default::default()
+			},
+		)?;
+		assert!(yanked[0].yanked);
+		assert_eq!(Package::find(&store, &PackageQuery::default())?.len(), 2);
+		Ok(())
+	}
+
+	#[test]
+	fn test_index_path() {
+		assert_eq!(index_path("a"), "1/a");
+		assert_eq!(index_path("ab"), "2/ab");
+		assert_eq!(index_path("abc"), "3/a/abc");
+		assert_eq!(index_path("Serde"), "se/rd/serde");
+		assert_eq!(index_path("é"), "1/é");
+	}
+
+	#[test]
+	fn test_download_url() {
+		let dl = "https://dl.example/{prefix}/{lowerprefix}/{crate}/{version}";
+		assert_eq!(
+			download_url(dl, "Serde", "1.0.0", ""),
+			"https://dl.example/Se/rd/se/rd/Serde/1.0.0"
+		);
+		assert_eq!(
+			download_url(dl, "ab", "1.0.0", ""),
+			"https://dl.example/2/2/ab/1.0.0"
+		);
+		assert!(is_valid_name("serde_json-2"));
+		assert!(!is_valid_name(""));
+		assert!(!is_valid_name("../serde"));
+	}
+}
@@ -0,0 +1,111 @@
+//! Running the pollers repeatedly, each channel on its own schedule.
+
+use autovet_core::error::Result;
+use log::{error, info};
+use std::{
+	sync::mpsc::{Receiver, RecvTimeoutError},
+	time::{Duration, Instant},
+};
+
+/// A synchronization that runs at a regular interval.
+pub struct Job<'a> {
+	pub name: &'static str,
+
+	pub interval: Duration,
+
+	/// When the job runs next
+	pub next: Instant,
+
+	pub sync: Box<dyn FnMut() -> Result<()> + 'a>,
+}
+
+/// The time until a job runs again: its interval plus a random part of the
+/// jitter.
+fn delay(interval: Duration, jitter: Duration) -> Duration {
+	interval + jitter.mul_f64(rand::random::<f64>())
+}
+
+/// Run each job whenever it's due until shutdown is requested through the
+/// given channel. Jobs that fail are tried again at their next scheduled time.
+/// A synchronization in progress is never interrupted; shutdown requests are
+/// noticed as soon as it finishes.
+pub fn run(jobs: &mut [Job], jitter: Duration, shutdown: &Receiver<()>) {
+	while let Some(job) = jobs.iter_mut().min_by_key(|job| job.next) {
+		match shutdown.recv_timeout(job.next.saturating_duration_since(Instant::now())) {
+			Err(RecvTimeoutError::Timeout) => {}
+			Ok(()) | Err(RecvTimeoutError::Disconnected) => {
+				info!("Shutting down");
+				return;
+			}
+		}
+
+		info!("Synchronizing {}", job.name);
+		match (job.sync)() {
+			Ok(()) => info!("Synchronized {}", job.name),
+			Err(e) => error!("Failed to synchronize {}: {}", job.name, e),
+		}
+
+		job.next = Instant::now() + delay(job.interval, jitter);
+		info!(
+			"Next synchronization of {} in {}s",
+			job.name,
+			job.next.saturating_duration_since(Instant::now()).as_secs()
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::error::Error;
+	use std::{cell::Cell, sync::mpsc};
+
+	#[test]
+	fn test_delay() {
+		let interval = Duration::from_secs(60);
+		for _ in 0..100 {
+			let delay = delay(interval, Duration::from_secs(10));
+			assert!(delay >= interval && delay <= Duration::from_secs(70));
+		}
+		assert_eq!(delay(interval, Duration::ZERO), interval);
+	}
+
+	#[test]
+	fn test_run() {
+		let (fast, slow) = (Cell::new(0), Cell::new(0));
+		let now = Instant::now();
+		let mut jobs = [
+			Job {
+				name: "fast",
+				interval: Duration::from_millis(10),
+				next: now,
+				sync: Box::new(|| {
+					fast.set(fast.get() + 1);
+					Err(Error::Parse(String::from(
+						"failures don't stop the schedule",
+					)))
+				}),
+			},
+			Job {
+				name: "slow",
+				interval: Duration::from_secs(3600),
+				next: now,
+				sync: Box::new(|| {
+					slow.set(slow.get() + 1);
+					Ok(())
+				}),
+			},
+		];
+
+		let (sender, receiver) = mpsc::channel();
+		let shutdown = std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(200));
+			sender.send(()).unwrap();
+		});
+		run(&mut jobs, Duration::ZERO, &receiver);
+		shutdown.join().unwrap();
+
+		assert!(fast.get() > 2);
+		assert_eq!(slow.get(), 1);
+	}
+}
@@ -0,0 +1,245 @@
+use crate::{
+	fetch::{fetch_bytes, fetch_optional, ATTEMPTS},
+	sync::{current_packages, insert_new},
+};
+use autovet_core::{
+	config::DebianConfig,
+	error::{retry, Error, Result},
+	package::{parse_stanza, Package, PackageChannel},
+	store::Store,
+};
+use flate2::read::GzDecoder;
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use xz2::read::XzDecoder;
+
+/// An index file listed in a suite's Release file.
+#[derive(Debug, PartialEq, Eq)]
+struct IndexFile {
+	/// The path relative to the suite directory
+	path: String,
+
+	size: u64,
+
+	sha256: String,
+}
+
+/// Parse the SHA256 section of a Release file.
+fn parse_release(release: &str) -> Result<Vec<IndexFile>> {
+	let mut files = Vec::new();
+
+	for (field, value) in parse_stanza(release)? {
+		if field != "SHA256" {
+			continue;
+		}
+
+		for line in value.lines().filter(|line| !line.is_empty()) {
+			match line.split_whitespace().collect::<Vec<&str>>()[..] {
+				[sha256, size, path] => files.push(IndexFile {
+					path: path.to_string(),
+					size: size
+						.parse()
+						.map_err(|_| Error::Parse(format!("Invalid size in Release: {}", line)))?,
+					sha256: sha256.to_string(),
+				}),
+				_ => return Err(Error::Parse(format!("Invalid Release entry: {}", line))),
+			}
+		}
+	}
+
+	Ok(files)
+}
+
+/// Remove the OpenPGP cleartext signature framing from an InRelease file.
+/// The signature itself isn't checked here.
+fn strip_clearsign(content: &str) -> &str {
+	let body = match content.split_once("-----BEGIN PGP SIGNED MESSAGE-----") {
+		// The armor headers end with an empty line
+		Some((_, rest)) => match rest.split_once("\n\n") {
+			Some((_, body)) => body,
+			None => rest,
+		},
+		None => return content,
+	};
+
+	match body.split_once("-----BEGIN PGP SIGNATURE-----") {
+		Some((body, _)) => body,
+		None => body,
+	}
+}
+
+/// Download the index of the given suite, preferring the inline signed
+/// InRelease file.
+fn fetch_release(base: &str) -> Result<Vec<IndexFile>> {
+	for name in ["InRelease", "Release"] {
+		let url = format!("{}/{}", base, name);
+		if let Some(mut body) = retry(ATTEMPTS, || fetch_optional(&url))? {
+			let mut content = String::new();
+			body.read_to_string(&mut content)?;
+			return parse_release(strip_clearsign(&content));
+		}
+	}
+
+	Err(Error::Parse(format!("No Release file found in {}", base)))
+}
+
+/// Download a Packages index, check it against the Release file and
+/// decompress it.
+fn fetch_packages(base: &str, files: &[IndexFile], prefix: &str) -> Result<Option<String>> {
+	// Prefer the smallest encoding
+	let file = match ["xz", "gz", ""].iter().find_map(|extension| {
+		let path = match *extension {
+			"" => format!("{}/Packages", prefix),
+			extension => format!("{}/Packages.{}", prefix, extension),
+		};
+		files.iter().find(|file| file.path == path)
+	}) {
+		Some(file) => file,
+		None => return Ok(None),
+	};
+
+	let url = format!("{}/{}", base, file.path);
+	let content = retry(ATTEMPTS, || fetch_bytes(&url))?;
+
+	let sha256 = hex::encode(Sha256::digest(&content));
+	if content.len() as u64 != file.size || sha256 != file.sha256 {
+		return Err(Error::Parse(format!(
+			"{} doesn't match the Release file (expected {}, got {})",
+			url, file.sha256, sha256
+		)));
+	}
+
+	let mut packages = String::new();
+	if file.path.ends_with(".xz") {
+		XzDecoder::new(&content[..]).read_to_string(&mut packages)?;
+	} else if file.path.ends_with(".gz") {
+		GzDecoder::new(&content[..]).read_to_string(&mut packages)?;
+	} else {
+		packages = String::from_utf8_lossy(&content).into_owned();
+	}
+	Ok(Some(packages))
+}
+
+/// Synchronize package metadata from the configured Debian suites.
+pub fn sync(store: &dyn Store, config: &DebianConfig) -> Result<()> {
+	let mut current_packages = current_packages(store, PackageChannel::Debian)?;
+
+	for suite in &config.suites {
+		let base = format!("{}/dists/{}", config.mirror.trim_end_matches('/'), suite);
+		let files = fetch_release(&base)?;
+
+		for component in &config.components {
+			for arch in &config.architectures {
+				let prefix = format!("{}/binary-{}", component, arch);
+				let packages = match fetch_packages(&base, &files, &prefix)? {
+					Some(packages) => packages,
+					None => {
+						warn!("No Packages index for {}/{}", suite, prefix);
+						continue;
+					}
+				};
+
+				let mut count = 0;
+				for stanza in packages.split("\n\n").filter(|s| !s.trim().is_empty()) {
+					match Package::from_debian_stanza(stanza) {
+						Ok(package) => {
+							if insert_new(store, &mut current_packages, package)? {
+								count += 1;
+							}
+						}
+						Err(e) => warn!("Skipping package in {}/{}: {}", suite, prefix, e),
+					}
+				}
+				info!("Found {} new packages in {}/{}", count, suite, prefix);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::store::{sqlite::SqliteStore, PackageQuery};
+	use flate2::{write::GzEncoder, Compression};
+	use std::{fs, io::Write, path::Path};
+
+	const PACKAGES: &str = "Package: grep
+Version: 3.8-5
+Architecture: amd64
+Maintainer: Anibal Monsalve Salazar <anibal@debian.org>
+Installed-Size: 1012
+Depends: libc6 (>= 2.34)
+Filename: pool/main/g/grep/grep_3.8-5_amd64.deb
+Size: 294576
+SHA256: 4b5b0dd6b7e55e40bf4bd8e0ee6b4a3b5d0dc51c6e3e8dcd7b0a3b7df8e6d1d7
+
+Package: sed
+Version: 4.9-1
+Architecture: amd64
+Filename: pool/main/s/sed/sed_4.9-1_amd64.deb
+SHA256: 5e1a7b2b3d2f3c6f0b5e5f2d1c4a7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d
+";
+
+	/// Lay out a minimal suite in the given directory.
+	fn write_suite(root: &Path) -> Result<()> {
+		let binary = root.join("dists/stable/main/binary-amd64");
+		fs::create_dir_all(&binary)?;
+
+		let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(PACKAGES.as_bytes())?;
+		let packages = encoder.finish()?;
+		fs::write(binary.join("Packages.gz"), &packages)?;
+
+		fs::write(
+			root.join("dists/stable/InRelease"),
+			format!(
+				"-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA256\n\nSuite: stable\nSHA256:\n {} {} main/binary-amd64/Packages.gz\n-----BEGIN PGP SIGNATURE-----\n\n-----END PGP SIGNATURE-----\n",
+				hex::encode(Sha256::digest(&packages)),
+				packages.len()
+			),
+		)?;
+		Ok(())
+	}
+
+	#[test]
+	fn test_sync_local_mirror() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		write_suite(root.path())?;
+
+		let config = DebianConfig {
+			mirror: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			suites: vec![String::from("stable")],
+			..Default::default()
+		};
+
+		let store = SqliteStore::open_in_memory()?;
+		sync(&store, &config)?;
+		sync(&store, &config)?;
+
+		let packages = Package::find(&store, &PackageQuery::default())?;
+		assert_eq!(packages.len(), 2);
+		assert!(packages.iter().all(|p| p.channel == PackageChannel::Debian));
+		Ok(())
+	}
+
+	#[test]
+	fn test_strip_clearsign() -> Result<()> {
+		let files = parse_release(strip_clearsign(
+			"-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA512\n\nOrigin: Debian\nSHA256:\n abc 10 main/binary-amd64/Packages.xz\n-----BEGIN PGP SIGNATURE-----\n\nxyz\n-----END PGP SIGNATURE-----\n",
+		))?;
+		assert_eq!(
+			files,
+			vec![IndexFile {
+				path: String::from("main/binary-amd64/Packages.xz"),
+				size: 10,
+				sha256: String::from("abc"),
+			}]
+		);
+		Ok(())
+	}
+}
@@ -0,0 +1,154 @@
+//! Downloading repository indices.
+//!
+//! Besides `http(s)://`, `file://` URLs are read from the local filesystem so
+//! pollers can run against a local copy of a repository.
+
+use autovet_core::error::{Error, Result};
+use reqwest::{
+	header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+	StatusCode, Url,
+};
+use std::{
+	fs::{self, File},
+	io::Read,
+	path::PathBuf,
+	time::UNIX_EPOCH,
+};
+
+/// The number of attempts made for operations that fail transiently.
+pub const ATTEMPTS: u32 = 3;
+
+/// The cache validators of a downloaded document, which let the server skip
+/// sending it again if it hasn't changed.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Validators {
+	pub etag: Option<String>,
+
+	pub last_modified: Option<String>,
+}
+
+fn file_path(url: &str) -> Result<PathBuf> {
+	match Url::parse(url).map(|url| url.to_file_path()) {
+		Ok(Ok(path)) => Ok(path),
+		_ => Err(Error::Config(format!("Invalid file URL: {}", url))),
+	}
+}
+
+/// Open the given URL, treating any unsuccessful status as an error.
+pub fn fetch(url: &str) -> Result<Box<dyn Read>> {
+	if url.starts_with("file://") {
+		return Ok(Box::new(File::open(file_path(url)?)?));
+	}
+
+	let rs = reqwest::blocking::get(url)?;
+	if rs.status().is_success() {
+		Ok(Box::new(rs))
+	} else {
+		Err(Error::Status {
+			status: rs.status(),
+			url: url.to_string(),
+		})
+	}
+}
+
+/// Open the given URL, returning `None` if it doesn't exist.
+pub fn fetch_optional(url: &str) -> Result<Option<Box<dyn Read>>> {
+	match fetch(url) {
+		Ok(body) => Ok(Some(body)),
+		Err(Error::Status {
+			status: StatusCode::NOT_FOUND,
+			..
+		}) => Ok(None),
+		Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(e),
+	}
+}
+
+/// Download the entire content of the given URL.
+pub fn fetch_bytes(url: &str) -> Result<Vec<u8>> {
+	let mut content = Vec::new();
+	fetch(url)?.read_to_end(&mut content)?;
+	Ok(content)
+}
+
+/// Download the entire content of the given URL unless it's unchanged since
+/// the given validators were recorded. Local files get an entity tag derived
+/// from their size and modification time, and a `Last-Modified` date.
+pub fn fetch_if_modified(
+	url: &str,
+	validators: &Validators,
+) -> Result<Option<(Vec<u8>, Validators)>> {
+	if url.starts_with("file://") {
+		let path = file_path(url)?;
+		let metadata = fs::metadata(&path)?;
+		let modified = metadata
+			.modified()?
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or_default();
+		let etag = format!("\"{:x}-{:x}\"", metadata.len(), modified.as_nanos());
+
+		if validators.etag.as_deref() == Some(etag.as_str()) {
+			return Ok(None);
+		}
+		let current = Validators {
+			etag: Some(etag),
+			last_modified: Some(httpdate::fmt_http_date(metadata.modified()?)),
+		};
+		return Ok(Some((fs::read(path)?, current)));
+	}
+
+	let mut request = reqwest::blocking::Client::new().get(url);
+	if let Some(etag) = &validators.etag {
+		request = request.header(IF_NONE_MATCH, etag);
+	}
+	if let Some(last_modified) = &validators.last_modified {
+		request = request.header(IF_MODIFIED_SINCE, last_modified);
+	}
+
+	let mut rs = request.send()?;
+	match rs.status() {
+		StatusCode::NOT_MODIFIED => Ok(None),
+		status if status.is_success() => {
+			let header = |name| {
+				rs.headers()
+					.get(name)
+					.and_then(|value| value.to_str().ok())
+					.map(String::from)
+			};
+			let current = Validators {
+				etag: header(ETAG),
+				last_modified: header(LAST_MODIFIED),
+			};
+
+			let mut content = Vec::new();
+			rs.read_to_end(&mut content)?;
+			Ok(Some((content, current)))
+		}
+		status => Err(Error::Status {
+			status,
+			url: url.to_string(),
+		}),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_fetch_if_modified() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		let path = root.path().join("core.db.tar.gz");
+		fs::write(&path, b"first")?;
+		let url = Url::from_file_path(&path).unwrap().to_string();
+
+		let (content, validators) = fetch_if_modified(&url, &Validators::default())?.unwrap();
+		assert_eq!(content, b"first");
+		assert!(fetch_if_modified(&url, &validators)?.is_none());
+
+		fs::write(&path, b"second")?;
+		let (content, _) = fetch_if_modified(&url, &validators)?.unwrap();
+		assert_eq!(content, b"second");
+		Ok(())
+	}
+}
@@ -1,7 +1,217 @@
+pub mod crates_io;
+pub mod daemon;
+pub mod debian;
+pub mod fetch;
+pub mod maven;
+pub mod mirrors;
+pub mod npm;
+pub mod oci;
 pub mod pacman;
-use std::error::Error;
+pub mod pypi;
+pub mod rollback;
+pub mod sync;
+use autovet_core::{config::Config, package::PackageChannel, store::Store};
+use clap::Parser;
+use log::info;
+use std::{
+	error::Error,
+	path::PathBuf,
+	time::{Duration, Instant},
+};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct CommandLine {
+	/// The configuration file to use
+	#[clap(long)]
+	config: Option<PathBuf>,
+
+	/// The store URL
+	#[clap(long)]
+	store: Option<String>,
+
+	/// The pacman mirror URL
+	#[clap(long)]
+	mirror: Option<String>,
+
+	/// A pacman repository to synchronize (may be given more than once)
+	#[clap(long = "repo")]
+	repos: Vec<String>,
+
+	/// An architecture to synchronize pacman repositories for (may be given
+	/// more than once)
+	#[clap(long = "arch")]
+	architectures: Vec<String>,
+
+	/// A Debian suite to synchronize (may be given more than once)
+	#[clap(long = "suite")]
+	suites: Vec<String>,
+
+	/// A PyPI project to follow (may be given more than once)
+	#[clap(long = "pypi-project")]
+	pypi_projects: Vec<String>,
+
+	/// An npm package to follow (may be given more than once)
+	#[clap(long = "npm-package")]
+	npm_packages: Vec<String>,
+
+	/// A crate to follow on crates.io (may be given more than once)
+	#[clap(long = "crate")]
+	crates: Vec<String>,
+
+	/// A groupId:artifactId to follow on Maven Central (may be given more
+	/// than once)
+	#[clap(long = "maven-artifact")]
+	maven_artifacts: Vec<String>,
+
+	/// A container image to follow, either a registry reference or an OCI
+	/// layout as `oci:<path>[:<tag>]` (may be given more than once)
+	#[clap(long = "image")]
+	images: Vec<String>,
+
+	/// Keep running and synchronize each channel at its configured interval
+	#[clap(long)]
+	daemon: bool,
+}
+
+type SyncFn = fn(&dyn Store, &Config) -> autovet_core::error::Result<()>;
+
+/// The channels that are configured to be synchronized, named after their
+/// configuration sections.
+fn channels(config: &Config) -> Vec<(&'static str, PackageChannel, SyncFn)> {
+	let mut channels: Vec<(&'static str, PackageChannel, SyncFn)> = Vec::new();
+	if !config.pacman.repos.is_empty() {
+		channels.push(("pacman", PackageChannel::Pacman, |store, config| {
+			crate::pacman::sync(store, &config.pacman)
+		}));
+	}
+	if !config.debian.suites.is_empty() {
+		channels.push(("debian", PackageChannel::Debian, |store, config| {
+			crate::debian::sync(store, &config.debian)
+		}));
+	}
+	if !config.pypi.projects.is_empty() {
+		channels.push(("pypi", PackageChannel::PyPi, |store, config| {
+			crate::pypi::sync(store, &config.pypi)
+		}));
+	}
+	if !config.npm.packages.is_empty() {
+		channels.push(("npm", PackageChannel::Npm, |store, config| {
+			crate::npm::sync(store, &config.npm)
+		}));
+	}
+	if !config.crates_io.crates.is_empty() {
+		channels.push(("crates_io", PackageChannel::CratesIo, |store, config| {
+			crate::crates_io::sync(store, &config.crates_io)
+		}));
+	}
+	if !config.maven.artifacts.is_empty() {
+		channels.push(("maven", PackageChannel::MavenCentral, |store, config| {
+			crate::maven::sync(store, &config.maven)
+		}));
+	}
+	if !config.oci.images.is_empty() {
+		channels.push(("oci", PackageChannel::DockerHub, |store, config| {
+			crate::oci::sync(store, &config.oci)
+		}));
+	}
+	channels
+}
+
+/// Synchronize every channel on its own schedule until the process is
+/// interrupted or terminated. Channels that were synchronized recently (by
+/// this or another poller) wait for the rest of their interval first.
+fn run_daemon(store: &dyn Store, config: &Config) -> autovet_core::error::Result<()> {
+	let (sender, shutdown) = std::sync::mpsc::channel();
+	if let Err(e) = ctrlc::set_handler(move || {
+		let _ = sender.send(());
+	}) {
+		return Err(autovet_core::error::Error::Config(format!(
+			"Failed to install signal handler: {}",
+			e
+		)));
+	}
+
+	let now = autovet_core::timestamp();
+	let mut jobs = Vec::new();
+	for (name, channel, sync) in channels(config) {
+		let interval = config.poll.interval(name);
+		let elapsed = match crate::sync::last_sync(store, channel)? {
+			Some(last_sync) => now.saturating_sub(last_sync),
+			None => interval,
+		};
+		let wait = Duration::from_secs(interval.saturating_sub(elapsed));
+		info!(
+			"Synchronizing {} every {}s, next in {}s",
+			name,
+			interval,
+			wait.as_secs()
+		);
+
+		jobs.push(daemon::Job {
+			name,
+			interval: Duration::from_secs(interval),
+			next: Instant::now() + wait,
+			sync: Box::new(move || {
+				sync(store, config)?;
+				crate::sync::record_sync(store, channel)
+			}),
+		});
+	}
+
+	daemon::run(
+		&mut jobs,
+		Duration::from_secs(config.poll.jitter),
+		&shutdown,
+	);
+	Ok(())
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
+	let command_line = CommandLine::parse();
 	env_logger::init_from_env(env_logger::Env::new());
-	crate::pacman::sync()
+
+	let mut config = Config::load(command_line.config.as_deref())?;
+	if let Some(store) = command_line.store {
+		config.store.url = store;
+	}
+	if let Some(mirror) = command_line.mirror {
+		config.pacman.mirror = mirror;
+	}
+	if !command_line.repos.is_empty() {
+		config.pacman.repos = command_line.repos;
+	}
+	if !command_line.architectures.is_empty() {
+		config.pacman.architectures = command_line.architectures;
+	}
+	if !command_line.suites.is_empty() {
+		config.debian.suites = command_line.suites;
+	}
+	if !command_line.pypi_projects.is_empty() {
+		config.pypi.projects = command_line.pypi_projects;
+	}
+	if !command_line.npm_packages.is_empty() {
+		config.npm.packages = command_line.npm_packages;
+	}
+	if !command_line.crates.is_empty() {
+		config.crates_io.crates = command_line.crates;
+	}
+	if !command_line.maven_artifacts.is_empty() {
+		config.maven.artifacts = command_line.maven_artifacts;
+	}
+	if !command_line.images.is_empty() {
+		config.oci.images = command_line.images;
+	}
+	config.validate()?;
+
+	let store = autovet_core::store::open(&config.store)?;
+	if command_line.daemon {
+		return Ok(run_daemon(store.as_ref(), &config)?);
+	}
+
+	for (_, channel, sync) in channels(&config) {
+		sync(store.as_ref(), &config)?;
+		crate::sync::record_sync(store.as_ref(), channel)?;
+	}
+	Ok(())
 }
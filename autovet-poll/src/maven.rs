@@ -0,0 +1,255 @@
+use crate::{
+	fetch::{fetch, fetch_optional, ATTEMPTS},
+	sync::{current_packages, insert_new, is_known},
+};
+use autovet_core::{
+	config::MavenConfig,
+	error::{retry, Error, Result},
+	package::{Artifact, Package, PackageChannel},
+	store::Store,
+};
+use log::{info, warn};
+use std::io::Read;
+
+/// Get the text of each `<tag>` element in the given XML. This is only meant
+/// for the flat, attribute-free elements of Maven metadata.
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+	let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+	let mut values = Vec::new();
+
+	let mut rest = xml;
+	while let Some(start) = rest.find(&open) {
+		rest = &rest[start + open.len()..];
+		match rest.find(&close) {
+			Some(end) => {
+				values.push(rest[..end].trim());
+				rest = &rest[end + close.len()..];
+			}
+			None => break,
+		}
+	}
+	values
+}
+
+/// List the versions in a `maven-metadata.xml` file.
+fn parse_metadata(xml: &str) -> Vec<String> {
+	match elements(xml, "versions").first() {
+		Some(versions) => elements(versions, "version")
+			.into_iter()
+			.map(String::from)
+			.collect(),
+		None => Vec::new(),
+	}
+}
+
+fn fetch_text(url: &str) -> Result<Option<String>> {
+	match retry(ATTEMPTS, || fetch_optional(url))? {
+		Some(mut body) => {
+			let mut content = String::new();
+			body.read_to_string(&mut content)?;
+			Ok(Some(content))
+		}
+		None => Ok(None),
+	}
+}
+
+/// Read a checksum sidecar. These sometimes include the file name after the
+/// hash.
+fn fetch_checksum(url: &str) -> Result<Option<String>> {
+	Ok(fetch_text(url)?.and_then(|content| content.split_whitespace().next().map(String::from)))
+}
+
+/// Describe a file of the given version along with its published checksums.
+fn artifact(base: &str, filename: String) -> Result<Artifact> {
+	let url = format!("{}/{}", base, filename);
+	Ok(Artifact {
+		sha1sum: fetch_checksum(&format!("{}.sha1", url))?,
+		sha256sum: fetch_checksum(&format!("{}.sha256", url))?,
+		md5sum: fetch_checksum(&format!("{}.md5", url))?,
+		kind: filename.rsplit('.').next().map(String::from),
+		size: None,
+		filename,
+		url,
+	})
+}
+
+/// Build a package for one version of the given artifact.
+fn fetch_version(base: &str, name: &str, artifact_id: &str, version: &str) -> Result<Package> {
+	let base = format!("{}/{}", base, version);
+	let pom = format!("{}-{}.pom", artifact_id, version);
+
+	let packaging = match fetch_text(&format!("{}/{}", base, pom))? {
+		Some(content) => elements(&content, "packaging")
+			.first()
+			.map(|packaging| packaging.to_string())
+			.unwrap_or_else(|| String::from("jar")),
+		None => {
+			return Err(Error::Parse(format!(
+				"No POM found for {} {}",
+				name, version
+			)))
+		}
+	};
+
+	let mut package = Package {
+		channel: PackageChannel::MavenCentral,
+		name: name.to_string(),
+		version: version.to_string(),
+		artifacts: vec![artifact(&base, pom)?],
+		..Default::default()
+	};
+
+	// Plugins and OSGi bundles are still packaged as JARs
+	let extension = match packaging.as_str() {
+		"pom" => None,
+		"bundle" | "maven-plugin" | "eclipse-plugin" => Some("jar"),
+		extension => Some(extension),
+	};
+	if let Some(extension) = extension {
+		let main = artifact(&base, format!("{}-{}.{}", artifact_id, version, extension))?;
+		package.url = Some(main.url.clone());
+		package.sha256sum = main.sha256sum.clone();
+		package.md5sum = main.md5sum.clone();
+		package.artifacts.insert(0, main);
+	}
+
+	package
+		.extra
+		.insert(String::from("packaging"), vec![packaging]);
+	Ok(package)
+}
+
+/// Synchronize the versions of the configured artifacts from the repository.
+pub fn sync(store: &dyn Store, config: &MavenConfig) -> Result<()> {
+	let mut current_packages = current_packages(store, PackageChannel::MavenCentral)?;
+
+	for coordinates in &config.artifacts {
+		let (group_id, artifact_id) = match coordinates.split_once(':') {
+			Some(coordinates) => coordinates,
+			None => {
+				warn!("Invalid Maven coordinates: {}", coordinates);
+				continue;
+			}
+		};
+		let base = format!(
+			"{}/{}/{}",
+			config.repository.trim_end_matches('/'),
+			group_id.replace('.', "/"),
+			artifact_id
+		);
+
+		let url = format!("{}/maven-metadata.xml", base);
+		let metadata = match retry(ATTEMPTS, || fetch(&url)).and_then(|mut body| {
+			let mut content = String::new();
+			body.read_to_string(&mut content)?;
+			Ok(content)
+		}) {
+			Ok(metadata) => metadata,
+			Err(e) => {
+				warn!("Failed to fetch metadata for {}: {}", coordinates, e);
+				continue;
+			}
+		};
+
+		let mut count = 0;
+		for version in parse_metadata(&metadata) {
+			if is_known(&current_packages, coordinates, &version, &None) {
+				continue;
+			}
+
+			match fetch_version(&base, coordinates, artifact_id, &version) {
+				Ok(package) => {
+					if insert_new(store, &mut current_packages, package)? {
+						count += 1;
+					}
+				}
+				Err(e) => warn!("Skipping {} {}: {}", coordinates, version, e),
+			}
+		}
+		info!("Found {} new versions of {}", count, coordinates);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::store::{sqlite::SqliteStore, PackageQuery};
+	use std::fs;
+
+	#[test]
+	fn test_parse_metadata() {
+		assert_eq!(
+			parse_metadata(
+				"<metadata>
+					<groupId>org.example</groupId>
+					<artifactId>demo</artifactId>
+					<versioning>
+						<latest>1.1</latest>
+						<release>1.1</release>
+						<versions>
+							<version>1.0</version>
+							<version>1.1</version>
+						</versions>
+					</versioning>
+				</metadata>"
+			),
+			vec!["1.0", "1.1"]
+		);
+	}
+
+	#[test]
+	fn test_sync_local_repository() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		let base = root.path().join("org/example/demo");
+
+		fs::create_dir_all(base.join("1.0"))?;
+		fs::write(
+			base.join("maven-metadata.xml"),
+			"<metadata><versioning><versions><version>1.0</version><version>2.0</version></versions></versioning></metadata>",
+		)?;
+		fs::write(base.join("1.0/demo-1.0.pom"), "<project></project>")?;
+		fs::write(base.join("1.0/demo-1.0.pom.sha1"), "1111")?;
+		fs::write(base.join("1.0/demo-1.0.jar"), "")?;
+		fs::write(
+			base.join("1.0/demo-1.0.jar.sha1"),
+			"da39a3ee5e6b4b0d3255bfef95601890afd80709  demo-1.0.jar",
+		)?;
+		fs::write(
+			base.join("1.0/demo-1.0.jar.sha256"),
+			"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+		)?;
+
+		let config = MavenConfig {
+			repository: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			artifacts: vec![String::from("org.example:demo")],
+		};
+
+		let store = SqliteStore::open_in_memory()?;
+		sync(&store, &config)?;
+		sync(&store, &config)?;
+
+		// Version 2.0 has no files yet
+		let packages = Package::find(&store, &PackageQuery::default())?;
+		assert_eq!(packages.len(), 1);
+
+		let package = &packages[0];
+		assert_eq!(package.name, "org.example:demo");
+		assert!(package.url.as_deref().unwrap().ends_with("demo-1.0.jar"));
+		assert_eq!(
+			package.sha256sum.as_deref(),
+			Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+		);
+		assert_eq!(package.artifacts.len(), 2);
+		assert_eq!(
+			package.artifacts[0].sha1sum.as_deref(),
+			Some("da39a3ee5e6b4b0d3255bfef95601890afd80709")
+		);
+		assert_eq!(package.artifacts[1].kind.as_deref(), Some("pom"));
+		assert_eq!(package.artifacts[1].sha1sum.as_deref(), Some("1111"));
+		Ok(())
+	}
+}
@@ -0,0 +1,115 @@
+//! Comparing the databases served by different mirrors of a repository.
+//!
+//! Mirrors lag behind each other, so a mirror serving another version of a
+//! package is only a warning. A mirror serving different content for the same
+//! version is how a tampered mirror shows itself, and is critical.
+
+use autovet_core::{
+	analysis::{Finding, FindingSeverity},
+	package::Package,
+};
+use std::collections::HashMap;
+
+/// The name of the analyses that hold mirror findings.
+pub const ANALYSIS_NAME: &str = "mirrors";
+
+/// A difference between the primary mirror and another mirror.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mismatch {
+	/// The package as served by the primary mirror
+	pub name: String,
+
+	pub version: String,
+
+	pub arch: Option<String>,
+
+	pub finding: Finding,
+}
+
+/// Compare the packages of a database from the primary mirror with the same
+/// database from another mirror.
+pub fn compare(primary: &[Package], other: &[Package], mirror: &str) -> Vec<Mismatch> {
+	let served: HashMap<(&str, Option<&str>), &Package> = other
+		.iter()
+		.map(|package| ((package.name.as_str(), package.arch.as_deref()), package))
+		.collect();
+
+	let mut mismatches = Vec::new();
+	for package in primary {
+		let other = match served.get(&(package.name.as_str(), package.arch.as_deref())) {
+			Some(other) => other,
+			None => continue,
+		};
+
+		let finding = if other.version != package.version {
+			Finding {
+				severity: FindingSeverity::Warning,
+				message: format!(
+					"{} serves {} {} instead of {}",
+					mirror, package.name, other.version, package.version
+				),
+			}
+		} else if other.sha256sum != package.sha256sum || other.md5sum != package.md5sum {
+			Finding {
+				severity: FindingSeverity::Critical,
+				message: format!(
+					"{} serves {} {} with sha256 {}, but the primary mirror has {}",
+					mirror,
+					package.name,
+					package.version,
+					other.sha256sum.as_deref().unwrap_or("(none)"),
+					package.sha256sum.as_deref().unwrap_or("(none)")
+				),
+			}
+		} else {
+			continue;
+		};
+
+		mismatches.push(Mismatch {
+			name: package.name.clone(),
+			version: package.version.clone(),
+			arch: package.arch.clone(),
+			finding,
+		});
+	}
+	mismatches
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::package::PackageChannel;
+
+	fn package(name: &str, version: &str, sha256sum: &str) -> Package {
+		Package {
+			channel: PackageChannel::Pacman,
+			name: name.to_string(),
+			version: version.to_string(),
+			arch: Some(String::from("x86_64")),
+			sha256sum: Some(sha256sum.to_string()),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_compare() {
+		let primary = [
+			package("grep", "3.7-1", "aaaa"),
+			package("sed", "4.8-1", "bbbb"),
+			package("gawk", "5.1.1-1", "cccc"),
+		];
+		let other = [
+			package("grep", "3.7-1", "dddd"),
+			package("sed", "4.7-1", "eeee"),
+			package("gawk", "5.1.1-1", "cccc"),
+		];
+
+		let mismatches = compare(&primary, &other, "http://mirror");
+		assert_eq!(mismatches.len(), 2);
+		assert_eq!(mismatches[0].name, "grep");
+		assert_eq!(mismatches[0].finding.severity, FindingSeverity::Critical);
+		assert!(mismatches[0].finding.message.contains("dddd"));
+		assert_eq!(mismatches[1].name, "sed");
+		assert_eq!(mismatches[1].finding.severity, FindingSeverity::Warning);
+	}
+}
@@ -0,0 +1,287 @@
+use crate::{
+	fetch::{fetch, ATTEMPTS},
+	sync::{current_packages, insert_new, is_known},
+};
+use autovet_core::{
+	config::NpmConfig,
+	error::{retry, Result},
+	package::{Package, PackageChannel},
+	store::Store,
+	version,
+};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Scripts that npm runs automatically when a package is installed or
+/// removed. These are where install-time malware usually lives.
+const LIFECYCLE_SCRIPTS: [&str; 10] = [
+	"preinstall",
+	"install",
+	"postinstall",
+	"prepublish",
+	"preprepare",
+	"prepare",
+	"postprepare",
+	"preuninstall",
+	"uninstall",
+	"postuninstall",
+];
+
+/// The registry's document describing every version of a package.
+#[derive(Deserialize)]
+struct Packument {
+	name: String,
+
+	#[serde(default)]
+	versions: BTreeMap<String, VersionManifest>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct VersionManifest {
+	name: String,
+	version: String,
+	description: Option<String>,
+	homepage: Option<String>,
+	/// Either a SPDX expression or a legacy `{"type": ...}` object
+	license: Option<Value>,
+	maintainers: Vec<Person>,
+	dependencies: BTreeMap<String, String>,
+	#[serde(rename = "optionalDependencies")]
+	optional_dependencies: BTreeMap<String, String>,
+	scripts: BTreeMap<String, Value>,
+	dist: Dist,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Person {
+	Object { name: String, email: Option<String> },
+	String(String),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Dist {
+	tarball: Option<String>,
+	integrity: Option<String>,
+	shasum: Option<String>,
+	#[serde(rename = "unpackedSize")]
+	unpacked_size: Option<u64>,
+}
+
+impl From<VersionManifest> for Package {
+	fn from(manifest: VersionManifest) -> Self {
+		let mut package = Package {
+			channel: PackageChannel::Npm,
+			name: manifest.name,
+			version: manifest.version,
+			description: manifest.description.filter(|s| !s.is_empty()),
+			upstream_url: manifest.homepage,
+			url: manifest.dist.tarball,
+			integrity: manifest.dist.integrity,
+			installed_size: manifest.dist.unpacked_size,
+			..Default::default()
+		};
+
+		package.license = match manifest.license {
+			Some(Value::String(license)) => vec![license],
+			Some(Value::Object(license)) => license
+				.get("type")
+				.and_then(Value::as_str)
+				.map(String::from)
+				.into_iter()
+				.collect(),
+			_ => Vec::new(),
+		};
+
+		package.maintainers = manifest
+			.maintainers
+			.into_iter()
+			.map(|person| match person {
+				Person::Object {
+					name,
+					email: Some(email),
+				} => format!("{} <{}>", name, email),
+				Person::Object { name, email: None } => name,
+				Person::String(person) => person,
+			})
+			.collect();
+
+		package.depends = manifest
+			.dependencies
+			.into_iter()
+			.map(|(name, range)| format!("{}@{}", name, range))
+			.collect();
+		package.optdepends = manifest
+			.optional_dependencies
+			.into_iter()
+			.map(|(name, range)| format!("{}@{}", name, range))
+			.collect();
+
+		package.scripts = manifest
+			.scripts
+			.into_iter()
+			.filter(|(event, _)| LIFECYCLE_SCRIPTS.contains(&event.as_str()))
+			.filter_map(|(event, script)| script.as_str().map(|script| (event, script.to_string())))
+			.collect();
+
+		if let Some(shasum) = manifest.dist.shasum {
+			package.extra.insert(String::from("shasum"), vec![shasum]);
+		}
+		package
+	}
+}
+
+/// The URL of a package's document. The slash in scoped package names must be
+/// escaped.
+fn packument_url(registry: &str, name: &str) -> String {
+	format!(
+		"{}/{}",
+		registry.trim_end_matches('/'),
+		name.replacen('/', "%2f", 1)
+	)
+}
+
+/// Synchronize the versions of the configured packages from the registry.
+pub fn sync(store: &dyn Store, config: &NpmConfig) -> Result<()> {
+	let mut current_packages = current_packages(store, PackageChannel::Npm)?;
+
+	for name in &config.packages {
+		let url = packument_url(&config.registry, name);
+		let packument: Packument = match retry(ATTEMPTS, || fetch(&url))
+			.and_then(|body| Ok(serde_json::from_reader(body)?))
+		{
+			Ok(packument) => packument,
+			Err(e) => {
+				warn!("Failed to fetch package {}: {}", name, e);
+				continue;
+			}
+		};
+
+		// Insert the oldest versions first so each can be linked to its
+		// predecessor
+		let mut versions: Vec<(String, VersionManifest)> = packument.versions.into_iter().collect();
+		version::sort(PackageChannel::Npm, &mut versions, |(version, _)| version);
+
+		let mut count = 0;
+		for (version, manifest) in versions {
+			if is_known(&current_packages, &packument.name, &version, &None) {
+				continue;
+			}
+
+			let mut package = Package::from(manifest);
+			if package.name.is_empty() {
+				package.name = packument.name.clone();
+			}
+			if package.version.is_empty() {
+				package.version = version;
+			}
+
+			if !package.scripts.is_empty() {
+				info!(
+					"{}@{} has install scripts: {:?}",
+					package.name,
+					package.version,
+					package.scripts.keys()
+				);
+			}
+
+			if insert_new(store, &mut current_packages, package)? {
+				count += 1;
+			}
+		}
+		info!("Found {} new versions of {}", count, packument.name);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::store::{sqlite::SqliteStore, PackageQuery};
+	use serde_json::json;
+	use std::fs;
+
+	#[test]
+	fn test_sync_local_registry() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		fs::create_dir_all(root.path().join("@demo"))?;
+		fs::write(
+			root.path().join("@demo/pad"),
+			json!({
+				"name": "@demo/pad",
+				"versions": {
+					"1.0.0": {
+						"name": "@demo/pad",
+						"version": "1.0.0",
+						"license": "MIT",
+						"maintainers": [{"name": "jane", "email": "jane@example.com"}],
+						"dependencies": {"left-pad": "^1.3.0"},
+						"dist": {
+							"tarball": "https://registry.example/@demo/pad/-/pad-1.0.0.tgz",
+							"integrity": "sha512-AAAA",
+							"shasum": "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+						}
+					},
+					"1.9.0": {
+						"name": "@demo/pad",
+						"version": "1.9.0",
+						"dist": {
+							"tarball": "https://registry.example/@demo/pad/-/pad-1.9.0.tgz"
+						}
+					},
+					"1.10.0": {
+						"name": "@demo/pad",
+						"version": "1.10.0",
+						"license": {"type": "MIT"},
+						"maintainers": ["mallory <mallory@example.com>"],
+						"scripts": {"test": "jest", "postinstall": "node steal.js"},
+						"dist": {
+							"tarball": "https://registry.example/@demo/pad/-/pad-1.10.0.tgz",
+							"integrity": "sha512-BBBB"
+						}
+					}
+				}
+			})
+			.to_string(),
+		)?;
+
+		let config = NpmConfig {
+			registry: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			packages: vec![String::from("@demo/pad")],
+		};
+
+		let store = SqliteStore::open_in_memory()?;
+		sync(&store, &config)?;
+		sync(&store, &config)?;
+
+		let mut packages = Package::find(&store, &PackageQuery::default())?;
+		packages.sort_by(|a, b| a.version.cmp(&b.version));
+		assert_eq!(packages.len(), 3);
+
+		assert_eq!(packages[0].depends, vec!["left-pad@^1.3.0"]);
+		assert_eq!(packages[0].maintainers, vec!["jane <jane@example.com>"]);
+		assert_eq!(packages[0].integrity.as_deref(), Some("sha512-AAAA"));
+
+		assert_eq!(packages[1].license, vec!["MIT"]);
+		assert_eq!(
+			packages[1].url.as_deref(),
+			Some("https://registry.example/@demo/pad/-/pad-1.10.0.tgz")
+		);
+		assert_eq!(
+			packages[1].scripts,
+			BTreeMap::from([(String::from("postinstall"), String::from("node steal.js"))])
+		);
+
+		// 1.10.0 follows 1.9.0 even though it sorts first as text
+		assert_eq!(packages[1].supersedes, packages[2]._id);
+		assert_eq!(packages[2].supersedes, packages[0]._id);
+		Ok(())
+	}
+}
@@ -0,0 +1,170 @@
+use crate::sync::{current_packages, insert_new};
+use autovet_core::{
+	config::OciConfig,
+	error::Result,
+	oci::ImageReference,
+	package::{Artifact, Package, PackageChannel},
+	store::Store,
+};
+use log::{info, warn};
+
+/// Describe the image that the given reference currently points to.
+fn fetch_image(image: &str) -> Result<Package> {
+	let reference = ImageReference::parse(image)?;
+	let manifest = reference.source.manifest(&reference.reference)?;
+	let config = reference.source.config(&manifest)?;
+
+	// Tags move, so the digest is part of the version
+	let version = if reference.reference.starts_with("sha256:") {
+		manifest.digest.clone()
+	} else {
+		format!("{}@{}", reference.reference, manifest.digest)
+	};
+
+	let mut package = Package {
+		channel: PackageChannel::DockerHub,
+		url: Some(format!("{}@{}", reference.name, manifest.digest)),
+		version,
+		arch: Some(config.architecture.clone()).filter(|arch| !arch.is_empty()),
+		digest: Some(manifest.digest),
+		config_digest: Some(manifest.config.digest),
+		entrypoint: config.config.command(),
+		artifacts: manifest
+			.layers
+			.into_iter()
+			.map(|layer| Artifact {
+				url: format!("{}@{}", reference.name, layer.digest),
+				sha256sum: layer.digest.strip_prefix("sha256:").map(String::from),
+				filename: layer.digest,
+				kind: Some(layer.media_type),
+				size: Some(layer.size),
+				..Default::default()
+			})
+			.collect(),
+		name: reference.name,
+		..Default::default()
+	};
+
+	let container = config.config;
+	if let Some(env) = container.env {
+		package.extra.insert(String::from("Env"), env);
+	}
+	if let Some(working_dir) = container.working_dir.filter(|s| !s.is_empty()) {
+		package
+			.extra
+			.insert(String::from("WorkingDir"), vec![working_dir]);
+	}
+	if let Some(user) = container.user.filter(|s| !s.is_empty()) {
+		package.extra.insert(String::from("User"), vec![user]);
+	}
+	Ok(package)
+}
+
+/// Record the current image behind each configured reference.
+pub fn sync(store: &dyn Store, config: &OciConfig) -> Result<()> {
+	let mut current_packages = current_packages(store, PackageChannel::DockerHub)?;
+
+	for image in &config.images {
+		match fetch_image(image) {
+			Ok(package) => {
+				let version = package.version.clone();
+				if insert_new(store, &mut current_packages, package)? {
+					info!("Found new image {} ({})", image, version);
+				}
+			}
+			Err(e) => warn!("Failed to fetch image {}: {}", image, e),
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::store::{sqlite::SqliteStore, PackageQuery};
+	use serde_json::{json, Value};
+	use sha2::{Digest, Sha256};
+	use std::{fs, path::Path};
+
+	/// Add a blob to the layout and return its descriptor.
+	fn blob(root: &Path, media_type: &str, content: &[u8]) -> Result<Value> {
+		let hex = hex::encode(Sha256::digest(content));
+		fs::write(root.join("blobs/sha256").join(&hex), content)?;
+		Ok(json!({
+			"mediaType": media_type,
+			"digest": format!("sha256:{}", hex),
+			"size": content.len(),
+		}))
+	}
+
+	#[test]
+	fn test_sync_image_layout() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		fs::create_dir_all(root.path().join("blobs/sha256"))?;
+
+		let layer = blob(
+			root.path(),
+			"application/vnd.oci.image.layer.v1.tar+gzip",
+			b"not really a layer",
+		)?;
+		let config = blob(
+			root.path(),
+			"application/vnd.oci.image.config.v1+json",
+			json!({
+				"architecture": "amd64",
+				"os": "linux",
+				"config": {
+					"Entrypoint": ["/usr/bin/demo"],
+					"Cmd": ["--serve"],
+					"Env": ["PATH=/usr/bin"]
+				}
+			})
+			.to_string()
+			.as_bytes(),
+		)?;
+		let mut manifest = blob(
+			root.path(),
+			"application/vnd.oci.image.manifest.v1+json",
+			json!({
+				"schemaVersion": 2,
+				"mediaType": "application/vnd.oci.image.manifest.v1+json",
+				"config": config,
+				"layers": [layer]
+			})
+			.to_string()
+			.as_bytes(),
+		)?;
+		manifest["annotations"] = json!({"org.opencontainers.image.ref.name": "1.0"});
+		fs::write(
+			root.path().join("index.json"),
+			json!({"schemaVersion": 2, "manifests": [manifest]}).to_string(),
+		)?;
+
+		let config = OciConfig {
+			images: vec![format!("oci:{}:1.0", root.path().display())],
+		};
+		let store = SqliteStore::open_in_memory()?;
+		sync(&store, &config)?;
+		sync(&store, &config)?;
+
+		let packages = Package::find(&store, &PackageQuery::default())?;
+		assert_eq!(packages.len(), 1);
+
+		let package = &packages[0];
+		assert_eq!(package.channel, PackageChannel::DockerHub);
+		assert_eq!(package.digest.as_deref(), manifest["digest"].as_str());
+		assert_eq!(
+			package.version,
+			format!("1.0@{}", manifest["digest"].as_str().unwrap())
+		);
+		assert_eq!(package.entrypoint, vec!["/usr/bin/demo", "--serve"]);
+		assert_eq!(package.arch.as_deref(), Some("amd64"));
+		assert_eq!(package.artifacts.len(), 1);
+		assert_eq!(
+			package.artifacts[0].filename,
+			layer["digest"].as_str().unwrap()
+		);
+		Ok(())
+	}
+}
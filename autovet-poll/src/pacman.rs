@@ -1,44 +1,913 @@
-use autovet_core::package::Package;
+use crate::{
+	fetch::{fetch_bytes, fetch_if_modified, fetch_optional, Validators, ATTEMPTS},
+	mirrors::{self, Mismatch},
+	rollback,
+	sync::record_findings,
+};
+use autovet_core::{
+	analysis::{Finding, FindingSeverity},
+	config::PacmanConfig,
+	error::{retry, Error, Result},
+	keyring::Keyring,
+	package::{Package, PackageChannel},
+	repository::Repository,
+	store::{PackageQuery, Store},
+	version,
+};
 use flate2::read::GzDecoder;
-use serde_json::json;
-use std::error::Error;
+use log::{error, info, warn};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
 use std::io::Read;
 use tar::Archive;
 
-/// Synchronize package metadata from the pacman database.
-pub fn sync() -> Result<(), Box<dyn Error>> {
-	// List current packages
-	let current_packages: Vec<Package> = Package::find(json!(
-	{
-		"selector": {
-			"channel": "Pacman"
-		},
-		"fields": ["name", "version", "channel"],
-		"limit": 1000000
-	}))?;
-
-	// Get latest pacman database and find new packages
-	for repo in vec!["core", "community", "extra", "multilib"] {
-		let tar_gz = reqwest::blocking::get(format!(
-			"http://mirror.fossable.org/archlinux/{repo}/os/x86_64/{repo}.db.tar.gz"
-		))?;
-		let tar = GzDecoder::new(tar_gz);
-		let mut archive = Archive::new(tar);
-
-		for entry in archive.entries()? {
-			let mut desc = String::new();
-			entry?.read_to_string(&mut desc)?;
-
-			if let Ok(mut package) = Package::from_pacman_desc(&desc) {
-				if !current_packages
-					.iter()
-					.any(|p| p.name == package.name && p.version == package.version)
-				{
-					package.update()?;
+/// The number of new packages written to the store at once, and of package
+/// names looked up at once.
+const BATCH_SIZE: usize = 1000;
+
+/// Packages are identified by name, version and architecture.
+type Key = (String, String, Option<String>);
+
+fn key(package: &Package) -> Key {
+	(
+		package.name.clone(),
+		package.version.clone(),
+		package.arch.clone(),
+	)
+}
+
+/// Check a repository database against its detached signature, returning the
+/// fingerprint of the key that signed it.
+fn verify_database(keyring: &Keyring, url: &str, db: &[u8]) -> Result<String> {
+	let mut signature = Vec::new();
+	match retry(ATTEMPTS, || fetch_optional(url))? {
+		Some(mut body) => body.read_to_end(&mut signature)?,
+		None => return Err(Error::Signature(format!("No signature found at {}", url))),
+	};
+	keyring.verify(db, &signature)
+}
+
+/// Parse every package in a repository database.
+fn read_database(repo: &str, db: &[u8]) -> Result<Vec<Package>> {
+	let mut archive = Archive::new(GzDecoder::new(db));
+	let mut packages = Vec::new();
+
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+
+		// Each package has a directory containing a "desc" file
+		if !entry.path()?.ends_with("desc") {
+			continue;
+		}
+
+		let mut desc = String::new();
+		entry.read_to_string(&mut desc)?;
+
+		match Package::from_pacman_desc(&desc) {
+			Ok(mut package) => {
+				package.repo = Some(repo.to_string());
+				packages.push(package);
+			}
+			Err(e) => warn!("Skipping package in {}: {}", repo, e),
+		}
+	}
+	Ok(packages)
+}
+
+/// Record which repository a package that's already in the store belongs to
+/// and whether it was withdrawn. The known packages only hold a few fields, so
+/// the full document is loaded before it's written back.
+fn update_state(store: &dyn Store, known: &mut Package, repo: &str, yanked: bool) -> Result<()> {
+	let query = PackageQuery {
+		channel: Some(PackageChannel::Pacman),
+		name: Some(known.name.clone()),
+		version: Some(known.version.clone()),
+		..Default::default()
+	};
+
+	for mut package in retry(ATTEMPTS, || Package::find(store, &query))? {
+		if package.arch != known.arch {
+			continue;
+		}
+
+		package.repo = Some(repo.to_string());
+		package.yanked = yanked;
+		match retry(ATTEMPTS, || package.update(store)) {
+			// The package changed under us, so the next sync will try again
+			Err(e) if e.is_conflict() => {}
+			result => result?,
+		}
+	}
+
+	known.repo = Some(repo.to_string());
+	known.yanked = yanked;
+	Ok(())
+}
+
+/// The names of the packages whose version changed since the database was
+/// last synchronized, including ones that were added or removed. Nothing else
+/// can need updating in the store.
+fn changed_names<'a>(repository: &'a Repository, packages: &'a [Package]) -> BTreeSet<&'a str> {
+	let current: BTreeSet<&str> = packages.iter().map(|p| p.name.as_str()).collect();
+	packages
+		.iter()
+		.filter(|p| repository.versions.get(&p.name) != Some(&p.version))
+		.map(|p| p.name.as_str())
+		.chain(
+			repository
+				.versions
+				.keys()
+				.map(String::as_str)
+				.filter(|name| !current.contains(name)),
+		)
+		.collect()
+}
+
+/// Find the ID of the given version of a package, among the packages loaded
+/// for this sync or else in the store.
+fn find_id(store: &dyn Store, known: &[Package], wanted: &Key) -> Result<Option<String>> {
+	if let Some(package) = known.iter().find(|p| key(p) == *wanted) {
+		return Ok(package._id.clone());
+	}
+	let query = PackageQuery {
+		channel: Some(PackageChannel::Pacman),
+		name: Some(wanted.0.clone()),
+		version: Some(wanted.1.clone()),
+		fields: Some(vec!["_id".into(), "arch".into()]),
+		..Default::default()
+	};
+	Ok(retry(ATTEMPTS, || Package::find(store, &query))?
+		.into_iter()
+		.find(|p| p.arch == wanted.2)
+		.and_then(|p| p._id))
+}
+
+/// Bring the store in line with the packages currently in a repository's
+/// database for one architecture: insert new ones, restore ones that
+/// reappeared or moved here from another repository, and flag ones that were
+/// withdrawn. Packages for any architecture are in every database, so only the
+/// primary architecture's database may withdraw them.
+fn sync_repo(
+	store: &dyn Store,
+	known: &mut Vec<Package>,
+	repo: &str,
+	arch: &str,
+	primary: bool,
+	packages: Vec<Package>,
+) -> Result<()> {
+	let index: HashMap<Key, usize> = known
+		.iter()
+		.enumerate()
+		.map(|(i, package)| (key(package), i))
+		.collect();
+	let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+	for (i, package) in known.iter().enumerate() {
+		by_name.entry(package.name.as_str()).or_default().push(i);
+	}
+
+	// Repositories only carry the latest version of each package, so a version
+	// that disappeared was only withdrawn if nothing at least as new replaced it
+	let mut present: HashMap<(&str, Option<&str>), Vec<&str>> = HashMap::new();
+	for package in &packages {
+		present
+			.entry((package.name.as_str(), package.arch.as_deref()))
+			.or_default()
+			.push(package.version.as_str());
+	}
+	let removed: Vec<usize> = known
+		.iter()
+		.enumerate()
+		.filter(|(_, package)| {
+			let in_scope = match package.arch.as_deref() {
+				Some("any") | None => primary,
+				Some(other) => other == arch,
+			};
+			in_scope
+				&& package.repo.as_deref() == Some(repo)
+				&& !package.yanked
+				&& !present
+					.get(&(package.name.as_str(), package.arch.as_deref()))
+					.into_iter()
+					.flatten()
+					.any(|version| {
+						version::compare(PackageChannel::Pacman, version, &package.version)
+							!= Some(Ordering::Less)
+					})
+		})
+		.map(|(i, _)| i)
+		.collect();
+
+	let (mut new, mut restored) = (Vec::new(), Vec::new());
+	for mut package in packages {
+		match index.get(&key(&package)) {
+			Some(&i) => {
+				let current = &known[i];
+				if current.yanked || current.repo.as_deref().is_some_and(|r| r != repo) {
+					restored.push(i);
 				}
 			}
+			None => {
+				let candidates = by_name.get(package.name.as_str()).into_iter().flatten();
+				package.supersedes = package
+					.predecessor_in(candidates.map(|&i| &known[i]))
+					.and_then(|predecessor| predecessor._id.clone());
+				new.push(package);
+			}
+		}
+	}
+
+	for &i in &restored {
+		info!("{} {} is now in {}", known[i].name, known[i].version, repo);
+		update_state(store, &mut known[i], repo, false)?;
+	}
+	for &i in &removed {
+		info!(
+			"{} {} was withdrawn from {}",
+			known[i].name, known[i].version, repo
+		);
+		update_state(store, &mut known[i], repo, true)?;
+	}
+
+	for batch in new.chunks_mut(BATCH_SIZE) {
+		let results = retry(ATTEMPTS, || store.create_packages(batch))?;
+		for result in results {
+			match result {
+				// Another poller already inserted this package
+				Err(e) if e.is_conflict() => {}
+				result => result?,
+			}
 		}
 	}
 
+	info!(
+		"Found {} new, {} restored and {} withdrawn packages in {} ({})",
+		new.len(),
+		restored.len(),
+		removed.len(),
+		repo,
+		arch
+	);
+	known.append(&mut new);
 	Ok(())
 }
+
+/// Compare a database from the primary mirror with the same database on each
+/// of the mirrors used for comparison. Mirrors that can't be reached are
+/// skipped.
+fn check_mirrors(
+	config: &PacmanConfig,
+	keyring: Option<&Keyring>,
+	repo: &str,
+	arch: &str,
+	packages: &[Package],
+) -> Vec<Mismatch> {
+	let mut mismatches = Vec::new();
+	for base in config.compare_servers(repo, arch) {
+		let url = format!("{}/{repo}.db.tar.gz", base);
+		let db = match retry(ATTEMPTS, || fetch_bytes(&url)) {
+			Ok(db) => db,
+			Err(e) => {
+				warn!("Failed to fetch {} for comparison: {}", url, e);
+				continue;
+			}
+		};
+
+		if let Some(keyring) = keyring {
+			if let Err(e) = verify_database(keyring, &format!("{}/{repo}.db.sig", base), &db) {
+				error!(
+					"The {} database from {} isn't properly signed: {}",
+					repo, base, e
+				);
+				continue;
+			}
+		}
+
+		match read_database(repo, &db) {
+			Ok(other) => mismatches.extend(mirrors::compare(packages, &other, &base)),
+			Err(e) => warn!("Failed to read {}: {}", url, e),
+		}
+	}
+	mismatches
+}
+
+/// Synchronize one repository database, unless it's unchanged since the last
+/// run. Only the packages whose version changed are loaded from the store.
+fn sync_database(
+	store: &dyn Store,
+	config: &PacmanConfig,
+	keyring: Option<&Keyring>,
+	repo: &str,
+	arch: &str,
+) -> Result<()> {
+	let base = config.server(repo, arch);
+	let url = format!("{}/{repo}.db.tar.gz", base);
+	let label = format!("The {} database for {}", repo, arch);
+
+	let mut repository = retry(ATTEMPTS, || {
+		Repository::find(store, PackageChannel::Pacman, repo, Some(arch))
+	})?;
+	let recorded = if repository.url == url {
+		Validators {
+			etag: repository.etag.clone(),
+			last_modified: repository.last_modified.clone(),
+		}
+	} else {
+		Validators::default()
+	};
+
+	// Other mirrors are compared with the primary's database on every sync,
+	// so it's needed even when it hasn't changed
+	let validators = if config.compare_mirrors.is_empty() {
+		recorded.clone()
+	} else {
+		Validators::default()
+	};
+	let (db, validators) = match retry(ATTEMPTS, || fetch_if_modified(&url, &validators))? {
+		Some(db) => db,
+		None => {
+			info!("{} hasn't changed", label);
+			return finish(store, config, &mut repository, &label);
+		}
+	};
+
+	// Don't trust anything from a database that isn't properly signed
+	if let Some(keyring) = keyring {
+		match verify_database(keyring, &format!("{}/{repo}.db.sig", base), &db) {
+			Ok(fingerprint) => info!("{} is signed by {}", label, fingerprint),
+			// The signature couldn't be fetched this time
+			Err(e) if e.is_transient() => return Err(e),
+			Err(e) => {
+				error!("Refusing to synchronize {} for {}: {}", repo, arch, e);
+				add_alert(
+					&mut repository,
+					Finding {
+						severity: FindingSeverity::Critical,
+						message: format!("{} isn't properly signed: {}", label, e),
+					},
+				);
+				return finish(store, config, &mut repository, &label);
+			}
+		}
+	}
+
+	// Nor from one that's older than what was seen before
+	let packages = read_database(repo, &db)?;
+	let timestamp = rollback::timestamp(validators.last_modified.as_deref());
+	if let Some(alert) = rollback::rolled_back(&repository, timestamp, &label) {
+		error!(
+			"Refusing to synchronize {} for {}: {}",
+			repo, arch, alert.message
+		);
+		add_alert(&mut repository, alert);
+		return finish(store, config, &mut repository, &label);
+	}
+
+	let mut findings: Vec<(Key, &str, Finding)> = Vec::new();
+	for mismatch in check_mirrors(config, keyring, repo, arch, &packages) {
+		let wanted = (mismatch.name, mismatch.version, mismatch.arch);
+		findings.push((wanted, mirrors::ANALYSIS_NAME, mismatch.finding));
+	}
+	for downgrade in rollback::downgrades(&repository, &packages) {
+		let finding = downgrade.finding(&label);
+		let wanted = (downgrade.name, downgrade.from, downgrade.arch);
+		findings.push((wanted, rollback::ANALYSIS_NAME, finding));
+	}
+
+	let versions = rollback::versions(&packages);
+	let changed = changed_names(&repository, &packages);
+	let mut known = Vec::new();
+	let names: Vec<String> = changed.iter().map(|name| name.to_string()).collect();
+	for names in names.chunks(BATCH_SIZE) {
+		let query = PackageQuery {
+			channel: Some(PackageChannel::Pacman),
+			names: Some(names.to_vec()),
+			..Default::default()
+		};
+		known.extend(retry(ATTEMPTS, || Package::find(store, &query))?);
+	}
+	let packages: Vec<Package> = packages
+		.iter()
+		.filter(|p| changed.contains(p.name.as_str()))
+		.cloned()
+		.collect();
+	let primary = config.architectures.first().map(String::as_str) == Some(arch);
+	sync_repo(store, &mut known, repo, arch, primary, packages)?;
+
+	// Every package is in the store now, so findings can be attached
+	let mut grouped: HashMap<(String, &str), Vec<Finding>> = HashMap::new();
+	for (wanted, name, finding) in findings {
+		warn!("{}", finding.message);
+		if let Some(id) = find_id(store, &known, &wanted)? {
+			grouped.entry((id, name)).or_default().push(finding);
+		}
+	}
+	for ((id, name), findings) in grouped {
+		record_findings(store, &id, name, findings)?;
+	}
+
+	// A database that's re-served with a fresh date but the same packages
+	// still hasn't changed
+	if repository.changed.is_none() || versions != repository.versions {
+		repository.changed = Some(autovet_core::timestamp());
+	}
+	repository.url = url;
+	repository.etag = validators.etag;
+	repository.last_modified = validators.last_modified;
+	repository.timestamp = timestamp.max(repository.timestamp);
+	repository.versions = versions;
+	finish(store, config, &mut repository, &label)
+}
+
+/// Check whether the database is frozen, then record that it was synchronized.
+fn finish(
+	store: &dyn Store,
+	config: &PacmanConfig,
+	repository: &mut Repository,
+	label: &str,
+) -> Result<()> {
+	let now = autovet_core::timestamp();
+	if let Some(alert) = config
+		.max_database_age
+		.and_then(|max_age| rollback::frozen(repository, now, max_age, label))
+	{
+		warn!("{}", alert.message);
+		add_alert(repository, alert);
+	}
+	repository.last_sync = Some(now);
+	save(store, repository)
+}
+
+/// Record a problem with a repository's database, unless it's already known.
+fn add_alert(repository: &mut Repository, alert: Finding) {
+	if !repository.alerts.contains(&alert) {
+		repository.alerts.push(alert);
+	}
+}
+
+fn save(store: &dyn Store, repository: &mut Repository) -> Result<()> {
+	match retry(ATTEMPTS, || repository.update(store)) {
+		// Another poller recorded the same state
+		Err(e) if e.is_conflict() => Ok(()),
+		result => result,
+	}
+}
+
+/// Synchronize package metadata from the configured repositories of a pacman
+/// mirror, for each configured architecture.
+pub fn sync(store: &dyn Store, config: &PacmanConfig) -> Result<()> {
+	let keyring = config.keyring.as_deref().map(Keyring::load).transpose()?;
+
+	// One database failing shouldn't hold back the others
+	let mut failed = Vec::new();
+	for repo in &config.repos {
+		for arch in &config.architectures {
+			if let Err(e) = sync_database(store, config, keyring.as_ref(), repo, arch) {
+				error!("Failed to synchronize {} for {}: {}", repo, arch, e);
+				failed.push(format!("{}/{}", repo, arch));
+			}
+		}
+	}
+
+	if failed.is_empty() {
+		Ok(())
+	} else {
+		Err(Error::Incomplete(failed))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::analysis::Analysis;
+	use autovet_core::store::sqlite::SqliteStore;
+	use autovet_testing::{public_keys, sign};
+	use flate2::{write::GzEncoder, Compression};
+	use pgp::composed::SignedSecretKey;
+	use sha2::Digest;
+	use std::time::{Duration, UNIX_EPOCH};
+	use std::{fs, path::Path};
+
+	fn generate_key() -> SignedSecretKey {
+		autovet_testing::generate_key("Packager <packager@example.com>")
+	}
+
+	/// Write an x86_64 database with the given packages (name and version),
+	/// signed by the given key.
+	fn write_repo(root: &Path, key: &SignedSecretKey, packages: &[(&str, &str)]) -> Result<()> {
+		write_database(root, "x86_64", key, packages)
+	}
+
+	/// Write the database of the given architecture, whose packages are all
+	/// built for that architecture.
+	fn write_database(
+		root: &Path,
+		arch: &str,
+		key: &SignedSecretKey,
+		packages: &[(&str, &str)],
+	) -> Result<()> {
+		let descs: Vec<String> = packages
+			.iter()
+			.map(|(name, version)| desc(name, version, arch, &format!("{name}-{version}")))
+			.collect();
+		write_descs(&root.join("core/os").join(arch), key, &descs)
+	}
+
+	/// A package description whose sha256 sum is that of the given content.
+	fn desc(name: &str, version: &str, arch: &str, content: &str) -> String {
+		let sha256sum = hex::encode(sha2::Sha256::digest(content.as_bytes()));
+		format!(
+			"%FILENAME%\n{name}-{version}-x86_64.pkg.tar.zst\n\n%NAME%\n{name}\n\n%VERSION%\n{version}\n\n%ARCH%\n{arch}\n\n%SHA256SUM%\n{sha256sum}\n"
+		)
+	}
+
+	/// Write a signed database to the given directory.
+	fn write_descs(base: &Path, key: &SignedSecretKey, descs: &[String]) -> Result<()> {
+		fs::create_dir_all(base)?;
+
+		let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+		for (i, desc) in descs.iter().enumerate() {
+			let mut header = tar::Header::new_gnu();
+			header.set_size(desc.len() as u64);
+			header.set_mode(0o644);
+			builder.append_data(&mut header, format!("{i}/desc"), desc.as_bytes())?;
+		}
+		let db = builder.into_inner()?.finish()?;
+
+		fs::write(base.join("core.db.sig"), sign(key, &db))?;
+		fs::write(base.join("core.db.tar.gz"), &db)?;
+		Ok(())
+	}
+
+	fn find(store: &dyn Store, name: &str, version: &str) -> Result<Package> {
+		let mut found = Package::find(
+			store,
+			&PackageQuery {
+				name: Some(name.to_string()),
+				version: Some(version.to_string()),
+				..Default::default()
+			},
+		)?;
+		assert_eq!(found.len(), 1);
+		Ok(found.remove(0))
+	}
+
+	#[test]
+	fn test_sync_signed_database() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		let packager = generate_key();
+		let keyring = root.path().join("keyring.gpg");
+		fs::write(&keyring, public_keys(&[&packager]))?;
+
+		let config = PacmanConfig {
+			mirror: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			repos: vec![String::from("core")],
+			keyring: Some(keyring),
+			..Default::default()
+		};
+		let store = SqliteStore::open_in_memory()?;
+
+		// A database signed by someone else is ignored and reported
+		write_repo(root.path(), &generate_key(), &[("grep", "3.7-1")])?;
+		sync(&store, &config)?;
+		assert!(Package::find(&store, &PackageQuery::default())?.is_empty());
+		let repository = Repository::find(&store, PackageChannel::Pacman, "core", Some("x86_64"))?;
+		assert_eq!(repository.alerts.len(), 1);
+		assert_eq!(repository.alerts[0].severity, FindingSeverity::Critical);
+		assert!(repository.etag.is_none());
+		assert!(repository.last_sync.is_some());
+
+		write_repo(root.path(), &packager, &[("grep", "3.7-1")])?;
+		sync(&store, &config)?;
+		let packages = Package::find(&store, &PackageQuery::default())?;
+		assert_eq!(packages.len(), 1);
+		assert_eq!(packages[0].repo.as_deref(), Some("core"));
+		Ok(())
+	}
+
+	#[test]
+	fn test_sync_incremental() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		let packager = generate_key();
+		let config = PacmanConfig {
+			mirror: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			repos: vec![String::from("core")],
+			..Default::default()
+		};
+		let store = SqliteStore::open_in_memory()?;
+
+		write_repo(
+			root.path(),
+			&packager,
+			&[("grep", "3.7-1"), ("sed", "4.8-1")],
+		)?;
+		sync(&store, &config)?;
+		assert_eq!(Package::find(&store, &PackageQuery::default())?.len(), 2);
+		let repository = Repository::find(&store, PackageChannel::Pacman, "core", Some("x86_64"))?;
+		assert!(repository.etag.is_some());
+		assert!(repository.last_sync.is_some());
+
+		// An unchanged database isn't downloaded again, so garbage with the same
+		// size and modification time goes unnoticed
+		let db = root.path().join("core/os/x86_64/core.db.tar.gz");
+		let (size, modified) = (fs::metadata(&db)?.len(), fs::metadata(&db)?.modified()?);
+		fs::write(&db, vec![0u8; size as usize])?;
+		fs::File::options()
+			.write(true)
+			.open(&db)?
+			.set_modified(modified)?;
+		sync(&store, &config)?;
+
+		// grep is upgraded and sed is removed from the repository
+		write_repo(root.path(), &packager, &[("grep", "3.8-1")])?;
+		sync(&store, &config)?;
+		let (old, new) = (
+			find(&store, "grep", "3.7-1")?,
+			find(&store, "grep", "3.8-1")?,
+		);
+		assert!(!old.yanked);
+		assert_eq!(new.supersedes, old._id);
+		assert_eq!(new.repo.as_deref(), Some("core"));
+
+		let sed = find(&store, "sed", "4.8-1")?;
+		assert!(sed.yanked);
+		assert_eq!(sed.url.as_deref(), Some("sed-4.8-1-x86_64.pkg.tar.zst"));
+
+		// Releases that come back are restored
+		write_repo(
+			root.path(),
+			&packager,
+			&[("grep", "3.8-1"), ("sed", "4.8-1")],
+		)?;
+		sync(&store, &config)?;
+		assert!(!find(&store, "sed", "4.8-1")?.yanked);
+		assert_eq!(Package::find(&store, &PackageQuery::default())?.len(), 3);
+
+		// A release that's pulled in favor of an older one is flagged
+		write_repo(
+			root.path(),
+			&packager,
+			&[("grep", "3.7-1"), ("sed", "4.8-1")],
+		)?;
+		sync(&store, &config)?;
+		assert!(find(&store, "grep", "3.8-1")?.yanked);
+		assert!(!find(&store, "grep", "3.7-1")?.yanked);
+		Ok(())
+	}
+
+	#[test]
+	fn test_changed_names() {
+		let package = |name: &str, version: &str| Package {
+			name: name.to_string(),
+			version: version.to_string(),
+			..Default::default()
+		};
+		let repository = Repository {
+			versions: [("grep", "3.7-1"), ("sed", "4.8-1"), ("tar", "1.34-1")]
+				.into_iter()
+				.map(|(name, version)| (name.to_string(), version.to_string()))
+				.collect(),
+			..Default::default()
+		};
+		let packages = vec![
+			package("grep", "3.7-1"),
+			package("sed", "4.9-1"),
+			package("zstd", "1.5.5-1"),
+		];
+		assert_eq!(
+			changed_names(&repository, &packages),
+			BTreeSet::from(["sed", "tar", "zstd"])
+		);
+	}
+
+	#[test]
+	fn test_sync_architectures() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		let packager = generate_key();
+		let config = PacmanConfig {
+			mirror: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			repos: vec![String::from("core")],
+			architectures: vec![String::from("x86_64"), String::from("aarch64")],
+			..Default::default()
+		};
+		let store = SqliteStore::open_in_memory()?;
+
+		write_database(root.path(), "x86_64", &packager, &[("grep", "3.7-1")])?;
+		write_database(root.path(), "aarch64", &packager, &[("grep", "3.7-1")])?;
+		sync(&store, &config)?;
+		assert_eq!(Package::find(&store, &PackageQuery::default())?.len(), 2);
+
+		// Dropping the aarch64 build leaves the x86_64 one alone
+		write_database(root.path(), "aarch64", &packager, &[])?;
+		sync(&store, &config)?;
+		let packages = Package::find(&store, &PackageQuery::default())?;
+		for package in packages {
+			assert_eq!(package.yanked, package.arch.as_deref() == Some("aarch64"));
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn test_sync_missing_database() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		let packager = generate_key();
+		let config = PacmanConfig {
+			mirror: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			repos: vec![String::from("core")],
+			architectures: vec![String::from("aarch64"), String::from("x86_64")],
+			..Default::default()
+		};
+		let store = SqliteStore::open_in_memory()?;
+
+		// The missing aarch64 database doesn't stop the x86_64 one
+		write_database(root.path(), "x86_64", &packager, &[("grep", "3.7-1")])?;
+		match sync(&store, &config) {
+			Err(Error::Incomplete(failed)) => assert_eq!(failed, ["core/aarch64"]),
+			result => panic!("Unexpected result: {:?}", result),
+		}
+		find(&store, "grep", "3.7-1")?;
+		Ok(())
+	}
+
+	#[test]
+	fn test_sync_compare_mirrors() -> Result<()> {
+		let (primary, mirror) = (tempfile::tempdir()?, tempfile::tempdir()?);
+		let packager = generate_key();
+		let config = PacmanConfig {
+			mirror: reqwest::Url::from_directory_path(primary.path())
+				.unwrap()
+				.to_string(),
+			compare_mirrors: vec![
+				reqwest::Url::from_directory_path(mirror.path())
+					.unwrap()
+					.to_string(),
+				String::from("file:///nonexistent"),
+			],
+			repos: vec![String::from("core")],
+			..Default::default()
+		};
+		let store = SqliteStore::open_in_memory()?;
+
+		write_repo(
+			primary.path(),
+			&packager,
+			&[("grep", "3.7-1"), ("sed", "4.8-1"), ("gawk", "5.1.1-1")],
+		)?;
+		write_descs(
+			&mirror.path().join("core/os/x86_64"),
+			&packager,
+			&[
+				desc("grep", "3.7-1", "x86_64", "backdoored"),
+				desc("sed", "4.7-1", "x86_64", "sed-4.7-1"),
+				desc("gawk", "5.1.1-1", "x86_64", "gawk-5.1.1-1"),
+			],
+		)?;
+		sync(&store, &config)?;
+
+		let findings = |name, version| -> Result<Vec<Finding>> {
+			let package = find(&store, name, version)?;
+			Ok(Analysis::find(&store, package._id.as_deref().unwrap())?
+				.into_iter()
+				.flat_map(|analysis| analysis.findings)
+				.collect())
+		};
+		let grep = findings("grep", "3.7-1")?;
+		assert_eq!(grep.len(), 1);
+		assert_eq!(grep[0].severity, FindingSeverity::Critical);
+		let sed = findings("sed", "4.8-1")?;
+		assert_eq!(sed.len(), 1);
+		assert_eq!(sed[0].severity, FindingSeverity::Warning);
+		assert!(findings("gawk", "5.1.1-1")?.is_empty());
+
+		// Mirrors are compared again even though the primary didn't change
+		write_descs(
+			&mirror.path().join("core/os/x86_64"),
+			&packager,
+			&[desc("gawk", "5.1.1-1", "x86_64", "backdoored")],
+		)?;
+		sync(&store, &config)?;
+		let gawk = findings("gawk", "5.1.1-1")?;
+		assert_eq!(gawk.len(), 1);
+		assert_eq!(gawk[0].severity, FindingSeverity::Critical);
+		assert_eq!(findings("grep", "3.7-1")?.len(), 1);
+		Ok(())
+	}
+
+	#[test]
+	fn test_sync_rollback() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		let packager = generate_key();
+		let config = PacmanConfig {
+			mirror: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			repos: vec![String::from("core")],
+			..Default::default()
+		};
+		let store = SqliteStore::open_in_memory()?;
+		let base = root.path().join("core/os/x86_64");
+		// Publish a database with the given packages, last modified at the
+		// given time
+		let publish = |packages: &[(&str, &str)], modified: u64| -> Result<()> {
+			let descs: Vec<String> = packages
+				.iter()
+				.map(|(name, version)| desc(name, version, "x86_64", &format!("{name}-{version}")))
+				.collect();
+			write_descs(&base, &packager, &descs)?;
+			fs::File::options()
+				.write(true)
+				.open(base.join("core.db.tar.gz"))?
+				.set_modified(UNIX_EPOCH + Duration::from_secs(modified))?;
+			Ok(())
+		};
+		let alerts = || -> Result<Vec<Finding>> {
+			Ok(Repository::find(&store, PackageChannel::Pacman, "core", Some("x86_64"))?.alerts)
+		};
+
+		publish(&[("grep", "3.8-1"), ("sed", "4.8-1")], 200)?;
+		sync(&store, &config)?;
+		assert!(alerts()?.is_empty());
+
+		// A properly signed but older database is refused
+		publish(&[("grep", "3.7-1"), ("sed", "4.8-1")], 150)?;
+		sync(&store, &config)?;
+		let rolled_back = alerts()?;
+		assert_eq!(rolled_back.len(), 1);
+		assert!(rolled_back[0].message.contains("rolled back"));
+		assert!(!find(&store, "grep", "3.8-1")?.yanked);
+		assert!(Package::find(
+			&store,
+			&PackageQuery {
+				version: Some(String::from("3.7-1")),
+				..Default::default()
+			}
+		)?
+		.is_empty());
+
+		// A newer database that downgrades a package is flagged on the release
+		// it replaced
+		publish(&[("grep", "3.7-1"), ("sed", "4.8-1")], 300)?;
+		sync(&store, &config)?;
+		let grep = find(&store, "grep", "3.8-1")?;
+		let findings: Vec<Finding> = Analysis::find(&store, grep._id.as_deref().unwrap())?
+			.into_iter()
+			.filter(|analysis| analysis.name == rollback::ANALYSIS_NAME)
+			.flat_map(|analysis| analysis.findings)
+			.collect();
+		assert_eq!(findings.len(), 1);
+		assert_eq!(findings[0].severity, FindingSeverity::Critical);
+
+		// Moving the newest build to another repository isn't a rollback
+		publish(&[("sed", "4.8-1")], 400)?;
+		sync(&store, &config)?;
+		assert_eq!(alerts()?, rolled_back);
+		Ok(())
+	}
+
+	#[test]
+	fn test_sync_frozen() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		let packager = generate_key();
+		let config = PacmanConfig {
+			mirror: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			repos: vec![String::from("core")],
+			max_database_age: Some(3600),
+			..Default::default()
+		};
+		let store = SqliteStore::open_in_memory()?;
+		let repository =
+			|| Repository::find(&store, PackageChannel::Pacman, "core", Some("x86_64"));
+
+		write_repo(root.path(), &packager, &[("grep", "3.7-1")])?;
+		sync(&store, &config)?;
+		sync(&store, &config)?;
+		assert!(repository()?.alerts.is_empty());
+
+		// The same packages two hours later, re-served with a fresh date
+		let mut recorded = repository()?;
+		recorded.changed = recorded.changed.map(|changed| changed - 7200);
+		recorded.update(&store)?;
+		write_repo(root.path(), &packager, &[("grep", "3.7-1")])?;
+		sync(&store, &config)?;
+		let alerts = repository()?.alerts;
+		assert_eq!(alerts.len(), 1);
+		assert_eq!(alerts[0].severity, FindingSeverity::Warning);
+
+		// New packages thaw it
+		write_repo(root.path(), &packager, &[("grep", "3.8-1")])?;
+		sync(&store, &config)?;
+		assert!(
+			rollback::frozen(&repository()?, autovet_core::timestamp(), 3600, "core").is_none()
+		);
+		Ok(())
+	}
+}
@@ -0,0 +1,297 @@
+use crate::{
+	fetch::{fetch, ATTEMPTS},
+	sync::{current_packages, insert_new, is_known},
+};
+use autovet_core::{
+	config::PyPiConfig,
+	error::{retry, Result},
+	package::{Artifact, Package, PackageChannel},
+	store::Store,
+	version,
+};
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Deserialize)]
+struct ProjectResponse {
+	info: ProjectInfo,
+
+	/// The files of every release, keyed by version
+	#[serde(default)]
+	releases: HashMap<String, Vec<ReleaseFile>>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseResponse {
+	info: ProjectInfo,
+
+	/// The files of this release
+	#[serde(default)]
+	urls: Vec<ReleaseFile>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ProjectInfo {
+	name: String,
+	version: String,
+	summary: Option<String>,
+	home_page: Option<String>,
+	license: Option<String>,
+	author: Option<String>,
+	author_email: Option<String>,
+	maintainer: Option<String>,
+	maintainer_email: Option<String>,
+	requires_dist: Option<Vec<String>>,
+	requires_python: Option<String>,
+	project_urls: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseFile {
+	filename: String,
+	url: String,
+	packagetype: Option<String>,
+	size: Option<u64>,
+	#[serde(default)]
+	digests: HashMap<String, String>,
+}
+
+/// Normalize a project name as described by PEP 503.
+fn normalize(name: &str) -> String {
+	let mut normalized = String::new();
+	for c in name.chars() {
+		if matches!(c, '-' | '_' | '.') {
+			if !normalized.ends_with('-') {
+				normalized.push('-');
+			}
+		} else {
+			normalized.push(c.to_ascii_lowercase());
+		}
+	}
+	normalized
+}
+
+/// Join a name and email address the way PyPI displays them.
+fn person(name: Option<String>, email: Option<String>) -> Option<String> {
+	match (
+		name.filter(|s| !s.is_empty()),
+		email.filter(|s| !s.is_empty()),
+	) {
+		(Some(name), Some(email)) if !email.contains('<') => Some(format!("{} <{}>", name, email)),
+		(Some(name), _) => Some(name),
+		(None, email) => email,
+	}
+}
+
+impl From<ReleaseFile> for Artifact {
+	fn from(mut file: ReleaseFile) -> Self {
+		Artifact {
+			filename: file.filename,
+			url: file.url,
+			kind: file.packagetype,
+			size: file.size,
+			md5sum: file.digests.remove("md5"),
+			sha1sum: None,
+			sha256sum: file.digests.remove("sha256"),
+		}
+	}
+}
+
+impl From<ReleaseResponse> for Package {
+	fn from(release: ReleaseResponse) -> Self {
+		let info = release.info;
+		let mut package = Package {
+			channel: PackageChannel::PyPi,
+			name: normalize(&info.name),
+			version: info.version,
+			description: info.summary.filter(|s| !s.is_empty()),
+			license: info.license.filter(|s| !s.is_empty()).into_iter().collect(),
+			depends: info.requires_dist.unwrap_or_default(),
+			artifacts: release.urls.into_iter().map(Artifact::from).collect(),
+			..Default::default()
+		};
+
+		let project_urls = info.project_urls.unwrap_or_default();
+		package.upstream_url = info
+			.home_page
+			.filter(|s| !s.is_empty())
+			.or_else(|| project_urls.get("Homepage").cloned());
+
+		package.maintainers = [
+			person(info.author, info.author_email),
+			person(info.maintainer, info.maintainer_email),
+		]
+		.into_iter()
+		.flatten()
+		.collect();
+		package.maintainers.dedup();
+
+		if let Some(requires_python) = info.requires_python.filter(|s| !s.is_empty()) {
+			package
+				.extra
+				.insert(String::from("requires_python"), vec![requires_python]);
+		}
+		package
+	}
+}
+
+fn fetch_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T> {
+	let body = retry(ATTEMPTS, || fetch(url))?;
+	Ok(serde_json::from_reader(body)?)
+}
+
+/// Synchronize the releases of the configured projects from the index's JSON
+/// API.
+pub fn sync(store: &dyn Store, config: &PyPiConfig) -> Result<()> {
+	let mut current_packages = current_packages(store, PackageChannel::PyPi)?;
+	let index = config.index.trim_end_matches('/');
+
+	for project in &config.projects {
+		let name = normalize(project);
+		let response: ProjectResponse = match fetch_json(&format!("{}/pypi/{}/json", index, name)) {
+			Ok(response) => response,
+			Err(e) => {
+				warn!("Failed to fetch project {}: {}", project, e);
+				continue;
+			}
+		};
+		let name = normalize(&response.info.name);
+
+		// Insert the oldest releases first so each can be linked to its
+		// predecessor
+		let mut releases: Vec<(String, Vec<ReleaseFile>)> = response.releases.into_iter().collect();
+		version::sort(PackageChannel::PyPi, &mut releases, |(version, _)| version);
+
+		let mut count = 0;
+		for (version, files) in releases {
+			// Releases without files have nothing to analyze
+			if files.is_empty() || is_known(&current_packages, &name, &version, &None) {
+				continue;
+			}
+
+			// The project response only describes the latest release
+			let release: ReleaseResponse =
+				match fetch_json(&format!("{}/pypi/{}/{}/json", index, name, version)) {
+					Ok(release) => release,
+					Err(e) => {
+						warn!("Failed to fetch release {} of {}: {}", version, name, e);
+						continue;
+					}
+				};
+
+			if insert_new(store, &mut current_packages, Package::from(release))? {
+				count += 1;
+			}
+		}
+		info!("Found {} new releases of {}", count, name);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::store::{sqlite::SqliteStore, PackageQuery};
+	use serde_json::json;
+	use std::{fs, path::Path};
+
+	fn file(version: &str, packagetype: &str, extension: &str) -> serde_json::Value {
+		json!({
+			"filename": format!("demo-{}{}", version, extension),
+			"url": format!("https://files.example/demo-{}{}", version, extension),
+			"packagetype": packagetype,
+			"size": 1024,
+			"digests": {
+				"md5": "d41d8cd98f00b204e9800998ecf8427e",
+				"sha256": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+			}
+		})
+	}
+
+	/// Lay out a stand-in for the JSON API in the given directory.
+	fn write_index(root: &Path) -> Result<()> {
+		let project = root.join("pypi/demo");
+		fs::create_dir_all(&project)?;
+
+		let info = |version: &str| {
+			json!({
+				"name": "Demo",
+				"version": version,
+				"summary": "A demo project",
+				"author": "Jane Doe",
+				"author_email": "jane@example.com",
+				"requires_dist": ["requests>=2"],
+			})
+		};
+		let files = |version: &str| {
+			vec![
+				file(version, "sdist", ".tar.gz"),
+				file(version, "bdist_wheel", "-py3-none-any.whl"),
+			]
+		};
+
+		fs::write(
+			project.join("json"),
+			json!({
+				"info": info("1.1"),
+				"releases": {
+					"1.1": files("1.1"),
+					"1.0": files("1.0"),
+					"1.2.dev0": [],
+					// The release's own JSON is missing
+					"2.0": files("2.0")
+				}
+			})
+			.to_string(),
+		)?;
+		for version in ["1.0", "1.1"] {
+			fs::create_dir_all(project.join(version))?;
+			fs::write(
+				project.join(version).join("json"),
+				json!({ "info": info(version), "urls": files(version) }).to_string(),
+			)?;
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn test_sync_local_index() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		write_index(root.path())?;
+
+		let config = PyPiConfig {
+			index: reqwest::Url::from_directory_path(root.path())
+				.unwrap()
+				.to_string(),
+			projects: vec![String::from("Demo")],
+		};
+
+		let store = SqliteStore::open_in_memory()?;
+		sync(&store, &config)?;
+		sync(&store, &config)?;
+
+		let mut packages = Package::find(&store, &PackageQuery::default())?;
+		packages.sort_by(|a, b| a.version.cmp(&b.version));
+		assert_eq!(packages.len(), 2);
+
+		let package = &packages[1];
+		assert_eq!(package.name, "demo");
+		assert_eq!(package.version, "1.1");
+		assert_eq!(package.maintainers, vec!["Jane Doe <jane@example.com>"]);
+		assert_eq!(package.depends, vec!["requests>=2"]);
+		assert_eq!(package.artifacts.len(), 2);
+		assert_eq!(package.artifacts[1].kind.as_deref(), Some("bdist_wheel"));
+		assert!(package.artifacts[1].sha256sum.is_some());
+		assert_eq!(package.supersedes, packages[0]._id);
+		Ok(())
+	}
+
+	#[test]
+	fn test_normalize() {
+		assert_eq!(normalize("Friendly-Bard"), "friendly-bard");
+		assert_eq!(normalize("FRIENDLY._-bard"), "friendly-bard");
+	}
+}
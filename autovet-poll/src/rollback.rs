@@ -0,0 +1,174 @@
+//! Detecting mirrors that serve stale or downgraded repository databases.
+//!
+//! A mirror can't forge a signed database, but it can keep serving an old one
+//! (a freeze) or go back to an older one (a rollback) to withhold security
+//! updates. Each database's `Last-Modified` date and package versions, and when
+//! those versions last changed, are recorded so both can be noticed.
+
+use autovet_core::{
+	analysis::{Finding, FindingSeverity},
+	package::{Package, PackageChannel},
+	repository::Repository,
+	version,
+};
+use std::{cmp::Ordering, collections::BTreeMap, time::UNIX_EPOCH};
+
+/// The name of the analyses that hold downgrade findings.
+pub const ANALYSIS_NAME: &str = "rollback";
+
+/// A package whose version went backwards between two synchronizations.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Downgrade {
+	pub name: String,
+
+	pub arch: Option<String>,
+
+	/// The version in the previous database
+	pub from: String,
+
+	/// The version in the current database
+	pub to: String,
+}
+
+impl Downgrade {
+	pub fn finding(&self, label: &str) -> Finding {
+		Finding {
+			severity: FindingSeverity::Critical,
+			message: format!(
+				"{} went back from {} {} to {}",
+				label, self.name, self.from, self.to
+			),
+		}
+	}
+}
+
+/// When a database was last modified, from its `Last-Modified` header. Build
+/// dates can't be used instead: the newest package may move to another
+/// repository, which makes a database look older than it is.
+pub fn timestamp(last_modified: Option<&str>) -> Option<u64> {
+	let modified = httpdate::parse_http_date(last_modified?).ok()?;
+	modified
+		.duration_since(UNIX_EPOCH)
+		.ok()
+		.map(|duration| duration.as_secs())
+}
+
+/// The version of each package in a database.
+pub fn versions(packages: &[Package]) -> BTreeMap<String, String> {
+	packages
+		.iter()
+		.map(|package| (package.name.clone(), package.version.clone()))
+		.collect()
+}
+
+/// Check whether a database is older than the one that was last recorded.
+pub fn rolled_back(
+	repository: &Repository,
+	timestamp: Option<u64>,
+	label: &str,
+) -> Option<Finding> {
+	match (repository.timestamp, timestamp) {
+		(Some(recorded), Some(timestamp)) if timestamp < recorded => Some(Finding {
+			severity: FindingSeverity::Critical,
+			message: format!(
+				"{} was rolled back to {} (previously {})",
+				label, timestamp, recorded
+			),
+		}),
+		_ => None,
+	}
+}
+
+/// Find packages whose version is older than the last recorded one. Packages
+/// that were missing from the previous database were removed in between, so
+/// they may come back with any version.
+pub fn downgrades(repository: &Repository, packages: &[Package]) -> Vec<Downgrade> {
+	packages
+		.iter()
+		.filter_map(|package| {
+			let previous = repository.versions.get(&package.name)?;
+			match version::compare(PackageChannel::Pacman, &package.version, previous) {
+				Some(Ordering::Less) => Some(Downgrade {
+					name: package.name.clone(),
+					arch: package.arch.clone(),
+					from: previous.clone(),
+					to: package.version.clone(),
+				}),
+				_ => None,
+			}
+		})
+		.collect()
+}
+
+/// Check whether a database's package versions haven't changed for longer
+/// than they should.
+pub fn frozen(repository: &Repository, now: u64, max_age: u64, label: &str) -> Option<Finding> {
+	let changed = repository.changed?;
+	if now.saturating_sub(changed) <= max_age {
+		return None;
+	}
+	Some(Finding {
+		severity: FindingSeverity::Warning,
+		message: format!("{} hasn't changed since {}", label, changed),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn package(name: &str, version: &str) -> Package {
+		Package {
+			channel: PackageChannel::Pacman,
+			name: name.to_string(),
+			version: version.to_string(),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_timestamp() {
+		assert_eq!(
+			timestamp(Some("Sun, 06 Nov 1994 08:49:37 GMT")),
+			Some(784111777)
+		);
+		assert_eq!(timestamp(Some("yesterday")), None);
+		assert_eq!(timestamp(None), None);
+	}
+
+	#[test]
+	fn test_rollback() {
+		let previous = [package("grep", "3.8-1"), package("sed", "4.8-1")];
+		let repository = Repository {
+			timestamp: Some(200),
+			versions: versions(&previous),
+			changed: Some(200),
+			..Default::default()
+		};
+
+		// An older database
+		assert!(rolled_back(&repository, Some(150), "core").is_some());
+		assert!(rolled_back(&repository, Some(200), "core").is_none());
+		assert!(rolled_back(&repository, None, "core").is_none());
+
+		// A newer database that still downgrades a package
+		let current = [
+			package("grep", "3.7-1"),
+			package("sed", "4.8-1"),
+			package("gawk", "5.1.1-1"),
+		];
+		assert!(rolled_back(&repository, Some(300), "core").is_none());
+		assert_eq!(
+			downgrades(&repository, &current),
+			vec![Downgrade {
+				name: String::from("grep"),
+				arch: None,
+				from: String::from("3.8-1"),
+				to: String::from("3.7-1"),
+			}]
+		);
+
+		assert!(frozen(&repository, 250, 100, "core").is_none());
+		assert!(frozen(&repository, 400, 100, "core").is_some());
+	}
+}
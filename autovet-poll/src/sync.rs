@@ -0,0 +1,151 @@
+//! Recording newly discovered packages in the store.
+
+use crate::fetch::ATTEMPTS;
+use autovet_core::{
+	analysis::{Analysis, Finding},
+	error::{retry, Result},
+	package::{Package, PackageChannel},
+	repository::Repository,
+	store::{PackageQuery, Store},
+};
+
+/// List the packages of the given channel that are already in the store.
+/// Only the fields needed to detect new versions are loaded.
+pub fn current_packages(store: &dyn Store, channel: PackageChannel) -> Result<Vec<Package>> {
+	retry(ATTEMPTS, || {
+		Package::find(
+			store,
+			&PackageQuery {
+				channel: Some(channel),
+				fields: Some(vec![
+					"_id".into(),
+					"name".into(),
+					"version".into(),
+					"arch".into(),
+					"yanked".into(),
+					"channel".into(),
+				]),
+				limit: Some(1000000),
+				..Default::default()
+			},
+		)
+	})
+}
+
+/// Whether the given version of a package is already in the store.
+pub fn is_known(
+	current_packages: &[Package],
+	name: &str,
+	version: &str,
+	arch: &Option<String>,
+) -> bool {
+	current_packages
+		.iter()
+		.any(|p| p.name == name && p.version == version && &p.arch == arch)
+}
+
+/// Insert the given package unless the same version is already known, linking
+/// it to the version it supersedes. Returns whether the package was new.
+pub fn insert_new(
+	store: &dyn Store,
+	current_packages: &mut Vec<Package>,
+	mut package: Package,
+) -> Result<bool> {
+	if is_known(
+		current_packages,
+		&package.name,
+		&package.version,
+		&package.arch,
+	) {
+		return Ok(false);
+	}
+
+	package.supersedes = package
+		.predecessor_in(current_packages.iter())
+		.and_then(|predecessor| predecessor._id.clone());
+
+	match retry(ATTEMPTS, || package.update(store)) {
+		// Another poller already inserted this package
+		Err(e) if e.is_conflict() => {}
+		result => result?,
+	}
+
+	current_packages.push(package);
+	Ok(true)
+}
+
+/// When the given channel was last synchronized successfully (seconds since
+/// the epoch).
+pub fn last_sync(store: &dyn Store, channel: PackageChannel) -> Result<Option<u64>> {
+	retry(ATTEMPTS, || Repository::find(store, channel, "", None))
+		.map(|repository| repository.last_sync)
+}
+
+/// Record that the given channel was just synchronized successfully.
+pub fn record_sync(store: &dyn Store, channel: PackageChannel) -> Result<()> {
+	let mut repository = retry(ATTEMPTS, || Repository::find(store, channel, "", None))?;
+	repository.last_sync = Some(autovet_core::timestamp());
+	match retry(ATTEMPTS, || repository.update(store)) {
+		// Another poller finished at the same time
+		Err(e) if e.is_conflict() => Ok(()),
+		result => result,
+	}
+}
+
+/// Record findings the poller made about a package as an analysis with the
+/// given name, skipping any that an earlier run already recorded.
+pub fn record_findings(
+	store: &dyn Store,
+	package_id: &str,
+	name: &str,
+	findings: Vec<Finding>,
+) -> Result<()> {
+	let existing = retry(ATTEMPTS, || Analysis::find(store, package_id))?;
+	let findings: Vec<Finding> = findings
+		.into_iter()
+		.filter(|finding| {
+			!existing
+				.iter()
+				.filter(|analysis| analysis.name == name)
+				.any(|analysis| analysis.findings.contains(finding))
+		})
+		.collect();
+	if findings.is_empty() {
+		return Ok(());
+	}
+
+	let now = autovet_core::timestamp();
+	let mut analysis = Analysis {
+		package_id: package_id.to_string(),
+		name: name.to_string(),
+		progress: 100,
+		start_time: now,
+		end_time: now,
+		findings,
+		..Default::default()
+	};
+	retry(ATTEMPTS, || analysis.update(store))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::analysis::FindingSeverity;
+	use autovet_core::store::sqlite::SqliteStore;
+
+	#[test]
+	fn test_record_findings() -> Result<()> {
+		let store = SqliteStore::open_in_memory()?;
+		let finding = || Finding {
+			severity: FindingSeverity::Critical,
+			message: String::from("tampered"),
+		};
+
+		record_findings(&store, "grep", "mirrors", vec![finding()])?;
+		record_findings(&store, "grep", "mirrors", vec![finding()])?;
+		let analyses = Analysis::find(&store, "grep")?;
+		assert_eq!(analyses.len(), 1);
+		assert_eq!(analyses[0].findings, vec![finding()]);
+		Ok(())
+	}
+}
@@ -0,0 +1,42 @@
+//! Throwaway OpenPGP keys and signatures for the tests of the crates that
+//! verify signatures.
+
+use pgp::composed::{
+	DetachedSignature, KeyType, SecretKeyParamsBuilder, SignedPublicKey, SignedSecretKey,
+};
+use pgp::crypto::hash::HashAlgorithm;
+use pgp::ser::Serialize;
+use pgp::types::Password;
+
+pub fn generate_key(user_id: &str) -> SignedSecretKey {
+	SecretKeyParamsBuilder::default()
+		.key_type(KeyType::Ed25519Legacy)
+		.can_certify(true)
+		.can_sign(true)
+		.primary_user_id(user_id.into())
+		.build()
+		.unwrap()
+		.generate(rand::thread_rng())
+		.unwrap()
+}
+
+/// A binary detached signature over the given data.
+pub fn sign(key: &SignedSecretKey, data: &[u8]) -> Vec<u8> {
+	DetachedSignature::sign_binary_data(
+		rand::thread_rng(),
+		&key.primary_key,
+		&Password::empty(),
+		HashAlgorithm::Sha256,
+		data,
+	)
+	.unwrap()
+	.to_bytes()
+	.unwrap()
+}
+
+/// A binary keyring holding the public parts of the given keys.
+pub fn public_keys(keys: &[&SignedSecretKey]) -> Vec<u8> {
+	keys.iter()
+		.flat_map(|key| SignedPublicKey::from((*key).clone()).to_bytes().unwrap())
+		.collect()
+}
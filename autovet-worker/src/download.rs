@@ -0,0 +1,300 @@
+//! Downloading package files into a content-addressed cache.
+//!
+//! Files are only stored once their checksums have been verified, as
+//! `<cache>/sha256/<hex>` with a hard link at `<cache>/md5/<hex>` so packages
+//! that only publish an MD5 sum are found too. Files that are only described
+//! by a Subresource Integrity string (npm) are downloaded every time, since
+//! the cache can't be searched by SHA-512. Downloads are written to a
+//! temporary file in the cache and renamed into place, so workers sharing the
+//! cache never see a partial file.
+
+use autovet_core::config::Config;
+use autovet_core::error::{Error, Result};
+use autovet_core::package::Package;
+use base64::Engine;
+use log::{debug, info};
+use md5::Md5;
+use reqwest::Url;
+use sha2::{Digest, Sha256, Sha512};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+pub struct Cache {
+	root: PathBuf,
+}
+
+/// The hashes of a downloaded file as lowercase hex.
+struct Hashes {
+	sha256: String,
+
+	md5: String,
+
+	sha512: String,
+}
+
+/// Open the given URL. `file://` URLs are read from the local filesystem.
+pub fn open(url: &str) -> Result<Box<dyn Read>> {
+	if url.starts_with("file://") {
+		let path = match Url::parse(url).map(|url| url.to_file_path()) {
+			Ok(Ok(path)) => path,
+			_ => return Err(Error::Config(format!("Invalid file URL: {}", url))),
+		};
+		return Ok(Box::new(File::open(path)?));
+	}
+
+	let rs = reqwest::blocking::get(url)?;
+	if rs.status().is_success() {
+		Ok(Box::new(rs))
+	} else {
+		Err(Error::Status {
+			status: rs.status(),
+			url: url.to_string(),
+		})
+	}
+}
+
+fn verify(url: &str, expected: Option<&str>, actual: &str) -> Result<()> {
+	match expected {
+		Some(expected) if !expected.eq_ignore_ascii_case(actual) => Err(Error::Checksum {
+			name: url.to_string(),
+			expected: expected.to_string(),
+			actual: actual.to_string(),
+		}),
+		_ => Ok(()),
+	}
+}
+
+impl Cache {
+	pub fn open(root: &Path) -> Result<Cache> {
+		fs::create_dir_all(root.join("sha256"))?;
+		fs::create_dir_all(root.join("md5"))?;
+		Ok(Cache {
+			root: root.to_path_buf(),
+		})
+	}
+
+	/// Path of the cached file with the given checksum. The checksum must be
+	/// lowercase hex of the algorithm's length, so it can't name anything
+	/// outside the cache.
+	fn path(&self, algorithm: &str, hash: &str) -> Result<PathBuf> {
+		let length = match algorithm {
+			"sha256" => 64,
+			"md5" => 32,
+			_ => {
+				return Err(Error::Parse(format!(
+					"Unknown checksum algorithm: {}",
+					algorithm
+				)))
+			}
+		};
+		if hash.len() != length || !hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+			return Err(Error::Parse(format!(
+				"Invalid {} checksum: {:?}",
+				algorithm, hash
+			)));
+		}
+		Ok(self.root.join(algorithm).join(hash))
+	}
+
+	/// Get the file at the given URL, downloading it only if no file with the
+	/// expected checksum is cached. At least one checksum is required.
+	pub fn fetch(&self, url: &str, sha256: Option<&str>, md5: Option<&str>) -> Result<PathBuf> {
+		let cached = match (sha256, md5) {
+			(Some(sha256), _) => self.path("sha256", sha256)?,
+			(None, Some(md5)) => self.path("md5", md5)?,
+			(None, None) => {
+				return Err(Error::Parse(format!(
+					"No checksum to verify {} against",
+					url
+				)))
+			}
+		};
+		if cached.is_file() {
+			debug!("Found {} in the cache", url);
+			return Ok(cached);
+		}
+
+		self.download(url, |hashes| {
+			verify(url, sha256, &hashes.sha256)?;
+			verify(url, md5, &hashes.md5)
+		})
+	}
+
+	/// Get the file at the given URL, verified against a Subresource Integrity
+	/// string such as `sha512-<base64>`.
+	pub fn fetch_integrity(&self, url: &str, integrity: &str) -> Result<PathBuf> {
+		let expected = match integrity.split_once('-') {
+			Some(("sha512", digest)) => base64::engine::general_purpose::STANDARD
+				.decode(digest)
+				.map(hex::encode)
+				.map_err(|_| Error::Parse(format!("Invalid integrity: {}", integrity)))?,
+			_ => {
+				return Err(Error::Parse(format!(
+					"Unsupported integrity: {}",
+					integrity
+				)))
+			}
+		};
+
+		self.download(url, |hashes| verify(url, Some(&expected), &hashes.sha512))
+	}
+
+	/// Download the file at the given URL into the cache once `check` accepts
+	/// its hashes.
+	fn download<F>(&self, url: &str, check: F) -> Result<PathBuf>
+	where
+		F: FnOnce(&Hashes) -> Result<()>,
+	{
+		info!("Downloading {}", url);
+		let mut body = open(url)?;
+		let mut file = tempfile::NamedTempFile::new_in(&self.root)?;
+		let (mut sha256_hasher, mut md5_hasher, mut sha512_hasher) =
+			(Sha256::new(), Md5::new(), Sha512::new());
+		let mut buffer = [0u8; 64 * 1024];
+		loop {
+			let count = body.read(&mut buffer)?;
+			if count == 0 {
+				break;
+			}
+			sha256_hasher.update(&buffer[..count]);
+			md5_hasher.update(&buffer[..count]);
+			sha512_hasher.update(&buffer[..count]);
+			file.write_all(&buffer[..count])?;
+		}
+
+		let hashes = Hashes {
+			sha256: hex::encode(sha256_hasher.finalize()),
+			md5: hex::encode(md5_hasher.finalize()),
+			sha512: hex::encode(sha512_hasher.finalize()),
+		};
+		check(&hashes)?;
+
+		let path = self.path("sha256", &hashes.sha256)?;
+		file.persist(&path).map_err(|e| Error::Io(e.error))?;
+		match fs::hard_link(&path, self.path("md5", &hashes.md5)?) {
+			Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(e.into()),
+			_ => {}
+		}
+		Ok(path)
+	}
+
+	/// Get the given package's file, verified against the checksums in its
+	/// record.
+	pub fn fetch_package(&self, package: &Package, config: &Config) -> Result<PathBuf> {
+		let url = package.download_url(config).ok_or_else(|| {
+			Error::Parse(format!(
+				"No download URL for {} {}",
+				package.name, package.version
+			))
+		})?;
+		match (package.download_checksums(), package.integrity.as_deref()) {
+			((None, None), Some(integrity)) => self.fetch_integrity(&url, integrity),
+			((sha256, md5), _) => self.fetch(&url, sha256, md5),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CONTENT: &[u8] = b"hello\n";
+	const SHA256: &str = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+	const MD5: &str = "b1946ac92492d2347c6235b4d2611184";
+	const SHA512: &str = "sha512-58IrmUxZ2c8rSOVJseJGZmNgRZMNPafBrLKZ0cO3+TH5Sq5B7dosKyB6NuEPi8uNRSI+VIePWzFufOO2vAGWKQ==";
+
+	#[test]
+	fn test_fetch() -> Result<()> {
+		let source = tempfile::tempdir()?;
+		let cache = tempfile::tempdir()?;
+		let cache = Cache::open(cache.path())?;
+
+		let file = source.path().join("hello.txt");
+		fs::write(&file, CONTENT)?;
+		let url = Url::from_file_path(&file).unwrap().to_string();
+
+		let path = cache.fetch(&url, Some(SHA256), None)?;
+		assert_eq!(fs::read(&path)?, CONTENT);
+		assert_eq!(path, cache.path("sha256", SHA256)?);
+
+		// Later requests are served from the cache, by either checksum
+		fs::remove_file(&file)?;
+		assert_eq!(cache.fetch(&url, Some(SHA256), Some(MD5))?, path);
+		assert_eq!(fs::read(cache.fetch(&url, None, Some(MD5))?)?, CONTENT);
+		Ok(())
+	}
+
+	#[test]
+	fn test_fetch_mismatch() -> Result<()> {
+		let source = tempfile::tempdir()?;
+		let cache = tempfile::tempdir()?;
+		let cache = Cache::open(cache.path())?;
+
+		let file = source.path().join("hello.txt");
+		fs::write(&file, b"tampered\n")?;
+		let url = Url::from_file_path(&file).unwrap().to_string();
+
+		match cache.fetch(&url, Some(SHA256), Some(MD5)) {
+			Err(Error::Checksum { expected, .. }) => assert_eq!(expected, SHA256),
+			result => panic!("Unexpected result: {:?}", result),
+		}
+		assert!(cache.fetch(&url, None, None).is_err());
+		assert_eq!(fs::read_dir(cache.root.join("sha256"))?.count(), 0);
+		Ok(())
+	}
+
+	#[test]
+	fn test_fetch_package() -> Result<()> {
+		let source = tempfile::tempdir()?;
+		let cache = tempfile::tempdir()?;
+		let cache = Cache::open(cache.path())?;
+
+		let file = source.path().join("demo-1.0.0.tgz");
+		fs::write(&file, CONTENT)?;
+		let mut package = Package {
+			channel: autovet_core::package::PackageChannel::Npm,
+			url: Some(Url::from_file_path(&file).unwrap().to_string()),
+			integrity: Some(String::from(SHA512)),
+			..Default::default()
+		};
+
+		let path = cache.fetch_package(&package, &Config::default())?;
+		assert_eq!(path, cache.path("sha256", SHA256)?);
+
+		package.integrity = Some(SHA512.replace('v', "w"));
+		assert!(matches!(
+			cache.fetch_package(&package, &Config::default()),
+			Err(Error::Checksum { .. })
+		));
+
+		package.integrity = Some(String::from("sha1-8a8N9hhfYXL8dGhpOCX6Z/NdoSs="));
+		assert!(matches!(
+			cache.fetch_package(&package, &Config::default()),
+			Err(Error::Parse(_))
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn test_fetch_invalid_checksum() -> Result<()> {
+		let cache = tempfile::tempdir()?;
+		let cache = Cache::open(cache.path())?;
+
+		let url = "file:///nonexistent";
+		for (sha256, md5) in [
+			(Some("../../etc/passwd"), None),
+			(Some(&SHA256[1..]), None),
+			(Some(&*SHA256.to_uppercase()), None),
+			(None, Some("../sha256/x")),
+			(None, Some(&MD5[..31])),
+		] {
+			match cache.fetch(url, sha256, md5) {
+				Err(Error::Parse(_)) => {}
+				result => panic!("Unexpected result: {:?}", result),
+			}
+		}
+		assert_eq!(fs::read_dir(cache.root.join("sha256"))?.count(), 0);
+		Ok(())
+	}
+}
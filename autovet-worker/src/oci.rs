@@ -0,0 +1,388 @@
+//! Unpacks container images so their binaries can be analyzed like any other
+//! package's.
+//!
+//! Layers are applied bottom-up with the usual whiteout rules: a `.wh.<name>`
+//! entry deletes `<name>` from the lower layers and a `.wh..wh..opq` entry
+//! empties its directory. Only regular files, directories and links are
+//! created; device nodes and the like are skipped.
+
+use crate::extract::entry_path;
+use autovet_core::error::{Error, Result};
+use autovet_core::oci::ImageReference;
+use autovet_core::package::Package;
+use flate2::read::GzDecoder;
+use log::{debug, warn};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+/// The search path used when the image doesn't set one.
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Links are followed at most this many times when resolving a path.
+const MAX_LINKS: usize = 40;
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+const WHITEOUT_OPAQUE: &str = ".wh..wh..opq";
+
+/// Open a layer for reading according to its media type.
+fn decompress(kind: &str, file: File) -> Result<Box<dyn Read>> {
+	if kind.ends_with("gzip") {
+		Ok(Box::new(GzDecoder::new(file)))
+	} else if kind.ends_with("zstd") {
+		Ok(Box::new(zstd::Decoder::new(file)?))
+	} else if kind.ends_with("tar") {
+		Ok(Box::new(file))
+	} else {
+		Err(Error::Parse(format!("Unsupported layer type: {}", kind)))
+	}
+}
+
+/// Remove whatever is at the given path, if anything.
+fn remove(path: &Path) -> Result<()> {
+	match fs::symlink_metadata(path) {
+		Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+		Ok(_) => fs::remove_file(path)?,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+		Err(e) => return Err(e.into()),
+	}
+	Ok(())
+}
+
+/// Apply one layer on top of the given root.
+fn apply_layer(layer: impl Read, rootfs: &Path) -> Result<()> {
+	let mut archive = tar::Archive::new(layer);
+
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		let path = match entry_path(&entry.path()?) {
+			Some(path) => path,
+			None => {
+				warn!("Skipping layer entry outside the root: {:?}", entry.path()?);
+				continue;
+			}
+		};
+
+		let name = match path.file_name() {
+			Some(name) => name.to_os_string(),
+			None => continue,
+		};
+
+		// Lower layers may have turned a parent directory into a link, so
+		// resolve it without leaving the root before deleting anything
+		let parent = resolve(rootfs, path.parent().unwrap_or_else(|| Path::new("")));
+
+		if name == WHITEOUT_OPAQUE {
+			if let Some(parent) = parent.filter(|parent| parent.is_dir()) {
+				for child in fs::read_dir(&parent)? {
+					remove(&child?.path())?;
+				}
+			}
+			continue;
+		}
+		if let Some(hidden) = name.to_string_lossy().strip_prefix(WHITEOUT_PREFIX) {
+			match parent {
+				_ if hidden.is_empty() || hidden == "." || hidden == ".." => {
+					warn!("Skipping invalid whiteout: {:?}", path)
+				}
+				Some(parent) => remove(&parent.join(hidden))?,
+				None => {}
+			}
+			continue;
+		}
+
+		let kind = entry.header().entry_type();
+		if !(kind.is_file() || kind.is_dir() || kind.is_symlink() || kind.is_hard_link()) {
+			debug!("Skipping special file: {:?}", path);
+			continue;
+		}
+
+		// Replace what the lower layers put here rather than writing through
+		// it, including links to directories
+		if let Some(parent) = parent {
+			let target = parent.join(&name);
+			let is_dir = fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.is_dir());
+			if !(kind.is_dir() && is_dir) {
+				remove(&target)?;
+			}
+		}
+		entry.unpack_in(rootfs)?;
+	}
+	Ok(())
+}
+
+/// Download the image's layers and apply them to the given root.
+pub fn unpack(package: &Package, rootfs: &Path) -> Result<()> {
+	let reference = ImageReference::parse(&package.name)?;
+
+	for layer in &package.artifacts {
+		let mut file = tempfile::tempfile()?;
+		reference.source.download_blob(&layer.filename, &mut file)?;
+		file.seek(SeekFrom::Start(0))?;
+
+		let kind = layer.kind.as_deref().unwrap_or("tar");
+		apply_layer(decompress(kind, file)?, rootfs)?;
+	}
+	Ok(())
+}
+
+/// Resolve a path inside the root, following links without leaving it.
+fn resolve(rootfs: &Path, path: &Path) -> Option<PathBuf> {
+	let mut resolved = PathBuf::new();
+	let mut pending: Vec<PathBuf> = path
+		.components()
+		.rev()
+		.map(|component| PathBuf::from(component.as_os_str()))
+		.collect();
+
+	let mut links = 0;
+	while let Some(component) = pending.pop() {
+		match component.components().next()? {
+			Component::RootDir => resolved = PathBuf::new(),
+			Component::ParentDir => {
+				resolved.pop();
+			}
+			Component::Normal(name) => {
+				resolved.push(name);
+				let full = rootfs.join(&resolved);
+				if fs::symlink_metadata(&full).ok()?.file_type().is_symlink() {
+					links += 1;
+					if links > MAX_LINKS {
+						return None;
+					}
+					resolved.pop();
+					pending.extend(
+						fs::read_link(&full)
+							.ok()?
+							.components()
+							.rev()
+							.map(|component| PathBuf::from(component.as_os_str())),
+					);
+				}
+			}
+			Component::CurDir | Component::Prefix(_) => {}
+		}
+	}
+	Some(rootfs.join(resolved))
+}
+
+fn is_elf(path: &Path) -> bool {
+	let mut magic = [0u8; 4];
+	File::open(path)
+		.and_then(|mut file| file.read_exact(&mut magic))
+		.is_ok()
+		&& &magic == b"\x7fELF"
+}
+
+/// Find the program the entrypoint runs. Scripts are represented by their
+/// interpreter.
+fn resolve_entrypoint(rootfs: &Path, entrypoint: &[String], env: &[String]) -> Option<PathBuf> {
+	let program = entrypoint.first()?;
+	let path = if program.contains('/') {
+		resolve(rootfs, Path::new(program))?
+	} else {
+		let search = env
+			.iter()
+			.find_map(|variable| variable.strip_prefix("PATH="))
+			.unwrap_or(DEFAULT_PATH);
+		search
+			.split(':')
+			.filter_map(|dir| resolve(rootfs, &Path::new(dir).join(program)))
+			.find(|path| path.is_file())?
+	};
+
+	if is_elf(&path) {
+		return Some(path);
+	}
+
+	// Follow a shebang line to the interpreter
+	let mut head = [0u8; 256];
+	let count = File::open(&path).ok()?.read(&mut head).ok()?;
+	let line = head[..count].strip_prefix(b"#!")?;
+	let line = String::from_utf8_lossy(line.split(|b| *b == b'\n').next()?).to_string();
+	let interpreter: Vec<String> = line.split_whitespace().map(String::from).collect();
+
+	// The interpreter itself may be `/usr/bin/env <name>`
+	match interpreter.as_slice() {
+		[env_program, name, ..] if env_program.ends_with("/env") => {
+			resolve_entrypoint(rootfs, std::slice::from_ref(name), env)
+		}
+		[program, ..] => resolve(rootfs, Path::new(program)).filter(|path| is_elf(path)),
+		[] => None,
+	}
+}
+
+fn find_elves(dir: &Path, elves: &mut BTreeSet<PathBuf>) -> Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		let kind = entry.file_type()?;
+		if kind.is_dir() {
+			find_elves(&entry.path(), elves)?;
+		} else if kind.is_file() && is_elf(&entry.path()) {
+			elves.insert(entry.path());
+		}
+	}
+	Ok(())
+}
+
+/// List the binaries in an unpacked image that should be analyzed, starting
+/// with the entrypoint.
+pub fn targets(rootfs: &Path, entrypoint: &[String], env: &[String]) -> Result<Vec<PathBuf>> {
+	let mut elves = BTreeSet::new();
+	find_elves(rootfs, &mut elves)?;
+
+	let mut targets = Vec::new();
+	match resolve_entrypoint(rootfs, entrypoint, env) {
+		Some(entrypoint) => {
+			elves.remove(&entrypoint);
+			targets.push(entrypoint);
+		}
+		None if !entrypoint.is_empty() => warn!("Failed to resolve entrypoint: {:?}", entrypoint),
+		None => {}
+	}
+	targets.extend(elves);
+	Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::package::Artifact;
+	use flate2::{write::GzEncoder, Compression};
+	use serde_json::json;
+	use sha2::{Digest, Sha256};
+
+	const ELF: &[u8] = b"\x7fELF\x02\x01\x01\x00";
+
+	/// Build a gzipped layer from (path, content or link target, is link).
+	/// Paths ending in `/` are directories.
+	fn layer(entries: &[(&str, &[u8], bool)]) -> Result<Vec<u8>> {
+		let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+		for (path, content, link) in entries {
+			let mut header = tar::Header::new_gnu();
+			header.set_mode(0o755);
+			if *link {
+				header.set_entry_type(tar::EntryType::Symlink);
+				header.set_size(0);
+				builder.append_link(&mut header, path, std::str::from_utf8(content).unwrap())?;
+			} else {
+				if path.ends_with('/') {
+					header.set_entry_type(tar::EntryType::Directory);
+				}
+				header.set_size(content.len() as u64);
+				builder.append_data(&mut header, path, *content)?;
+			}
+		}
+		Ok(builder.into_inner()?.finish()?)
+	}
+
+	/// Write a layout holding the given layers and describe it as a package.
+	fn image(root: &Path, layers: Vec<Vec<u8>>) -> Result<Package> {
+		fs::create_dir_all(root.join("blobs/sha256"))?;
+		fs::write(
+			root.join("index.json"),
+			json!({"schemaVersion": 2, "manifests": []}).to_string(),
+		)?;
+
+		let mut package = Package {
+			name: format!("oci:{}", root.display()),
+			..Default::default()
+		};
+		for content in layers {
+			let hex = hex::encode(Sha256::digest(&content));
+			fs::write(root.join("blobs/sha256").join(&hex), &content)?;
+			package.artifacts.push(Artifact {
+				filename: format!("sha256:{}", hex),
+				kind: Some(String::from("application/vnd.oci.image.layer.v1.tar+gzip")),
+				..Default::default()
+			});
+		}
+		Ok(package)
+	}
+
+	#[test]
+	fn test_unpack_and_find_targets() -> Result<()> {
+		let layout = tempfile::tempdir()?;
+		let package = image(
+			layout.path(),
+			vec![
+				layer(&[
+					("usr/bin/demo", ELF, false),
+					("usr/bin/sh", ELF, false),
+					("usr/lib/libdemo.so", ELF, false),
+					("bin", b"usr/bin", true),
+					("etc/secret", ELF, false),
+					("opt/data/old", ELF, false),
+					("escape", b"../../../../usr/bin", true),
+				])?,
+				layer(&[
+					("etc/.wh.secret", b"", false),
+					("opt/data/.wh..wh..opq", b"", false),
+					("opt/data/new", b"data", false),
+					("entrypoint.sh", b"#!/bin/sh\nexec demo\n", false),
+				])?,
+			],
+		)?;
+
+		let rootfs = tempfile::tempdir()?;
+		unpack(&package, rootfs.path())?;
+		assert!(!rootfs.path().join("etc/secret").exists());
+		assert!(!rootfs.path().join("opt/data/old").exists());
+		assert!(rootfs.path().join("opt/data/new").exists());
+
+		// The entrypoint is found through the PATH and the /bin link
+		let env = vec![String::from("PATH=/bin")];
+		let found = targets(rootfs.path(), &[String::from("demo")], &env)?;
+		assert_eq!(
+			found,
+			vec![
+				rootfs.path().join("usr/bin/demo"),
+				rootfs.path().join("usr/bin/sh"),
+				rootfs.path().join("usr/lib/libdemo.so"),
+			]
+		);
+
+		// Scripts are represented by their interpreter
+		let found = targets(rootfs.path(), &[String::from("/entrypoint.sh")], &env)?;
+		assert_eq!(found[0], rootfs.path().join("usr/bin/sh"));
+
+		// Links can't leave the root
+		assert_eq!(
+			resolve(rootfs.path(), Path::new("/escape/demo")),
+			Some(rootfs.path().join("usr/bin/demo"))
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_whiteouts_stay_inside_the_root() -> Result<()> {
+		let outside = tempfile::tempdir()?;
+		fs::create_dir(outside.path().join("etc"))?;
+		fs::write(outside.path().join("etc/passwd"), b"root")?;
+		fs::write(outside.path().join("etc/shadow"), b"root")?;
+		let target = outside.path().join("etc").to_string_lossy().to_string();
+
+		let layout = tempfile::tempdir()?;
+		let package = image(
+			layout.path(),
+			vec![
+				layer(&[("etc", target.as_bytes(), true)])?,
+				layer(&[
+					("etc/.wh.passwd", b"", false),
+					("etc/.wh..wh..opq", b"", false),
+				])?,
+				// A directory replaces the link instead of being written through it
+				layer(&[("etc/", b"", false)])?,
+			],
+		)?;
+
+		let rootfs = tempfile::tempdir()?;
+		unpack(&package, rootfs.path())?;
+		assert!(outside.path().join("etc/passwd").exists());
+		assert!(outside.path().join("etc/shadow").exists());
+		assert!(fs::symlink_metadata(rootfs.path().join("etc"))?.is_dir());
+		Ok(())
+	}
+}
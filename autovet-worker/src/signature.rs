@@ -0,0 +1,206 @@
+//! Checking the detached signatures of downloaded pacman packages.
+
+use crate::download;
+use autovet_core::analysis::{Finding, FindingSeverity};
+use autovet_core::config::Config;
+use autovet_core::error::{Error, Result};
+use autovet_core::keyring::Keyring;
+use autovet_core::package::Package;
+use autovet_core::store::Store;
+use base64::Engine;
+use log::warn;
+use reqwest::StatusCode;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Get the package's signature, either from its record or from the `.sig`
+/// file published next to it.
+fn signature(package: &Package, config: &Config) -> Result<Option<Vec<u8>>> {
+	if let Some(pgpsig) = &package.pgpsig {
+		return base64::engine::general_purpose::STANDARD
+			.decode(pgpsig)
+			.map(Some)
+			.map_err(|e| Error::Signature(format!("Malformed signature: {}", e)));
+	}
+
+	let url = match package.download_url(config) {
+		Some(url) => format!("{}.sig", url),
+		None => return Ok(None),
+	};
+	let mut signature = Vec::new();
+	match download::open(&url) {
+		Ok(mut body) => body.read_to_end(&mut signature)?,
+		Err(Error::Status {
+			status: StatusCode::NOT_FOUND,
+			..
+		}) => return Ok(None),
+		Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+		Err(e) => return Err(e),
+	};
+	Ok(Some(signature))
+}
+
+fn critical(message: String) -> Finding {
+	Finding {
+		severity: FindingSeverity::Critical,
+		message,
+	}
+}
+
+/// Verify the signature over a downloaded package file and record the key
+/// that made it. Packages that are unsigned, badly signed or signed by a key
+/// that never signed an earlier version are critical.
+pub fn check(
+	store: &dyn Store,
+	keyring: &Keyring,
+	config: &Config,
+	package: &mut Package,
+	path: &Path,
+) -> Finding {
+	let signature = match signature(package, config) {
+		Ok(Some(signature)) => signature,
+		Ok(None) => {
+			return critical(format!(
+				"{} {} is not signed",
+				package.name, package.version
+			))
+		}
+		Err(e @ Error::Signature(_)) => return critical(e.to_string()),
+		Err(e) => {
+			return Finding {
+				severity: FindingSeverity::Warning,
+				message: format!("Failed to get signature: {}", e),
+			}
+		}
+	};
+
+	let fingerprint = match File::open(path)
+		.map_err(Error::from)
+		.and_then(|file| keyring.verify(file, &signature))
+	{
+		Ok(fingerprint) => fingerprint,
+		Err(e) => {
+			return critical(format!(
+				"{} {} failed signature verification: {}",
+				package.name, package.version, e
+			))
+		}
+	};
+	package.signing_key = Some(fingerprint.clone());
+
+	// Compare with the keys that signed earlier versions
+	let known: BTreeSet<String> = match package.history(store) {
+		Ok(history) => history
+			.into_iter()
+			.filter(|version| version._id != package._id)
+			.filter_map(|version| version.signing_key)
+			.collect(),
+		Err(e) => {
+			warn!("Failed to get history of {}: {}", package.name, e);
+			BTreeSet::new()
+		}
+	};
+	if !known.is_empty() && !known.contains(&fingerprint) {
+		return critical(format!(
+			"{} {} is signed by {}, which never signed it before (previously {})",
+			package.name,
+			package.version,
+			fingerprint,
+			known.into_iter().collect::<Vec<_>>().join(", ")
+		));
+	}
+
+	Finding {
+		severity: FindingSeverity::Info,
+		message: format!("Signed by {}", fingerprint),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use autovet_core::package::PackageChannel;
+	use autovet_core::store::sqlite::SqliteStore;
+	use autovet_testing::{generate_key, public_keys};
+	use pgp::composed::SignedSecretKey;
+	use std::fs;
+
+	/// A base64 signature, as found in a pacman database.
+	fn sign(key: &SignedSecretKey, data: &[u8]) -> String {
+		base64::engine::general_purpose::STANDARD.encode(autovet_testing::sign(key, data))
+	}
+
+	#[test]
+	fn test_check() -> Result<()> {
+		let (alice, bob) = (generate_key("Alice"), generate_key("Bob"));
+		let keyring = Keyring::from_reader(public_keys(&[&alice, &bob]).as_slice())?;
+
+		let root = tempfile::tempdir()?;
+		let path = root.path().join("grep-3.7-1-x86_64.pkg.tar.zst");
+		fs::write(&path, b"package")?;
+
+		let mut config = Config::default();
+		config.pacman.mirror = reqwest::Url::from_directory_path(root.path())
+			.unwrap()
+			.to_string();
+
+		let store = SqliteStore::open_in_memory()?;
+		let mut package = Package {
+			channel: PackageChannel::Pacman,
+			name: String::from("grep"),
+			version: String::from("3.7-1"),
+			url: Some(String::from("grep-3.7-1-x86_64.pkg.tar.zst")),
+			pgpsig: Some(sign(&alice, b"package")),
+			..Default::default()
+		};
+		let finding = check(&store, &keyring, &config, &mut package, &path);
+		assert_eq!(finding.severity, FindingSeverity::Info);
+		assert!(package.signing_key.is_some());
+		package.update(&store)?;
+
+		// Alice signed the versions either side of one signed by another key,
+		// but is only listed once
+		for (version, signing_key) in [("3.7-2", "0123456789ABCDEF"), ("3.7-3", "")] {
+			let mut rebuild = Package {
+				version: version.to_string(),
+				_id: None,
+				_rev: None,
+				..package.clone()
+			};
+			if !signing_key.is_empty() {
+				rebuild.signing_key = Some(signing_key.to_string());
+			}
+			rebuild.update(&store)?;
+		}
+		let alice_fingerprint = package.signing_key.clone().unwrap();
+
+		// The next version is signed by a different (but trusted) key
+		let mut next = Package {
+			version: String::from("3.8-1"),
+			pgpsig: Some(sign(&bob, b"package")),
+			signing_key: None,
+			_id: None,
+			_rev: None,
+			..package
+		};
+		let finding = check(&store, &keyring, &config, &mut next, &path);
+		assert_eq!(finding.severity, FindingSeverity::Critical);
+		assert!(finding.message.contains("never signed it before"));
+		assert_eq!(finding.message.matches(&alice_fingerprint).count(), 1);
+
+		// Tampered package
+		next.pgpsig = Some(sign(&alice, b"something else"));
+		let finding = check(&store, &keyring, &config, &mut next, &path);
+		assert_eq!(finding.severity, FindingSeverity::Critical);
+
+		// No signature in the record or on the mirror
+		next.pgpsig = None;
+		next.repo = Some(String::from("core"));
+		let finding = check(&store, &keyring, &config, &mut next, &path);
+		assert_eq!(finding.severity, FindingSeverity::Critical);
+		assert!(finding.message.contains("not signed"));
+		Ok(())
+	}
+}
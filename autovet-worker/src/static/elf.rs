@@ -0,0 +1,739 @@
+//! A reader for ELF files, so the analyzers don't depend on binutils being
+//! installed (or on the language its output is in).
+//!
+//! Both 32 and 64-bit files of either byte order are understood. Everything is
+//! read up front: program headers, section headers, symbols from the static
+//! and dynamic symbol tables, and the entries of the dynamic segment. The
+//! dynamic segment is found through the program headers, so it's available
+//! even when the section headers have been stripped.
+//!
+//! Addresses are the virtual addresses recorded in the file. Position
+//! independent binaries are linked at zero, so their addresses are offsets from
+//! wherever the loader eventually puts them.
+
+use simple_error::bail;
+use std::error::Error;
+use std::path::Path;
+
+/// A loadable segment
+pub const PT_LOAD: u32 = 1;
+
+/// The segment holding the dynamic linking entries
+pub const PT_DYNAMIC: u32 = 2;
+
+/// The segment naming the program interpreter
+pub const PT_INTERP: u32 = 3;
+
+/// Executable segment permission
+pub const PF_X: u32 = 1;
+
+/// A section with no content in the file, like `.bss`
+pub const SHT_NOBITS: u32 = 8;
+
+/// The static symbol table
+pub const SHT_SYMTAB: u32 = 2;
+
+/// The dynamic symbol table
+pub const SHT_DYNSYM: u32 = 11;
+
+/// Section flag for sections occupying memory at runtime
+pub const SHF_ALLOC: u64 = 2;
+
+/// Section flag for sections containing instructions
+pub const SHF_EXECINSTR: u64 = 4;
+
+/// A fixed address executable
+pub const ET_EXEC: u16 = 2;
+
+/// A shared object or position independent executable
+pub const ET_DYN: u16 = 3;
+
+/// The x86_64 machine type
+pub const EM_X86_64: u16 = 62;
+
+/// The last entry of the dynamic segment
+pub const DT_NULL: i64 = 0;
+
+/// A library the binary depends on
+pub const DT_NEEDED: i64 = 1;
+
+/// The address of the dynamic string table
+pub const DT_STRTAB: i64 = 5;
+
+/// The size of the dynamic string table
+pub const DT_STRSZ: i64 = 10;
+
+/// Additional flags for the dynamic linker
+pub const DT_FLAGS_1: i64 = 0x6ffffffb;
+
+/// The `DT_FLAGS_1` bit set by linkers on position independent executables
+pub const DF_1_PIE: u64 = 0x08000000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramHeader {
+	pub p_type: u32,
+	pub flags: u32,
+	pub offset: u64,
+	pub vaddr: u64,
+	pub filesz: u64,
+	pub memsz: u64,
+	pub align: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+	pub name: String,
+	pub sh_type: u32,
+	pub flags: u64,
+	pub addr: u64,
+	pub offset: u64,
+	pub size: u64,
+	pub link: u32,
+	pub entsize: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+	pub name: String,
+	pub value: u64,
+	pub size: u64,
+
+	/// The symbol type (`STT_*`) from the low bits of `st_info`
+	pub kind: u8,
+
+	/// The symbol binding (`STB_*`) from the high bits of `st_info`
+	pub binding: u8,
+
+	/// The index of the section the symbol is defined in, or zero if it's
+	/// undefined
+	pub section: u16,
+
+	/// Whether the symbol came from the dynamic symbol table
+	pub dynamic: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dynamic {
+	pub tag: i64,
+	pub value: u64,
+}
+
+/// A range of instructions to decode.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Code<'a> {
+	/// The section name, or a description of the segment
+	pub name: String,
+
+	/// The virtual address of the first byte
+	pub address: u64,
+
+	pub bytes: &'a [u8],
+}
+
+/// The offset of an entry in a table, saturating so that absurd values from a
+/// malformed file fail to read rather than overflow.
+fn entry_offset(table: u64, index: u64, size: u64) -> u64 {
+	table.saturating_add(index.saturating_mul(size))
+}
+
+/// Reads fields of the file's class and byte order.
+struct Reader<'a> {
+	data: &'a [u8],
+	is_64: bool,
+	little_endian: bool,
+}
+
+impl Reader<'_> {
+	fn bytes<const N: usize>(&self, offset: u64) -> Result<[u8; N], Box<dyn Error>> {
+		let start = usize::try_from(offset)?;
+		match start
+			.checked_add(N)
+			.and_then(|end| self.data.get(start..end))
+		{
+			Some(bytes) => Ok(bytes.try_into()?),
+			None => bail!("ELF file is truncated at offset {:#x}", offset),
+		}
+	}
+
+	fn u8(&self, offset: u64) -> Result<u8, Box<dyn Error>> {
+		Ok(self.bytes::<1>(offset)?[0])
+	}
+
+	fn u16(&self, offset: u64) -> Result<u16, Box<dyn Error>> {
+		let bytes = self.bytes(offset)?;
+		Ok(if self.little_endian {
+			u16::from_le_bytes(bytes)
+		} else {
+			u16::from_be_bytes(bytes)
+		})
+	}
+
+	fn u32(&self, offset: u64) -> Result<u32, Box<dyn Error>> {
+		let bytes = self.bytes(offset)?;
+		Ok(if self.little_endian {
+			u32::from_le_bytes(bytes)
+		} else {
+			u32::from_be_bytes(bytes)
+		})
+	}
+
+	fn u64(&self, offset: u64) -> Result<u64, Box<dyn Error>> {
+		let bytes = self.bytes(offset)?;
+		Ok(if self.little_endian {
+			u64::from_le_bytes(bytes)
+		} else {
+			u64::from_be_bytes(bytes)
+		})
+	}
+
+	/// Read an address or offset, whose size depends on the class.
+	fn word(&self, offset: u64) -> Result<u64, Box<dyn Error>> {
+		if self.is_64 {
+			self.u64(offset)
+		} else {
+			Ok(self.u32(offset)? as u64)
+		}
+	}
+
+	/// Read a signed word, which is sign extended for 32-bit files.
+	fn sword(&self, offset: u64) -> Result<i64, Box<dyn Error>> {
+		if self.is_64 {
+			Ok(self.u64(offset)? as i64)
+		} else {
+			Ok(self.u32(offset)? as i32 as i64)
+		}
+	}
+
+	/// Read a NUL terminated string from a string table.
+	fn string(&self, table: u64, size: u64, index: u32) -> String {
+		let start = table.saturating_add(index as u64);
+		let end = table.saturating_add(size).min(self.data.len() as u64);
+		if start >= end {
+			return String::new();
+		}
+
+		let bytes = &self.data[start as usize..end as usize];
+		let length = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+		String::from_utf8_lossy(&bytes[..length]).to_string()
+	}
+}
+
+/// A parsed ELF file.
+#[derive(Debug)]
+pub struct Elf {
+	pub is_64: bool,
+	pub little_endian: bool,
+
+	/// The file type (`ET_*`)
+	pub e_type: u16,
+
+	/// The machine type (`EM_*`)
+	pub machine: u16,
+
+	/// The address of the entry point, or zero if there is none
+	pub entry: u64,
+
+	pub program_headers: Vec<ProgramHeader>,
+
+	/// The section headers, which may be missing from stripped binaries
+	pub sections: Vec<Section>,
+
+	pub symbols: Vec<Symbol>,
+
+	pub dynamic: Vec<Dynamic>,
+
+	data: Vec<u8>,
+}
+
+impl Elf {
+	pub fn open(path: impl AsRef<Path>) -> Result<Elf, Box<dyn Error>> {
+		Elf::parse(std::fs::read(path)?)
+	}
+
+	pub fn parse(data: Vec<u8>) -> Result<Elf, Box<dyn Error>> {
+		if data.len() < 16 || &data[..4] != b"\x7fELF" {
+			bail!("Not an ELF file");
+		}
+
+		let reader = Reader {
+			data: &data,
+			is_64: match data[4] {
+				1 => false,
+				2 => true,
+				class => bail!("Unknown ELF class: {}", class),
+			},
+			little_endian: match data[5] {
+				1 => true,
+				2 => false,
+				encoding => bail!("Unknown ELF data encoding: {}", encoding),
+			},
+		};
+
+		// The header fields after the entry point are shifted by the word size
+		let w = if reader.is_64 { 8 } else { 4 };
+		let e_type = reader.u16(0x10)?;
+		let machine = reader.u16(0x12)?;
+		let entry = reader.word(0x18)?;
+		let phoff = reader.word(0x18 + w)?;
+		let shoff = reader.word(0x18 + 2 * w)?;
+		let phentsize = reader.u16(0x1c + 3 * w + 2)? as u64;
+		let phnum = reader.u16(0x1c + 3 * w + 4)? as u64;
+		let shentsize = reader.u16(0x1c + 3 * w + 6)? as u64;
+		let shnum = reader.u16(0x1c + 3 * w + 8)? as u64;
+		let shstrndx = reader.u16(0x1c + 3 * w + 10)? as u64;
+
+		let mut program_headers = Vec::new();
+		for i in 0..phnum {
+			let base = entry_offset(phoff, i, phentsize);
+			program_headers.push(if reader.is_64 {
+				ProgramHeader {
+					p_type: reader.u32(base)?,
+					flags: reader.u32(base + 4)?,
+					offset: reader.u64(base + 8)?,
+					vaddr: reader.u64(base + 16)?,
+					filesz: reader.u64(base + 32)?,
+					memsz: reader.u64(base + 40)?,
+					align: reader.u64(base + 48)?,
+				}
+			} else {
+				ProgramHeader {
+					p_type: reader.u32(base)?,
+					offset: reader.u32(base + 4)? as u64,
+					vaddr: reader.u32(base + 8)? as u64,
+					filesz: reader.u32(base + 16)? as u64,
+					memsz: reader.u32(base + 20)? as u64,
+					flags: reader.u32(base + 24)?,
+					align: reader.u32(base + 28)? as u64,
+				}
+			});
+		}
+
+		// Section names are resolved once the name table's location is known
+		let mut names = Vec::new();
+		let mut sections = Vec::new();
+		if shoff != 0 {
+			for i in 0..shnum {
+				let base = entry_offset(shoff, i, shentsize);
+				names.push(reader.u32(base)?);
+				sections.push(Section {
+					name: String::new(),
+					sh_type: reader.u32(base + 4)?,
+					flags: reader.word(base + 8)?,
+					addr: reader.word(base + 8 + w)?,
+					offset: reader.word(base + 8 + 2 * w)?,
+					size: reader.word(base + 8 + 3 * w)?,
+					link: reader.u32(base + 8 + 4 * w)?,
+					entsize: reader.word(base + 16 + 5 * w)?,
+				});
+			}
+		}
+		if let Some(table) = sections.get(shstrndx as usize).cloned() {
+			for (section, name) in sections.iter_mut().zip(names) {
+				section.name = reader.string(table.offset, table.size, name);
+			}
+		}
+
+		let mut symbols = Vec::new();
+		for section in &sections {
+			if section.sh_type != SHT_SYMTAB && section.sh_type != SHT_DYNSYM {
+				continue;
+			}
+			let strings = match sections.get(section.link as usize) {
+				Some(strings) => strings,
+				None => bail!("Symbol table {} has no string table", section.name),
+			};
+
+			let entsize = match section.entsize {
+				0 if reader.is_64 => 24,
+				0 => 16,
+				entsize => entsize,
+			};
+			for i in 0..section.size / entsize {
+				let base = entry_offset(section.offset, i, entsize);
+				let (name, value, size, info, shndx) = if reader.is_64 {
+					(
+						reader.u32(base)?,
+						reader.u64(base + 8)?,
+						reader.u64(base + 16)?,
+						reader.u8(base + 4)?,
+						reader.u16(base + 6)?,
+					)
+				} else {
+					(
+						reader.u32(base)?,
+						reader.u32(base + 4)? as u64,
+						reader.u32(base + 8)? as u64,
+						reader.u8(base + 12)?,
+						reader.u16(base + 14)?,
+					)
+				};
+				symbols.push(Symbol {
+					name: reader.string(strings.offset, strings.size, name),
+					value,
+					size,
+					kind: info & 0xf,
+					binding: info >> 4,
+					section: shndx,
+					dynamic: section.sh_type == SHT_DYNSYM,
+				});
+			}
+		}
+
+		let mut dynamic = Vec::new();
+		if let Some(segment) = program_headers.iter().find(|p| p.p_type == PT_DYNAMIC) {
+			for i in 0..segment.filesz / (2 * w) {
+				let base = entry_offset(segment.offset, i, 2 * w);
+				let entry = Dynamic {
+					tag: reader.sword(base)?,
+					value: reader.word(base + w)?,
+				};
+				if entry.tag == DT_NULL {
+					break;
+				}
+				dynamic.push(entry);
+			}
+		}
+
+		Ok(Elf {
+			is_64: reader.is_64,
+			little_endian: reader.little_endian,
+			e_type,
+			machine,
+			entry,
+			program_headers,
+			sections,
+			symbols,
+			dynamic,
+			data,
+		})
+	}
+
+	/// Whether this is a position independent executable rather than a fixed
+	/// address executable or a shared object.
+	pub fn is_pie(&self) -> bool {
+		self.e_type == ET_DYN
+			&& (self.program_headers.iter().any(|p| p.p_type == PT_INTERP)
+				|| self
+					.dynamic
+					.iter()
+					.any(|d| d.tag == DT_FLAGS_1 && d.value & DF_1_PIE != 0))
+	}
+
+	pub fn section(&self, name: &str) -> Option<&Section> {
+		self.sections.iter().find(|section| section.name == name)
+	}
+
+	/// Find the offset in the file that's loaded at the given virtual
+	/// address. Addresses that are only backed by zeroed memory (like `.bss`)
+	/// have no offset.
+	pub fn offset(&self, address: u64) -> Option<u64> {
+		self.program_headers
+			.iter()
+			.filter(|p| p.p_type == PT_LOAD)
+			.find(|p| address >= p.vaddr && address - p.vaddr < p.filesz)
+			.map(|p| p.offset + (address - p.vaddr))
+	}
+
+	/// Read the bytes loaded at the given virtual address.
+	pub fn read(&self, address: u64, length: usize) -> Option<&[u8]> {
+		self.slice(self.offset(address)?, length as u64)
+	}
+
+	fn slice(&self, offset: u64, size: u64) -> Option<&[u8]> {
+		let start = usize::try_from(offset).ok()?;
+		let end = usize::try_from(offset.checked_add(size)?).ok()?;
+		self.data.get(start..end)
+	}
+
+	/// The libraries named by `DT_NEEDED` entries.
+	pub fn needed(&self) -> Vec<String> {
+		let table = self
+			.dynamic
+			.iter()
+			.find(|d| d.tag == DT_STRTAB)
+			.and_then(|d| self.offset(d.value));
+		let size = self
+			.dynamic
+			.iter()
+			.find(|d| d.tag == DT_STRSZ)
+			.map_or(0, |d| d.value);
+
+		let reader = Reader {
+			data: &self.data,
+			is_64: self.is_64,
+			little_endian: self.little_endian,
+		};
+		match table {
+			Some(table) => self
+				.dynamic
+				.iter()
+				.filter(|d| d.tag == DT_NEEDED)
+				.map(|d| reader.string(table, size, d.value as u32))
+				.collect(),
+			None => Vec::new(),
+		}
+	}
+
+	/// The code to analyze: every executable section, or every executable
+	/// segment if the section headers have been stripped.
+	pub fn code(&self) -> Vec<Code<'_>> {
+		let sections: Vec<Code> = self
+			.sections
+			.iter()
+			.filter(|s| s.flags & SHF_EXECINSTR != 0 && s.flags & SHF_ALLOC != 0)
+			.filter(|s| s.sh_type != SHT_NOBITS)
+			.filter_map(|s| {
+				Some(Code {
+					name: s.name.clone(),
+					address: s.addr,
+					bytes: self.slice(s.offset, s.size)?,
+				})
+			})
+			.collect();
+		if !sections.is_empty() {
+			return sections;
+		}
+
+		self.program_headers
+			.iter()
+			.enumerate()
+			.filter(|(_, p)| p.p_type == PT_LOAD && p.flags & PF_X != 0)
+			.filter_map(|(i, p)| {
+				Some(Code {
+					name: format!("segment {}", i),
+					address: p.vaddr,
+					bytes: self.slice(p.offset, p.filesz)?,
+				})
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+
+	const SHT_PROGBITS: u32 = 1;
+	const SHT_STRTAB: u32 = 3;
+	const SHT_DYNAMIC: u32 = 6;
+
+	fn put(content: &mut Vec<u8>, offset: usize, bytes: &[u8]) {
+		if content.len() < offset + bytes.len() {
+			content.resize(offset + bytes.len(), 0);
+		}
+		content[offset..offset + bytes.len()].copy_from_slice(bytes);
+	}
+
+	/// Build a 64-bit x86_64 binary that runs the given code, which must have
+	/// been assembled at [`code_address`]. It has a `_start` symbol and needs
+	/// `libc.so.6`. Position independent binaries are linked at zero.
+	pub(crate) fn binary(code: &[u8], pie: bool, stripped: bool) -> Vec<u8> {
+		let base = if pie { 0 } else { 0x400000 };
+		let text = 64 + 2 * 56;
+		let dynstr_content = b"\0libc.so.6\0";
+		let dynstr = (text + code.len() + 7) & !7;
+		let dynamic = (dynstr + dynstr_content.len() + 7) & !7;
+		let mut entries = vec![
+			(DT_NEEDED, 1),
+			(DT_STRTAB, (base + dynstr) as u64),
+			(DT_STRSZ, dynstr_content.len() as u64),
+		];
+		if pie {
+			entries.push((DT_FLAGS_1, DF_1_PIE));
+		}
+		entries.push((DT_NULL, 0));
+		let strtab_content = b"\0_start\0";
+		let strtab = dynamic + entries.len() * 16;
+		let symtab = (strtab + strtab_content.len() + 7) & !7;
+		let shstrtab_content = b"\0.text\0.dynstr\0.dynamic\0.symtab\0.strtab\0.shstrtab\0";
+		let shstrtab = symtab + 2 * 24;
+		let shoff = (shstrtab + shstrtab_content.len() + 7) & !7;
+
+		let mut content = Vec::new();
+		put(&mut content, 0, b"\x7fELF\x02\x01\x01");
+		put(
+			&mut content,
+			0x10,
+			&(if pie { ET_DYN } else { ET_EXEC }).to_le_bytes(),
+		);
+		put(&mut content, 0x12, &EM_X86_64.to_le_bytes());
+		put(&mut content, 0x18, &((base + text) as u64).to_le_bytes());
+		put(&mut content, 0x20, &64u64.to_le_bytes());
+		put(&mut content, 0x34, &64u16.to_le_bytes());
+		put(&mut content, 0x36, &56u16.to_le_bytes());
+		put(&mut content, 0x38, &2u16.to_le_bytes());
+		if !stripped {
+			put(&mut content, 0x28, &(shoff as u64).to_le_bytes());
+			put(&mut content, 0x3a, &64u16.to_le_bytes());
+			put(&mut content, 0x3c, &7u16.to_le_bytes());
+			put(&mut content, 0x3e, &6u16.to_le_bytes());
+		}
+
+		let mut program_header =
+			|index: usize, p_type: u32, flags: u32, offset: usize, size: usize| {
+				let at = 64 + index * 56;
+				put(&mut content, at, &p_type.to_le_bytes());
+				put(&mut content, at + 4, &flags.to_le_bytes());
+				put(&mut content, at + 8, &(offset as u64).to_le_bytes());
+				put(
+					&mut content,
+					at + 16,
+					&((base + offset) as u64).to_le_bytes(),
+				);
+				put(&mut content, at + 32, &(size as u64).to_le_bytes());
+				put(&mut content, at + 40, &(size as u64).to_le_bytes());
+			};
+		program_header(0, PT_LOAD, PF_X | 4, 0, shoff);
+		program_header(1, PT_DYNAMIC, 4 | 2, dynamic, entries.len() * 16);
+
+		put(&mut content, text, code);
+		put(&mut content, dynstr, dynstr_content);
+		for (i, (tag, value)) in entries.iter().enumerate() {
+			put(&mut content, dynamic + i * 16, &tag.to_le_bytes());
+			put(&mut content, dynamic + i * 16 + 8, &value.to_le_bytes());
+		}
+		put(&mut content, strtab, strtab_content);
+		let symbol = symtab + 24;
+		put(&mut content, symbol, &1u32.to_le_bytes());
+		put(&mut content, symbol + 4, &[0x12]);
+		put(&mut content, symbol + 6, &1u16.to_le_bytes());
+		put(
+			&mut content,
+			symbol + 8,
+			&((base + text) as u64).to_le_bytes(),
+		);
+		put(
+			&mut content,
+			symbol + 16,
+			&(code.len() as u64).to_le_bytes(),
+		);
+		put(&mut content, shstrtab, shstrtab_content);
+		content.resize(shoff, 0);
+
+		if !stripped {
+			let sections: [(u32, u32, u64, usize, usize, u32, u64); 7] = [
+				(0, 0, 0, 0, 0, 0, 0),
+				(
+					1,
+					SHT_PROGBITS,
+					SHF_ALLOC | SHF_EXECINSTR,
+					text,
+					code.len(),
+					0,
+					0,
+				),
+				(7, SHT_STRTAB, SHF_ALLOC, dynstr, dynstr_content.len(), 0, 0),
+				(
+					15,
+					SHT_DYNAMIC,
+					SHF_ALLOC,
+					dynamic,
+					entries.len() * 16,
+					2,
+					16,
+				),
+				(24, SHT_SYMTAB, 0, symtab, 2 * 24, 5, 24),
+				(32, SHT_STRTAB, 0, strtab, strtab_content.len(), 0, 0),
+				(40, SHT_STRTAB, 0, shstrtab, shstrtab_content.len(), 0, 0),
+			];
+			for (i, (name, sh_type, flags, offset, size, link, entsize)) in
+				sections.into_iter().enumerate()
+			{
+				let at = shoff + i * 64;
+				let addr = if flags & SHF_ALLOC != 0 {
+					base + offset
+				} else {
+					0
+				};
+				put(&mut content, at, &name.to_le_bytes());
+				put(&mut content, at + 4, &sh_type.to_le_bytes());
+				put(&mut content, at + 8, &flags.to_le_bytes());
+				put(&mut content, at + 16, &(addr as u64).to_le_bytes());
+				put(&mut content, at + 24, &(offset as u64).to_le_bytes());
+				put(&mut content, at + 32, &(size as u64).to_le_bytes());
+				put(&mut content, at + 40, &link.to_le_bytes());
+				put(&mut content, at + 56, &entsize.to_le_bytes());
+			}
+		}
+		content
+	}
+
+	/// Where the code of a [`binary`] is loaded.
+	pub(crate) fn code_address(pie: bool) -> u64 {
+		if pie {
+			64 + 2 * 56
+		} else {
+			0x400000 + 64 + 2 * 56
+		}
+	}
+
+	#[test]
+	fn test_parse() -> Result<(), Box<dyn Error>> {
+		let code = [0xb8, 0x03, 0x00, 0x00, 0x00, 0x0f, 0x05];
+		let elf = Elf::parse(binary(&code, false, false))?;
+
+		assert!(elf.is_64 && elf.little_endian);
+		assert_eq!(elf.machine, EM_X86_64);
+		assert!(!elf.is_pie());
+		assert_eq!(elf.entry, code_address(false));
+		assert_eq!(elf.program_headers.len(), 2);
+		assert_eq!(elf.section(".text").unwrap().size, code.len() as u64);
+		assert_eq!(elf.needed(), vec![String::from("libc.so.6")]);
+
+		let start = elf.symbols.iter().find(|s| s.name == "_start").unwrap();
+		assert_eq!(start.value, elf.entry);
+		assert_eq!(start.kind, 2);
+		assert!(!start.dynamic);
+
+		assert_eq!(elf.offset(elf.entry), Some(64 + 2 * 56));
+		assert_eq!(elf.read(elf.entry, code.len()), Some(&code[..]));
+		assert_eq!(elf.offset(0x1000), None);
+
+		let regions = elf.code();
+		assert_eq!(regions.len(), 1);
+		assert_eq!(regions[0].name, ".text");
+		assert_eq!(regions[0].address, elf.entry);
+		assert_eq!(regions[0].bytes, &code[..]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_pie() -> Result<(), Box<dyn Error>> {
+		let code = [0x0f, 0x05];
+		let elf = Elf::parse(binary(&code, true, false))?;
+
+		assert!(elf.is_pie());
+		assert_eq!(elf.entry, code_address(true));
+		assert_eq!(elf.read(elf.entry, code.len()), Some(&code[..]));
+		assert_eq!(elf.needed(), vec![String::from("libc.so.6")]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_stripped() -> Result<(), Box<dyn Error>> {
+		let code = [0x0f, 0x05];
+		let elf = Elf::parse(binary(&code, true, true))?;
+
+		// Only the program headers are left
+		assert!(elf.sections.is_empty());
+		assert!(elf.symbols.is_empty());
+		assert!(elf.is_pie());
+		assert_eq!(elf.needed(), vec![String::from("libc.so.6")]);
+
+		let regions = elf.code();
+		assert_eq!(regions.len(), 1);
+		assert_eq!(regions[0].name, "segment 0");
+		assert_eq!(regions[0].address, 0);
+		assert_eq!(elf.read(elf.entry, code.len()), Some(&code[..]));
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_invalid() {
+		assert!(Elf::parse(b"#!/bin/sh\n".to_vec()).is_err());
+
+		let mut truncated = binary(&[0x0f, 0x05], false, false);
+		truncated.truncate(100);
+		assert!(Elf::parse(truncated).is_err());
+	}
+}
@@ -1 +1,2 @@
+pub mod elf;
 pub mod x86_64;
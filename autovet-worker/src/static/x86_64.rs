@@ -1,324 +1,873 @@
 //! This module attempts to discover all reachable syscalls in a program by decoding
 //! instructions and analyzing the execution flow.
 //!
+//! - Instructions are decoded on demand at the addresses execution reaches, so data
+//!   mixed in with code doesn't throw off decoding.
 //! - When a conditional jump is encountered, the analyzer forks and follows both paths.
-//! - If an instruction is visited twice in a particular thread, the thread completes to
-//!   avoid infinite loops.
+//! - Direct jumps and calls are followed to their targets, and calls record a return
+//!   address for the matching `ret`. Calls through registers or memory can't be
+//!   followed, so they're assumed to return with the caller-saved registers changed.
+//! - Paths wait in a worklist rather than on the call stack. A path completes when it
+//!   reaches an instruction that was already visited with the same syscall number in
+//!   RAX, which prevents infinite loops without missing code that's reached again with
+//!   a different syscall number.
+//! - Registers hold a known value, one of a few values, or an unknown value. Memory
+//!   isn't tracked, and instructions that aren't understood make the registers they
+//!   write unknown. A syscall whose number in RAX is unknown is reported as
+//!   unresolved rather than guessed at.
+//! - Exploration stops early when it runs out of instructions, paths or time (see
+//!   [`Budget`]), in which case the [`Report`] says it was truncated.
 //!
 
+use super::elf::{Code, Elf, EM_X86_64};
+use autovet_core::config::WorkerConfig;
 use autovet_core::Syscall;
 use autovet_core::SyscallType;
-use iced_x86::{Decoder, DecoderOptions, Instruction, Mnemonic, OpKind, Register};
+use iced_x86::{
+	Code as OpCode, Decoder, DecoderOptions, FlowControl, Instruction, InstructionInfoFactory,
+	Mnemonic, OpAccess, OpKind, Register,
+};
 use log::{debug, trace};
 use simple_error::bail;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::default::Default;
 use std::error::Error;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::Cursor;
-use std::io::Read;
-use std::os::unix::prelude::FileExt;
-use std::process::Command;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Limits on how much work goes into exploring a single binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+	/// Instructions emulated across every path
+	pub instructions: u64,
+
+	/// Paths explored, including the first
+	pub paths: u64,
+
+	pub time: Duration,
+}
+
+impl Default for Budget {
+	fn default() -> Self {
+		Budget::from(&WorkerConfig::default())
+	}
+}
+
+impl From<&WorkerConfig> for Budget {
+	fn from(config: &WorkerConfig) -> Self {
+		Budget {
+			instructions: config.max_instructions,
+			paths: config.max_paths,
+			time: Duration::from_secs(config.analysis_timeout),
+		}
+	}
+}
+
+/// The part of a [`Budget`] that ran out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+	Instructions,
+	Paths,
+	Time,
+}
+
+impl fmt::Display for Limit {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Limit::Instructions => write!(f, "instruction budget"),
+			Limit::Paths => write!(f, "path budget"),
+			Limit::Time => write!(f, "time budget"),
+		}
+	}
+}
+
+/// The syscalls found in a binary, and how thoroughly it was explored.
+#[derive(Debug, Default)]
+pub struct Report {
+	pub syscalls: Vec<Syscall>,
+
+	/// The addresses of syscalls whose number couldn't be determined
+	pub unresolved: Vec<u64>,
+
+	/// Instructions emulated across every path
+	pub instructions: u64,
+
+	/// Paths explored, including the first
+	pub paths: u64,
+
+	/// The limit that cut exploration short. Syscalls that are only reachable
+	/// from unexplored paths are missing.
+	pub truncated: Option<Limit>,
+}
+
+impl Report {
+	pub fn is_exhaustive(&self) -> bool {
+		self.truncated.is_none()
+	}
+
+	/// Describe how much of the binary was explored.
+	pub fn coverage(&self) -> String {
+		let work = format!("{} instructions on {} paths", self.instructions, self.paths);
+		match self.truncated {
+			None => format!("exhaustive, {}", work),
+			Some(limit) => format!("truncated by the {} after {}", limit, work),
+		}
+	}
+}
+
+/// The most values a register is tracked with before it's considered unknown.
+const MAX_VALUES: usize = 16;
+
+/// What's known about the value of a register.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+enum Value {
+	Known(u64),
+
+	/// One of a few values, like the result of a conditional move
+	Set(BTreeSet<u64>),
+
+	#[default]
+	Unknown,
+}
+
+impl Value {
+	fn from_values(values: BTreeSet<u64>) -> Value {
+		match values.len() {
+			1 => Value::Known(values.into_iter().next().unwrap_or_default()),
+			0 => Value::Unknown,
+			n if n <= MAX_VALUES => Value::Set(values),
+			_ => Value::Unknown,
+		}
+	}
+
+	/// The possible values, if they're known.
+	fn values(&self) -> Option<BTreeSet<u64>> {
+		match self {
+			Value::Known(value) => Some(BTreeSet::from([*value])),
+			Value::Set(values) => Some(values.clone()),
+			Value::Unknown => None,
+		}
+	}
+
+	fn map(&self, f: impl Fn(u64) -> u64) -> Value {
+		match self.values() {
+			Some(values) => Value::from_values(values.into_iter().map(f).collect()),
+			None => Value::Unknown,
+		}
+	}
+
+	/// Apply an operation to every combination of this value and another.
+	fn combine(&self, other: &Value, f: impl Fn(u64, u64) -> u64) -> Value {
+		match (self.values(), other.values()) {
+			(Some(a), Some(b)) if a.len() * b.len() <= MAX_VALUES * MAX_VALUES => {
+				let f = &f;
+				Value::from_values(
+					a.iter()
+						.flat_map(|&a| b.iter().map(move |&b| f(a, b)))
+						.collect(),
+				)
+			}
+			_ => Value::Unknown,
+		}
+	}
+
+	/// The value that's either this one or the other.
+	fn union(&self, other: &Value) -> Value {
+		match (self.values(), other.values()) {
+			(Some(a), Some(b)) => Value::from_values(a.union(&b).copied().collect()),
+			_ => Value::Unknown,
+		}
+	}
+}
+
+/// Sign extend the low `bits` of a value to 64 bits.
+fn sign_extend(value: u64, bits: u32) -> u64 {
+	let shift = 64 - bits;
+	(((value << shift) as i64) >> shift) as u64
+}
+
+/// The width of a register in bits.
+fn width(register: Register) -> u32 {
+	register.size() as u32 * 8
+}
+
+/// The low `bits` of a value.
+fn truncate(value: u64, bits: u32) -> u64 {
+	if bits >= 64 {
+		value
+	} else {
+		value & ((1 << bits) - 1)
+	}
+}
+
+/// Whether the register is one of the legacy high byte registers.
+fn is_high_byte(register: Register) -> bool {
+	matches!(
+		register,
+		Register::AH | Register::BH | Register::CH | Register::DH
+	)
+}
 
 #[derive(Default, Clone)]
 struct RegisterState {
-	pub rax: u64,
-	pub rbx: u64,
-	pub rcx: u64,
-	pub rdx: u64,
-	pub rsi: u64,
-	pub rdi: u64,
-	pub rbp: u64,
-	pub rsp: u64,
-	pub rip: usize,
-	pub r8: u64,
-	pub r9: u64,
-	pub r10: u64,
-	pub r11: u64,
-	pub r12: u64,
-	pub r13: u64,
-	pub r14: u64,
-	pub r15: u64,
-
-	pub stack: Vec<u64>,
+	pub rax: Value,
+	pub rbx: Value,
+	pub rcx: Value,
+	pub rdx: Value,
+	pub rsi: Value,
+	pub rdi: Value,
+	pub rbp: Value,
+	pub rsp: Value,
+	pub rip: u64,
+	pub r8: Value,
+	pub r9: Value,
+	pub r10: Value,
+	pub r11: Value,
+	pub r12: Value,
+	pub r13: Value,
+	pub r14: Value,
+	pub r15: Value,
+
+	pub stack: Vec<Value>,
+
+	/// The return addresses of the calls being emulated, innermost last
+	pub returns: Vec<u64>,
 }
 
+/// What decides whether a state was already explored: the address, and the
+/// syscall number in RAX that code from there may end up using.
+type Key = (u64, Value);
+
 impl RegisterState {
-	fn set(&mut self, register: Register, value: u64) {
-		trace!("Setting {:?} to {}", register, value);
-		match register {
-			Register::RAX | Register::EAX => self.rax = value,
-			Register::RBX | Register::EBX => self.rbx = value,
-			Register::RCX | Register::ECX => self.rcx = value,
-			Register::RDX | Register::EDX => self.rdx = value,
-			Register::RSI | Register::ESI => self.rsi = value,
-			Register::RDI | Register::EDI => self.rdi = value,
-			Register::RBP | Register::EBP => self.rbp = value,
-			Register::RSP | Register::ESP => self.rsp = value,
-			_ => todo!(),
-		}
-	}
-
-	fn get(&self, register: Register) -> u64 {
-		match register {
-			Register::RAX => self.rax,
-			Register::RBX => self.rbx,
-			Register::RCX => self.rcx,
-			Register::RDX => self.rdx,
-			Register::RSI => self.rsi,
-			Register::RDI => self.rdi,
-			Register::RBP => self.rbp,
-			Register::RSP => self.rsp,
-			Register::R8 => self.r8,
-			Register::R9 => self.r9,
-			Register::R10 => self.r10,
-			Register::R11 => self.r11,
-			Register::R12 => self.r12,
-			Register::R13 => self.r13,
-			Register::R14 => self.r14,
-			Register::R15 => self.r15,
-			_ => todo!(),
+	fn key(&self) -> Key {
+		(self.rip, self.rax.clone())
+	}
+
+	/// The 64-bit register that contains the given register.
+	fn full(&mut self, register: Register) -> Option<&mut Value> {
+		match register.full_register() {
+			Register::RAX => Some(&mut self.rax),
+			Register::RBX => Some(&mut self.rbx),
+			Register::RCX => Some(&mut self.rcx),
+			Register::RDX => Some(&mut self.rdx),
+			Register::RSI => Some(&mut self.rsi),
+			Register::RDI => Some(&mut self.rdi),
+			Register::RBP => Some(&mut self.rbp),
+			Register::RSP => Some(&mut self.rsp),
+			Register::R8 => Some(&mut self.r8),
+			Register::R9 => Some(&mut self.r9),
+			Register::R10 => Some(&mut self.r10),
+			Register::R11 => Some(&mut self.r11),
+			Register::R12 => Some(&mut self.r12),
+			Register::R13 => Some(&mut self.r13),
+			Register::R14 => Some(&mut self.r14),
+			Register::R15 => Some(&mut self.r15),
+			_ => None,
 		}
 	}
-}
 
-/// Extract the "entrypoint" field from readelf output.
-fn parse_readelf_entrypoint(output: &str) -> Result<u64, Box<dyn Error>> {
-	for line in Cursor::new(output).lines() {
-		let line = line?;
-		let line = line.trim();
-		let fields: Vec<&str> = line.split_whitespace().collect();
-
-		if fields.len() == 4
-			&& fields[0] == "Entry"
-			&& fields[1] == "point"
-			&& fields[2] == "address:"
-		{
-			return Ok(u64::from_str_radix(fields[3].trim_start_matches("0x"), 16)?);
+	/// Write a register the way the processor does: 32-bit writes clear the
+	/// upper half, while 8 and 16-bit writes leave the rest of the register
+	/// alone. Registers other than the general purpose ones aren't tracked.
+	fn set(&mut self, register: Register, value: Value) {
+		trace!("Setting {:?} to {:?}", register, value);
+		let bits = width(register);
+		if let Some(full) = self.full(register) {
+			*full = match bits {
+				64 => value,
+				32 => value.map(|v| truncate(v, 32)),
+				8 if is_high_byte(register) => {
+					full.combine(&value, |old, v| (old & !0xff00) | (truncate(v, 8) << 8))
+				}
+				_ => full.combine(&value, |old, v| {
+					(old & !truncate(!0, bits)) | truncate(v, bits)
+				}),
+			};
+		}
+	}
+
+	/// Read a register, zero extended to 64 bits.
+	fn get(&mut self, register: Register) -> Value {
+		let bits = width(register);
+		match self.full(register) {
+			Some(full) if is_high_byte(register) => full.map(|v| truncate(v >> 8, 8)),
+			Some(full) => full.map(|v| truncate(v, bits)),
+			None => Value::Unknown,
+		}
+	}
+
+	/// Forget the registers that a called function may change, according to
+	/// the System V calling convention.
+	fn clobber(&mut self) {
+		for register in [
+			Register::RAX,
+			Register::RCX,
+			Register::RDX,
+			Register::RSI,
+			Register::RDI,
+			Register::R8,
+			Register::R9,
+			Register::R10,
+			Register::R11,
+		] {
+			self.set(register, Value::Unknown);
+		}
+	}
+
+	/// The value of an operand, zero extended to 64 bits. Memory isn't
+	/// tracked, so its contents are unknown.
+	fn operand(&mut self, ins: &Instruction, operand: u32) -> Value {
+		match ins.op_kind(operand) {
+			OpKind::Register => self.get(ins.op_register(operand)),
+			OpKind::Immediate8
+			| OpKind::Immediate8_2nd
+			| OpKind::Immediate16
+			| OpKind::Immediate32
+			| OpKind::Immediate64
+			| OpKind::Immediate8to16
+			| OpKind::Immediate8to32
+			| OpKind::Immediate8to64
+			| OpKind::Immediate32to64 => Value::Known(ins.immediate(operand)),
+			_ => Value::Unknown,
+		}
+	}
+
+	/// The address computed by a memory operand.
+	fn address(&mut self, ins: &Instruction) -> Value {
+		// RIP relative displacements are already resolved by the decoder
+		if ins.memory_base() == Register::RIP {
+			return Value::Known(ins.memory_displacement64());
+		}
+
+		let mut address = Value::Known(ins.memory_displacement64());
+		if ins.memory_base() != Register::None {
+			address = address.combine(&self.get(ins.memory_base()), u64::wrapping_add);
+		}
+		if ins.memory_index() != Register::None {
+			let scale = ins.memory_index_scale() as u64;
+			let index = self.get(ins.memory_index()).map(|v| v.wrapping_mul(scale));
+			address = address.combine(&index, u64::wrapping_add);
+		}
+		address
+	}
+
+	/// Apply an operation to the destination and source operands, storing the
+	/// result in the destination if it's a register.
+	fn binary(&mut self, ins: &Instruction, f: impl Fn(u64, u64) -> u64) {
+		if ins.op0_kind() == OpKind::Register {
+			let destination = ins.op0_register();
+			let value = self.get(destination).combine(&self.operand(ins, 1), f);
+			self.set(destination, value);
+		}
+	}
+
+	/// Apply an operation to the destination operand, if it's a register.
+	fn unary(&mut self, ins: &Instruction, f: impl Fn(u64) -> u64) {
+		if ins.op0_kind() == OpKind::Register {
+			let destination = ins.op0_register();
+			let value = self.get(destination).map(f);
+			self.set(destination, value);
 		}
 	}
-	bail!("No entry point found");
-}
 
-/// Extract the size and offset of the text section from objdump output.
-fn parse_objdump_size_offset(output: &str) -> Result<(u64, u64), Box<dyn Error>> {
-	for line in Cursor::new(output).lines() {
-		let line = line?;
-		let line = line.trim();
-		let fields: Vec<&str> = line.split_whitespace().collect();
-		if fields.len() == 7 && fields[1] == ".text" {
-			return Ok((
-				u64::from_str_radix(fields[2], 16)?,
-				u64::from_str_radix(fields[5], 16)?,
-			));
+	/// Store a value in the destination operand, if it's a register.
+	fn store(&mut self, ins: &Instruction, value: Value) {
+		if ins.op0_kind() == OpKind::Register {
+			self.set(ins.op0_register(), value);
 		}
 	}
-	bail!("No text segment found");
+
+	/// Simulate the data flow of an instruction, returning whether it was
+	/// understood. Control flow is handled by the caller.
+	fn execute(&mut self, ins: &Instruction) -> bool {
+		// The width of the destination, for operations that depend on it
+		let bits = if ins.op_count() > 0 && ins.op0_kind() == OpKind::Register {
+			width(ins.op0_register())
+		} else {
+			64
+		};
+
+		match ins.mnemonic() {
+			Mnemonic::Mov => {
+				let value = self.operand(ins, 1);
+				self.store(ins, value);
+			}
+			Mnemonic::Movzx => {
+				let value = self.operand(ins, 1);
+				self.store(ins, value);
+			}
+			Mnemonic::Movsx | Mnemonic::Movsxd => {
+				let source = match ins.op1_kind() {
+					OpKind::Register => width(ins.op1_register()),
+					_ => 64,
+				};
+				let value = self.operand(ins, 1).map(|v| sign_extend(v, source));
+				self.store(ins, value);
+			}
+			Mnemonic::Cbw => {
+				let value = self.get(Register::AL).map(|v| sign_extend(v, 8));
+				self.set(Register::AX, value);
+			}
+			Mnemonic::Cwde => {
+				let value = self.get(Register::AX).map(|v| sign_extend(v, 16));
+				self.set(Register::EAX, value);
+			}
+			Mnemonic::Cdqe => {
+				let value = self.get(Register::EAX).map(|v| sign_extend(v, 32));
+				self.set(Register::RAX, value);
+			}
+			Mnemonic::Lea => {
+				let value = self.address(ins);
+				self.store(ins, value);
+			}
+			Mnemonic::Xchg => {
+				if ins.op0_kind() == OpKind::Register && ins.op1_kind() == OpKind::Register {
+					let (a, b) = (ins.op0_register(), ins.op1_register());
+					let (first, second) = (self.get(a), self.get(b));
+					self.set(a, second);
+					self.set(b, first);
+				} else {
+					self.store(ins, Value::Unknown);
+					if ins.op1_kind() == OpKind::Register {
+						self.set(ins.op1_register(), Value::Unknown);
+					}
+				}
+			}
+
+			// Zeroing a register with itself doesn't depend on its value
+			Mnemonic::Xor | Mnemonic::Sub
+				if ins.op0_kind() == OpKind::Register
+					&& ins.op1_kind() == OpKind::Register
+					&& ins.op0_register() == ins.op1_register() =>
+			{
+				self.store(ins, Value::Known(0));
+			}
+			Mnemonic::Add => self.binary(ins, u64::wrapping_add),
+			Mnemonic::Sub => self.binary(ins, u64::wrapping_sub),
+			Mnemonic::And => self.binary(ins, |a, b| a & b),
+			Mnemonic::Or => self.binary(ins, |a, b| a | b),
+			Mnemonic::Xor => self.binary(ins, |a, b| a ^ b),
+			Mnemonic::Imul if ins.op_count() == 2 => self.binary(ins, u64::wrapping_mul),
+			Mnemonic::Imul if ins.op_count() == 3 => {
+				let value = self
+					.operand(ins, 1)
+					.combine(&self.operand(ins, 2), u64::wrapping_mul);
+				self.store(ins, value);
+			}
+			Mnemonic::Shl | Mnemonic::Sal => {
+				let mask = if bits == 64 { 63 } else { 31 };
+				self.binary(ins, |a, b| a.wrapping_shl((b & mask) as u32));
+			}
+			Mnemonic::Shr => {
+				let mask = if bits == 64 { 63 } else { 31 };
+				self.binary(ins, |a, b| a.wrapping_shr((b & mask) as u32));
+			}
+			Mnemonic::Sar => {
+				let mask = if bits == 64 { 63 } else { 31 };
+				self.binary(ins, |a, b| {
+					((sign_extend(a, bits) as i64) >> (b & mask)) as u64
+				});
+			}
+			Mnemonic::Not => self.unary(ins, |a| !a),
+			Mnemonic::Neg => self.unary(ins, u64::wrapping_neg),
+			Mnemonic::Inc => self.unary(ins, |a| a.wrapping_add(1)),
+			Mnemonic::Dec => self.unary(ins, |a| a.wrapping_sub(1)),
+			Mnemonic::Cmovo
+			| Mnemonic::Cmovno
+			| Mnemonic::Cmovb
+			| Mnemonic::Cmovae
+			| Mnemonic::Cmove
+			| Mnemonic::Cmovne
+			| Mnemonic::Cmovbe
+			| Mnemonic::Cmova
+			| Mnemonic::Cmovs
+			| Mnemonic::Cmovns
+			| Mnemonic::Cmovp
+			| Mnemonic::Cmovnp
+			| Mnemonic::Cmovl
+			| Mnemonic::Cmovge
+			| Mnemonic::Cmovle
+			| Mnemonic::Cmovg => {
+				// Flags aren't tracked, so it may or may not move
+				let value = self.operand(ins, 0).union(&self.operand(ins, 1));
+				self.store(ins, value);
+			}
+			Mnemonic::Seto
+			| Mnemonic::Setno
+			| Mnemonic::Setb
+			| Mnemonic::Setae
+			| Mnemonic::Sete
+			| Mnemonic::Setne
+			| Mnemonic::Setbe
+			| Mnemonic::Seta
+			| Mnemonic::Sets
+			| Mnemonic::Setns
+			| Mnemonic::Setp
+			| Mnemonic::Setnp
+			| Mnemonic::Setl
+			| Mnemonic::Setge
+			| Mnemonic::Setle
+			| Mnemonic::Setg => self.store(ins, Value::from_values(BTreeSet::from([0, 1]))),
+			Mnemonic::Push => {
+				let value = self.operand(ins, 0);
+				self.stack.push(value);
+			}
+			Mnemonic::Pop => {
+				let value = self.stack.pop().unwrap_or_default();
+				self.store(ins, value);
+			}
+			Mnemonic::Test | Mnemonic::Cmp | Mnemonic::Nop | Mnemonic::Endbr64 => {
+				// Only sets condition codes (or nothing), so we can ignore
+			}
+			_ => return false,
+		}
+		true
+	}
 }
 
-pub fn extract_syscalls(path: &str) -> Result<Vec<Syscall>, Box<dyn Error>> {
-	// Get entrypoint with readelf
-	let readelf = Command::new("readelf").arg("-h").arg(path).output()?;
-	let entry_point = parse_readelf_entrypoint(std::str::from_utf8(&readelf.stdout)?)?;
-
-	// Get code section size and offset with objdump
-	let objdump = Command::new("objdump").arg("-h").arg(path).output()?;
-	let (text_size, text_offset) =
-		parse_objdump_size_offset(std::str::from_utf8(&objdump.stdout)?)?;
-
-	trace!(
-		".text size: {}, .text offset: {}, entrypoint: {}",
-		text_size,
-		text_offset,
-		entry_point
-	);
+/// The decoded instructions of a program, by address.
+struct Program<'a> {
+	code: Vec<Code<'a>>,
+	instructions: HashMap<u64, Instruction>,
+}
 
-	// Read code section
-	let mut file = File::open(path)?;
-	//let mut code = vec![0u8; text_size as usize + text_offset as usize];
-	//file.read_exact(&mut code)?;
-	let mut code = vec![0u8; text_size as usize];
-	file.read_exact_at(&mut code, text_offset)?;
+impl<'a> Program<'a> {
+	fn new(code: Vec<Code<'a>>) -> Self {
+		Program {
+			code,
+			instructions: HashMap::new(),
+		}
+	}
+
+	/// Decode the instruction at the given address, if it's in the code.
+	fn instruction(&mut self, address: u64) -> Option<Instruction> {
+		if let Some(ins) = self.instructions.get(&address) {
+			return Some(*ins);
+		}
 
-	// Allocate a boolean for each instruction to track whether it has been visited
-	let mut instructions: Vec<(Instruction, bool)> =
-		Decoder::with_ip(64, &code, entry_point, DecoderOptions::NONE)
+		let code = self
+			.code
 			.iter()
-			.map(|i| (i, false))
-			.collect();
+			.find(|c| address >= c.address && address - c.address < c.bytes.len() as u64)?;
+		let bytes = &code.bytes[(address - code.address) as usize..];
+		let ins = Decoder::with_ip(64, bytes, address, DecoderOptions::NONE).decode();
+		if ins.code() == OpCode::INVALID {
+			return None;
+		}
+		self.instructions.insert(address, ins);
+		Some(ins)
+	}
+}
+
+pub fn extract_syscalls(path: &str, budget: &Budget) -> Result<Report, Box<dyn Error>> {
+	let elf = Elf::open(path)?;
+	if elf.machine != EM_X86_64 {
+		bail!("Not an x86_64 binary (machine {})", elf.machine);
+	}
 
-	let mut state = RegisterState::default();
-	state.rip = entry_point as usize - text_offset as usize;
+	// Decode from every executable section, or every executable segment when
+	// the section headers are gone
+	let code = elf.code();
+	for code in &code {
+		trace!(
+			"{}: {} bytes at {:#x}, entrypoint: {:#x}",
+			code.name,
+			code.bytes.len(),
+			code.address,
+			elf.entry
+		);
+	}
+	if code.is_empty() {
+		bail!("No executable code found");
+	}
 
-	let mut syscalls: Vec<Syscall> = Vec::new();
-	emulate(&mut state, &mut instructions, &mut syscalls);
+	let state = RegisterState {
+		rip: elf.entry,
+		..Default::default()
+	};
+	let report = Explorer::new(Program::new(code), *budget).explore(state);
 
-	println!("Extracted {} syscalls", syscalls.len());
-	return Ok(syscalls);
+	debug!(
+		"Extracted {} syscalls ({})",
+		report.syscalls.len(),
+		report.coverage()
+	);
+	Ok(report)
 }
 
-/// Emulate the given instructions with the given register state and collect syscalls encountered.
-fn emulate(
-	state: &mut RegisterState,
-	instructions: &mut Vec<(Instruction, bool)>,
-	syscalls: &mut Vec<Syscall>,
-) {
-	while state.rip < instructions.len() {
-		// If we already visited this instruction, then we should stop to prevent infinite loops
-		if instructions[state.rip].1 {
-			break;
-		} else {
-			instructions[state.rip].1 = true;
+/// The destination of a direct jump or call.
+fn branch_target(ins: &Instruction) -> Option<u64> {
+	match ins.op_kind(0) {
+		OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 => {
+			Some(ins.near_branch_target())
 		}
+		_ => None,
+	}
+}
 
-		// Get the next decoded instruction
-		let ins = instructions[state.rip].0;
-		state.rip = ins.next_ip() as usize;
+/// Explores the paths through a program until there are none left or the
+/// budget runs out.
+struct Explorer<'a> {
+	program: Program<'a>,
+	budget: Budget,
+	start: Instant,
 
-		trace!("Executing instruction: {:?}", ins);
+	/// The paths waiting to be explored
+	worklist: Vec<RegisterState>,
 
-		// Simulate the instruction
-		match ins.mnemonic() {
-			Mnemonic::Mov => match (ins.op_kind(0), ins.op_kind(1)) {
-				(OpKind::Register, OpKind::Register) => {
-					state.set(ins.op_register(0), state.get(ins.op_register(1)));
+	visited: HashSet<Key>,
+	info: InstructionInfoFactory,
+	report: Report,
+}
+
+impl<'a> Explorer<'a> {
+	fn new(program: Program<'a>, budget: Budget) -> Self {
+		Explorer {
+			program,
+			budget,
+			start: Instant::now(),
+			worklist: Vec::new(),
+			visited: HashSet::new(),
+			info: InstructionInfoFactory::new(),
+			report: Report::default(),
+		}
+	}
+
+	/// Emulate every path through the program from the given register state
+	/// and collect syscalls encountered.
+	fn explore(mut self, state: RegisterState) -> Report {
+		self.fork(state);
+		self.run();
+		self.report
+	}
+
+	/// Emulate the paths in the worklist until it's empty.
+	fn run(&mut self) {
+		while let Some(mut state) = self.worklist.pop() {
+			self.emulate(&mut state);
+
+			// Running out of paths still lets the queued ones finish
+			if matches!(
+				self.report.truncated,
+				Some(Limit::Instructions | Limit::Time)
+			) {
+				break;
+			}
+		}
+	}
+
+	/// Queue a path to explore, if the budget allows it.
+	fn fork(&mut self, state: RegisterState) {
+		if self.report.paths >= self.budget.paths {
+			self.report.truncated.get_or_insert(Limit::Paths);
+			return;
+		}
+		self.report.paths += 1;
+		self.worklist.push(state);
+	}
+
+	/// Check whether another instruction may be emulated.
+	fn has_budget(&mut self) -> bool {
+		if self.report.instructions >= self.budget.instructions {
+			self.report.truncated.get_or_insert(Limit::Instructions);
+			return false;
+		}
+
+		// Checking the clock on every instruction would be slow
+		if self.report.instructions.is_multiple_of(1024) && self.start.elapsed() >= self.budget.time
+		{
+			self.report.truncated.get_or_insert(Limit::Time);
+			return false;
+		}
+		true
+	}
+
+	/// Emulate one path with the given register state until it ends, queueing
+	/// the paths that branch off of it, and collect syscalls encountered.
+	fn emulate(&mut self, state: &mut RegisterState) {
+		// RIP is an address, so look up the instruction that starts there
+		while self.has_budget() {
+			let ins = match self.program.instruction(state.rip) {
+				Some(ins) => ins,
+				None => break,
+			};
+
+			// If we already visited this instruction in the same state, then we
+			// should stop to prevent infinite loops. Inside a function, the rest
+			// of it was explored already, so carry on as if it returned.
+			if !self.visited.insert(state.key()) {
+				match state.returns.pop() {
+					Some(address) => {
+						state.clobber();
+						state.rip = address;
+						continue;
+					}
+					None => break,
 				}
-				(OpKind::Register, OpKind::Immediate64) => {
-					state.set(ins.op_register(0), ins.immediate(1))
+			}
+			self.report.instructions += 1;
+			state.rip = ins.next_ip();
+
+			trace!("Executing instruction: {:?}", ins);
+
+			// Follow the control flow
+			match ins.flow_control() {
+				// Syscalls return to the next instruction
+				_ if ins.mnemonic() == Mnemonic::Syscall => {
+					self.syscall(state, &ins);
+					continue;
 				}
-				_ => todo!(),
-			},
-			Mnemonic::Add => match (ins.op_kind(0), ins.op_kind(1)) {
-				(OpKind::Register, OpKind::Register) => {
-					state.set(
-						ins.op_register(0),
-						state.get(ins.op_register(0)) + state.get(ins.op_register(1)),
-					);
+				FlowControl::Next | FlowControl::XbeginXabortXend => {}
+				FlowControl::UnconditionalBranch => match branch_target(&ins) {
+					Some(target) => {
+						state.rip = target;
+						continue;
+					}
+					None => break,
+				},
+				FlowControl::ConditionalBranch => {
+					// TODO disallow jumps into data
+					if let Some(target) = branch_target(&ins) {
+						let mut taken = state.clone();
+						taken.rip = target;
+						self.fork(taken);
+					}
+					continue;
 				}
-				(OpKind::Register, OpKind::Immediate64) => state.set(
-					ins.op_register(0),
-					state.get(ins.op_register(0)) + ins.immediate(1),
-				),
-				_ => todo!(),
-			},
-			Mnemonic::Sub => match (ins.op_kind(0), ins.op_kind(1)) {
-				(OpKind::Register, OpKind::Register) => {
-					state.set(
-						ins.op_register(0),
-						state.get(ins.op_register(0)) - state.get(ins.op_register(1)),
-					);
+				FlowControl::Call => {
+					// A function that was already explored is assumed to return, as
+					// is one that isn't in the code
+					if let Some(target) = branch_target(&ins) {
+						if !self.visited.contains(&(target, state.rax.clone()))
+							&& self.program.instruction(target).is_some()
+						{
+							state.returns.push(state.rip);
+							state.rip = target;
+							continue;
+						}
+					}
+					state.clobber();
+					continue;
 				}
-				(OpKind::Register, OpKind::Immediate64) => state.set(
-					ins.op_register(0),
-					state.get(ins.op_register(0)) - ins.immediate(1),
-				),
-				_ => todo!(),
-			},
-			Mnemonic::And => match (ins.op_kind(0), ins.op_kind(1)) {
-				(OpKind::Register, OpKind::Register) => state.set(
-					ins.op_register(0),
-					state.get(ins.op_register(1)) & state.get(ins.op_register(0)),
-				),
-				_ => todo!(),
-			},
-			Mnemonic::Or => match (ins.op_kind(0), ins.op_kind(1)) {
-				(OpKind::Register, OpKind::Register) => state.set(
-					ins.op_register(0),
-					state.get(ins.op_register(1)) | state.get(ins.op_register(0)),
-				),
-				_ => todo!(),
-			},
-			Mnemonic::Xor => match (ins.op_kind(0), ins.op_kind(1)) {
-				(OpKind::Register, OpKind::Register) => state.set(
-					ins.op_register(0),
-					state.get(ins.op_register(1)) ^ state.get(ins.op_register(0)),
-				),
-				_ => todo!(),
-			},
-			Mnemonic::Not => match ins.op_kind(0) {
-				OpKind::Register => state.set(ins.op_register(0), !state.get(ins.op_register(0))),
-				_ => todo!(),
-			},
-			Mnemonic::Jmp
-			| Mnemonic::Je
-			| Mnemonic::Jl
-			| Mnemonic::Jg
-			| Mnemonic::Jne
-			| Mnemonic::Jle
-			| Mnemonic::Jge
-			| Mnemonic::Ja
-			| Mnemonic::Jae
-			| Mnemonic::Jb
-			| Mnemonic::Jbe
-			| Mnemonic::Js
-			| Mnemonic::Jns => {
-				// TODO disallow jumps into data
-
-				// Take all jumps regardless of the condition
-				let mut tmp_state = state.clone();
-				// TODO set RIP
-				emulate(&mut tmp_state, instructions, syscalls);
-			}
-			Mnemonic::Push => match ins.op_kind(0) {
-				OpKind::Register => state.stack.push(state.get(ins.op_register(0))),
-				_ => todo!(),
-			},
-			Mnemonic::Pop => match ins.op_kind(0) {
-				OpKind::Register => {
-					let value = state.stack.pop().unwrap();
-					state.set(ins.op_register(0), value);
+				FlowControl::IndirectCall => {
+					trace!("[{:#x}] unresolved call", ins.ip());
+					state.clobber();
+					continue;
 				}
-				_ => todo!(),
-			},
-			Mnemonic::Test => {
-				// Just sets condition codes, so we can ignore
-			}
-			Mnemonic::Nop => {
-				// Do nothing
+				FlowControl::IndirectBranch => {
+					// Most likely a tail call through the PLT, so the current
+					// function ends here
+					trace!("[{:#x}] unresolved jump", ins.ip());
+					match state.returns.pop() {
+						Some(address) => {
+							state.clobber();
+							state.rip = address;
+							continue;
+						}
+						None => break,
+					}
+				}
+				FlowControl::Return => match state.returns.pop() {
+					Some(address) => {
+						state.rip = address;
+						continue;
+					}
+					None => break,
+				},
+				FlowControl::Interrupt | FlowControl::Exception => break,
 			}
-			Mnemonic::Call => {
-				// Push return address
-				// TODO
-				// Jump to a function
-				// TODO
+
+			// Simulate the instruction, forgetting whatever it writes if it isn't
+			// understood
+			if !state.execute(&ins) {
+				trace!("[{:#x}] unknown instruction", ins.ip());
+				for used in self.info.info(&ins).used_registers() {
+					if matches!(
+						used.access(),
+						OpAccess::Write
+							| OpAccess::CondWrite | OpAccess::ReadWrite
+							| OpAccess::ReadCondWrite
+					) {
+						state.set(used.register(), Value::Unknown);
+					}
+				}
 			}
-			Mnemonic::Syscall => {
-				trace!("Discovered syscall: {}", state.rax);
+		}
+	}
 
-				// Determine what syscall this is
-				syscalls.push(match SyscallType::from_repr(state.rax) {
-					Some(SyscallType::Close) => Syscall {
-						name: String::from("close"),
+	/// Record the syscalls an instruction may make, given the number in RAX.
+	fn syscall(&mut self, state: &mut RegisterState, ins: &Instruction) {
+		match state.rax.values() {
+			Some(numbers) => {
+				for number in numbers {
+					trace!("Discovered syscall: {}", number);
+					self.report.syscalls.push(Syscall {
+						name: syscall_name(number),
 						address: ins.ip(),
 						arguments: vec![],
-					},
-					_ => todo!(),
-				});
+					});
+				}
+			}
+			None => {
+				trace!("[{:#x}] syscall with unresolved number", ins.ip());
+				self.report.unresolved.push(ins.ip());
 			}
-			_ => trace!("[{}] unknown instruction", state.rip),
 		}
+
+		// The kernel returns a result in RAX and uses RCX and R11 to get back
+		state.set(Register::RAX, Value::Unknown);
+		state.set(Register::RCX, Value::Unknown);
+		state.set(Register::R11, Value::Unknown);
+	}
+}
+
+/// The name of a syscall from its number.
+fn syscall_name(number: u64) -> String {
+	match SyscallType::from_repr(number) {
+		Some(syscall) => <&'static str>::from(syscall).to_string(),
+		None => format!("syscall_{}", number),
 	}
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::r#static::elf;
 	use iced_x86::code_asm::*;
 	use std::error::Error;
 
 	fn test_emulate(mut a: CodeAssembler, state: &mut RegisterState) -> Vec<Syscall> {
 		let bytes = a.assemble(0).unwrap();
-		let mut instructions: Vec<(Instruction, bool)> =
-			Decoder::new(64, &bytes, DecoderOptions::NONE)
-				.iter()
-				.map(|i| (i, false))
-				.collect();
+		let program = Program::new(vec![Code {
+			name: String::from(".text"),
+			address: 0,
+			bytes: &bytes,
+		}]);
 
-		// Ignore syscalls
-		let mut syscalls: Vec<Syscall> = Vec::new();
+		// Finish the first path before the ones that branched off, so its final
+		// state can be checked
+		let mut explorer = Explorer::new(program, Budget::default());
+		explorer.emulate(state);
+		explorer.run();
+		explorer.report.syscalls
+	}
+
+	/// Explore the given code with a budget.
+	fn test_explore(mut a: CodeAssembler, budget: Budget) -> Report {
+		let bytes = a.assemble(0).unwrap();
+		let program = Program::new(vec![Code {
+			name: String::from(".text"),
+			address: 0,
+			bytes: &bytes,
+		}]);
+		Explorer::new(program, budget).explore(RegisterState::default())
+	}
 
-		super::emulate(state, &mut instructions, &mut syscalls);
-		return syscalls;
+	/// The addresses of the syscalls found, in order.
+	fn addresses(syscalls: &[Syscall]) -> Vec<u64> {
+		let mut addresses: Vec<u64> = syscalls.iter().map(|syscall| syscall.address).collect();
+		addresses.sort_unstable();
+		addresses
 	}
 
 	#[test]
@@ -327,14 +876,16 @@ mod tests {
 		a.mov(rax, rbx)?;
 		a.mov(rbx, 0x100u64)?;
 
-		let mut state = RegisterState::default();
-		state.rax = 0x1337;
-		state.rbx = 0x890;
+		let mut state = RegisterState {
+			rax: Value::Known(0x1337),
+			rbx: Value::Known(0x890),
+			..Default::default()
+		};
 
 		test_emulate(a, &mut state);
 
-		assert_eq!(state.rbx, 0x100);
-		assert_eq!(state.rax, 0x890);
+		assert_eq!(state.rbx, Value::Known(0x100));
+		assert_eq!(state.rax, Value::Known(0x890));
 		Ok(())
 	}
 
@@ -343,14 +894,16 @@ mod tests {
 		let mut a = CodeAssembler::new(64)?;
 		a.and(rax, rbx)?;
 
-		let mut state = RegisterState::default();
-		state.rax = 0x1337;
-		state.rbx = 0x890;
+		let mut state = RegisterState {
+			rax: Value::Known(0x1337),
+			rbx: Value::Known(0x890),
+			..Default::default()
+		};
 
 		test_emulate(a, &mut state);
 
-		assert_eq!(state.rax, 0x890 & 0x1337);
-		assert_eq!(state.rbx, 0x890);
+		assert_eq!(state.rax, Value::Known(0x890 & 0x1337));
+		assert_eq!(state.rbx, Value::Known(0x890));
 		Ok(())
 	}
 
@@ -359,12 +912,270 @@ mod tests {
 		let mut a = CodeAssembler::new(64)?;
 		a.syscall()?;
 
-		let mut state = RegisterState::default();
-		state.rax = 3;
+		let mut state = RegisterState {
+			rax: Value::Known(3),
+			..Default::default()
+		};
 
 		let syscalls = test_emulate(a, &mut state);
 
 		assert_eq!("close", syscalls.first().unwrap().name);
 		Ok(())
 	}
+
+	/// Analyze a binary built around the given code.
+	fn extract(a: &mut CodeAssembler, pie: bool, stripped: bool) -> Result<Report, Box<dyn Error>> {
+		let code = a.assemble(elf::tests::code_address(pie))?;
+		let file = tempfile::NamedTempFile::new()?;
+		std::fs::write(file.path(), elf::tests::binary(&code, pie, stripped))?;
+		extract_syscalls(file.path().to_str().unwrap(), &Budget::default())
+	}
+
+	#[test]
+	fn syscalls_in_binary() -> Result<(), Box<dyn Error>> {
+		for (pie, stripped) in [(false, false), (true, false), (true, true)] {
+			let mut a = CodeAssembler::new(64)?;
+			a.mov(eax, 3u32)?;
+			a.syscall()?;
+			a.ret()?;
+
+			let report = extract(&mut a, pie, stripped)?;
+			assert!(report.is_exhaustive());
+			let syscalls = report.syscalls;
+			assert_eq!(syscalls.len(), 1);
+			assert_eq!(syscalls[0].name, "close");
+			assert_eq!(syscalls[0].address, elf::tests::code_address(pie) + 5);
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn branches() -> Result<(), Box<dyn Error>> {
+		let mut a = CodeAssembler::new(64)?;
+		let mut taken = a.create_label();
+		let mut end = a.create_label();
+		a.mov(eax, 3u32)?;
+		a.je(taken)?;
+		a.syscall()?;
+		a.jmp(end)?;
+		a.set_label(&mut taken)?;
+		a.syscall()?;
+		a.set_label(&mut end)?;
+		a.ret()?;
+
+		// Both sides of the branch are explored
+		let syscalls = test_emulate(a, &mut RegisterState::default());
+		assert_eq!(addresses(&syscalls), vec![7, 11]);
+		Ok(())
+	}
+
+	#[test]
+	fn jumps() -> Result<(), Box<dyn Error>> {
+		let mut a = CodeAssembler::new(64)?;
+		let mut over = a.create_label();
+		a.mov(eax, 3u32)?;
+		a.jmp(over)?;
+		a.db(&[0xff, 0xff, 0xff])?;
+		a.set_label(&mut over)?;
+		a.syscall()?;
+		a.jmp(over)?;
+
+		// The data is jumped over, and the loop ends once it comes around
+		let syscalls = test_emulate(a, &mut RegisterState::default());
+		assert_eq!(addresses(&syscalls), vec![10]);
+		Ok(())
+	}
+
+	#[test]
+	fn calls() -> Result<(), Box<dyn Error>> {
+		let mut a = CodeAssembler::new(64)?;
+		let mut function = a.create_label();
+		a.call(function)?;
+		a.call(function)?;
+		a.call(rbx)?;
+		a.mov(eax, 3u32)?;
+		a.syscall()?;
+		a.ret()?;
+		a.set_label(&mut function)?;
+		a.mov(eax, 3u32)?;
+		a.syscall()?;
+		a.ret()?;
+
+		// Execution resumes after each call, including the one that can't be
+		// followed
+		let mut state = RegisterState::default();
+		let syscalls = test_emulate(a, &mut state);
+		assert_eq!(addresses(&syscalls), vec![17, 25]);
+		assert!(state.returns.is_empty());
+		Ok(())
+	}
+
+	#[test]
+	fn revisits() -> Result<(), Box<dyn Error>> {
+		let mut a = CodeAssembler::new(64)?;
+		let mut function = a.create_label();
+		a.mov(eax, 3u32)?;
+		a.call(function)?;
+		a.mov(eax, 2u32)?;
+		a.call(function)?;
+		a.ret()?;
+		a.set_label(&mut function)?;
+		a.nop()?;
+		a.ret()?;
+
+		// The function is explored again because RAX changed
+		let report = test_explore(a, Budget::default());
+		assert!(report.is_exhaustive());
+		assert_eq!(report.instructions, 9);
+		Ok(())
+	}
+
+	#[test]
+	fn budgets() -> Result<(), Box<dyn Error>> {
+		let code = || -> Result<CodeAssembler, Box<dyn Error>> {
+			let mut a = CodeAssembler::new(64)?;
+			let mut top = a.create_label();
+			a.xor(eax, eax)?;
+			a.set_label(&mut top)?;
+			a.add(rax, 0x1000)?;
+			a.jne(top)?;
+			a.ret()?;
+			Ok(a)
+		};
+
+		// Every iteration of the loop is a new state, so the budget ends it
+		let report = test_explore(
+			code()?,
+			Budget {
+				instructions: 100,
+				..Default::default()
+			},
+		);
+		assert_eq!(report.truncated, Some(Limit::Instructions));
+		assert_eq!(report.instructions, 100);
+		assert!(report
+			.coverage()
+			.starts_with("truncated by the instruction budget"));
+
+		let report = test_explore(
+			code()?,
+			Budget {
+				paths: 10,
+				..Default::default()
+			},
+		);
+		assert_eq!(report.truncated, Some(Limit::Paths));
+		assert_eq!(report.paths, 10);
+
+		let report = test_explore(
+			code()?,
+			Budget {
+				time: Duration::ZERO,
+				..Default::default()
+			},
+		);
+		assert_eq!(report.truncated, Some(Limit::Time));
+		assert_eq!(report.instructions, 0);
+		Ok(())
+	}
+
+	#[test]
+	fn sub_registers() -> Result<(), Box<dyn Error>> {
+		let mut a = CodeAssembler::new(64)?;
+		a.mov(eax, 1u32)?;
+		a.mov(bx, 2u32)?;
+		a.mov(ch, 3u32)?;
+		a.mov(r8d, 0xffff_fff0u32)?;
+		a.add(r8d, 0x20)?;
+		a.movsx(rdx, cl)?;
+		a.movzx(esi, cl)?;
+		a.movsxd(rdi, r9d)?;
+		a.cdqe()?;
+
+		let mut state = RegisterState {
+			rax: Value::Known(u64::MAX),
+			rbx: Value::Known(u64::MAX),
+			rcx: Value::Known(0xff),
+			r9: Value::Known(0x8000_0000),
+			..Default::default()
+		};
+		test_emulate(a, &mut state);
+
+		// 32-bit writes clear the upper half, smaller ones merge
+		assert_eq!(state.rax, Value::Known(1));
+		assert_eq!(state.rbx, Value::Known(0xffff_ffff_ffff_0002));
+		assert_eq!(state.rcx, Value::Known(0x3ff));
+		assert_eq!(state.r8, Value::Known(0x10));
+		assert_eq!(state.rdx, Value::Known(u64::MAX));
+		assert_eq!(state.rsi, Value::Known(0xff));
+		assert_eq!(state.rdi, Value::Known(0xffff_ffff_8000_0000));
+		Ok(())
+	}
+
+	#[test]
+	fn unknowns() -> Result<(), Box<dyn Error>> {
+		let mut a = CodeAssembler::new(64)?;
+		a.mov(eax, 1u32)?;
+		a.mov(ecx, 2u32)?;
+		a.mov(rdx, qword_ptr(rbx))?;
+		a.mov(qword_ptr(rbx + 8), rax)?;
+		a.add(rax, qword_ptr(rsp))?;
+		a.lea(rsi, qword_ptr(rbp + rbp * 2 + 8))?;
+		a.lea(rdi, qword_ptr(rcx * 4 + 1))?;
+		a.cpuid()?;
+
+		let mut state = RegisterState::default();
+		test_emulate(a, &mut state);
+
+		// Memory isn't tracked, and unknown instructions forget what they write
+		assert_eq!(state.rdx, Value::Unknown);
+		assert_eq!(state.rax, Value::Unknown);
+		assert_eq!(state.rcx, Value::Unknown);
+		assert_eq!(state.rsi, Value::Unknown);
+		assert_eq!(state.rdi, Value::Known(9));
+		Ok(())
+	}
+
+	#[test]
+	fn syscall_numbers() -> Result<(), Box<dyn Error>> {
+		let mut a = CodeAssembler::new(64)?;
+		a.mov(eax, 3u32)?;
+		a.mov(ecx, 231u32)?;
+		a.test(rdi, rdi)?;
+		a.cmove(eax, ecx)?;
+		a.syscall()?;
+		a.syscall()?;
+		a.mov(eax, 1000u32)?;
+		a.syscall()?;
+		a.ret()?;
+
+		// A syscall may be one of a few, and the one after it can't be known
+		let report = test_explore(a, Budget::default());
+		let mut names: Vec<&str> = report.syscalls.iter().map(|s| s.name.as_str()).collect();
+		names.sort_unstable();
+		assert_eq!(names, vec!["close", "exit_group", "syscall_1000"]);
+		assert_eq!(report.unresolved, vec![18]);
+
+		assert_eq!(syscall_name(13), "rt_sigaction");
+		assert_eq!(syscall_name(17), "pread64");
+		assert_eq!(syscall_name(72), "fcntl");
+		assert_eq!(syscall_name(291), "epoll_create1");
+		Ok(())
+	}
+
+	#[test]
+	fn values() {
+		let set = Value::Known(1).union(&Value::Known(2));
+		assert_eq!(set, Value::Set(BTreeSet::from([1, 2])));
+		assert_eq!(
+			set.combine(&Value::Known(10), u64::wrapping_add),
+			Value::Set(BTreeSet::from([11, 12]))
+		);
+		assert_eq!(set.map(|_| 7), Value::Known(7));
+		assert_eq!(set.union(&Value::Unknown), Value::Unknown);
+
+		// Too many possibilities aren't worth tracking
+		let many = Value::from_values((0..MAX_VALUES as u64).collect());
+		assert_eq!(many.combine(&set, u64::wrapping_add), Value::Unknown);
+	}
 }
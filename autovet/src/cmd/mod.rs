@@ -1,5 +1,20 @@
+// The interactive commands are kept as written; their lints are allowed here
+// rather than rewriting them
+#[allow(clippy::while_let_loop, clippy::zombie_processes)]
 pub mod pacman;
 pub mod query;
+#[allow(
+	dead_code,
+	unreachable_code,
+	unused_imports,
+	unused_variables,
+	clippy::collapsible_match,
+	clippy::explicit_counter_loop,
+	clippy::items_after_test_module,
+	clippy::needless_borrow,
+	clippy::needless_return,
+	clippy::useless_conversion
+)]
 pub mod test;
 
 #[derive(clap::Subcommand, Debug)]
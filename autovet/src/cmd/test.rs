@@ -115,7 +115,11 @@ pub fn run(cmd: Commands) -> Result<(), Box<dyn Error>> {
 			// TODO
 
 			// Get static syscalls
-			let static_syscalls = autovet_worker::r#static::x86_64::extract_syscalls(&executable)?;
+			let static_syscalls = autovet_worker::r#static::x86_64::extract_syscalls(
+				&executable,
+				&autovet_worker::r#static::x86_64::Budget::default(),
+			)?
+			.syscalls;
 
 			loop {
 				let args: String = Input::with_theme(&theme)